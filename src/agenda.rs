@@ -0,0 +1,105 @@
+//! List upcoming deadlines from the latest devlog entry.
+
+use crate::date;
+use crate::error::Error;
+use crate::file::{LogFile, ParseOptions};
+use crate::repository::LogRepository;
+use crate::task::Task;
+use chrono::NaiveDate;
+use std::io::Write;
+
+/// Prints the incomplete tasks with due dates in the latest devlog entry, ordered by due date.
+/// Tasks are grouped into overdue tasks, tasks due `today`, and upcoming tasks.
+pub fn print<W: Write>(
+    w: &mut W,
+    repo: &LogRepository,
+    options: &ParseOptions,
+    today: NaiveDate,
+) -> Result<(), Error> {
+    let tasks = load_due_tasks(repo, options)?;
+    let sections: [(&str, Vec<&(NaiveDate, Task)>); 3] = [
+        (
+            "Overdue",
+            tasks.iter().filter(|(d, _)| *d < today).collect(),
+        ),
+        ("Today", tasks.iter().filter(|(d, _)| *d == today).collect()),
+        (
+            "Upcoming",
+            tasks.iter().filter(|(d, _)| *d > today).collect(),
+        ),
+    ];
+
+    let mut has_prev = false;
+    for (name, tasks) in &sections {
+        if tasks.is_empty() {
+            continue;
+        }
+        if has_prev {
+            writeln!(w)?;
+        }
+        writeln!(w, "{}:", name)?;
+        for (d, t) in tasks {
+            writeln!(w, "{}  {}", d.format("%a %Y-%m-%d"), t)?;
+        }
+        has_prev = true;
+    }
+    Ok(())
+}
+
+/// Loads the incomplete tasks with due dates from the latest entry, sorted by due date.
+/// Subtasks are included without their indentation.
+fn load_due_tasks(
+    repo: &LogRepository,
+    options: &ParseOptions,
+) -> Result<Vec<(NaiveDate, Task)>, Error> {
+    let logpath = match repo.latest()? {
+        Some(logpath) => logpath,
+        None => return Ok(Vec::new()),
+    };
+    let entry_date = date::entry_date(logpath.path())?;
+    let f = LogFile::load_with(logpath.path(), options)?;
+    let mut tasks: Vec<(NaiveDate, Task)> = f
+        .tasks()
+        .iter()
+        .filter(|t| !t.status().is_complete())
+        .filter_map(|t| {
+            let d = t.due_date(entry_date)?;
+            Some((d, Task::new(t.status(), t.content())))
+        })
+        .collect();
+    tasks.sort_by_key(|(d, _)| *d);
+    Ok(tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_agenda() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        let contents = "* Later due:2022-05-20\n^ Now due:2022-05-08\n* Missed due:2022-05-01\n+ Finished due:2022-05-01\n* Someday\n";
+        std::fs::write(logpath.path(), contents).unwrap();
+
+        let mut buf = Vec::new();
+        let today = NaiveDate::from_ymd(2022, 5, 8);
+        print(&mut buf, &repo, &ParseOptions::default(), today).unwrap();
+        assert_eq!(
+            str::from_utf8(&buf).unwrap(),
+            "Overdue:\nSun 2022-05-01  * Missed due:2022-05-01\n\nToday:\nSun 2022-05-08  ^ Now due:2022-05-08\n\nUpcoming:\nFri 2022-05-20  * Later due:2022-05-20\n"
+        );
+    }
+
+    #[test]
+    fn test_agenda_no_deadlines() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let mut buf = Vec::new();
+        print(&mut buf, &repo, &ParseOptions::default(), date::today()).unwrap();
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,407 @@
+//! Archive old devlog entries into one file per year, so the repository directory
+//! only holds recent entries.
+//!
+//! Archives are stored in the `archive` directory of the repository, named by year
+//! like `2022.devlog-archive`.  Each archived entry is a header line with the entry's
+//! sequence number, date, and length in bytes, followed by the entry's contents:
+//!
+//! ```text
+//! devlog-entry 000000001 2022-05-01 16
+//! * Fix the build
+//! ```
+//!
+//! The most recent entry is never archived, so commands that work with the latest entry,
+//! like `edit` and `rollover`, are unaffected.
+
+use crate::atomic;
+use crate::date::EntryDates;
+use crate::error::Error;
+use crate::path::LogPath;
+use crate::repository::LogRepository;
+use chrono::{Datelike, NaiveDate};
+use std::collections::BTreeMap;
+use std::fs::{read, read_dir, read_to_string, remove_file};
+use std::io::{Error as IOError, ErrorKind};
+use std::path::{Path, PathBuf};
+
+const ARCHIVE_DIR_NAME: &str = "archive";
+const ARCHIVE_EXTENSION: &str = "devlog-archive";
+const HEADER_PREFIX: &str = "devlog-entry";
+
+/// Selects which entries to archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cutoff {
+    /// Archive entries created before this date.
+    Date(NaiveDate),
+
+    /// Archive entries with a sequence number less than this.
+    SeqNum(usize),
+}
+
+impl Cutoff {
+    /// Parses a sequence number like "120", or a date like "2022-01-01" or "monday".
+    pub fn parse(s: &str, today: NaiveDate) -> Option<Cutoff> {
+        match s.parse::<usize>() {
+            Ok(seq_num) => Some(Cutoff::SeqNum(seq_num)),
+            Err(_) => crate::date::parse_past_date(s, today).map(Cutoff::Date),
+        }
+    }
+}
+
+/// A devlog entry stored in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivedEntry {
+    seq_num: usize,
+    date: NaiveDate,
+    contents: String,
+    archive_path: PathBuf,
+}
+
+impl ArchivedEntry {
+    /// The entry's sequence number.
+    pub fn seq_num(&self) -> usize {
+        self.seq_num
+    }
+
+    /// The date the entry was created.
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    /// The full contents of the entry.
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+
+    /// The archive file that contains the entry.
+    pub fn archive_path(&self) -> &Path {
+        &self.archive_path
+    }
+}
+
+/// Returns the path to the directory of archives, which may or may not exist.
+pub fn archive_dir(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(ARCHIVE_DIR_NAME)
+}
+
+/// Returns the path to the archive for the specified year, which may or may not exist.
+pub fn archive_path(repo_dir: &Path, year: i32) -> PathBuf {
+    archive_dir(repo_dir).join(format!("{}.{}", year, ARCHIVE_EXTENSION))
+}
+
+/// Returns the entries in the repository directory that the cutoff selects for archiving,
+/// ordered by sequence number.  The most recent entry is never selected.
+pub fn select(repo: &LogRepository, cutoff: Cutoff) -> Result<Vec<LogPath>, Error> {
+    let mut paths = repo.list()?;
+    paths.sort();
+    paths.pop();
+    let dates = EntryDates::load(repo.path())?;
+    let mut selected = Vec::new();
+    for p in paths {
+        let is_old = match cutoff {
+            Cutoff::Date(d) => dates.get(p.path())? < d,
+            Cutoff::SeqNum(n) => p.seq_num() < n,
+        };
+        if is_old {
+            selected.push(p);
+        }
+    }
+    Ok(selected)
+}
+
+/// Moves the entries into the archives for the years they were created,
+/// returning the number of entries archived for each year.
+/// Each archive is written before any entry file is removed,
+/// so a failure never loses an entry.
+pub fn archive(repo: &LogRepository, paths: &[LogPath]) -> Result<Vec<(i32, usize)>, Error> {
+    let dates = EntryDates::load(repo.path())?;
+    let mut by_year: BTreeMap<i32, Vec<ArchivedEntry>> = BTreeMap::new();
+    for p in paths {
+        let date = dates.get(p.path())?;
+        by_year.entry(date.year()).or_default().push(ArchivedEntry {
+            seq_num: p.seq_num(),
+            date,
+            contents: read_to_string(p.path())?,
+            archive_path: archive_path(repo.path(), date.year()),
+        });
+    }
+
+    let mut counts = Vec::new();
+    for (year, new_entries) in by_year {
+        let path = archive_path(repo.path(), year);
+        let mut entries = if path.is_file() {
+            load_archive(&path)?
+        } else {
+            Vec::new()
+        };
+        // An earlier archive that failed before removing the entry files may have stored them already.
+        entries.retain(|e| !new_entries.iter().any(|n| n.seq_num == e.seq_num));
+        counts.push((year, new_entries.len()));
+        entries.extend(new_entries);
+        entries.sort_by_key(|e| e.seq_num);
+        std::fs::create_dir_all(archive_dir(repo.path()))?;
+        atomic::write(&path, &format_archive(&entries))?;
+    }
+
+    for p in paths {
+        remove_file(p.path())?;
+    }
+    Ok(counts)
+}
+
+/// Loads every archived entry, ordered by sequence number.
+pub fn load(repo_dir: &Path) -> Result<Vec<ArchivedEntry>, Error> {
+    let dir = archive_dir(repo_dir);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for dir_entry in read_dir(&dir)? {
+        let path = dir_entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some(ARCHIVE_EXTENSION) {
+            entries.extend(load_archive(&path)?);
+        }
+    }
+    entries.sort_by_key(|e| e.seq_num);
+    Ok(entries)
+}
+
+/// Returns the archived entry with the specified sequence number, if there is one.
+pub fn find(repo_dir: &Path, seq_num: usize) -> Result<Option<ArchivedEntry>, Error> {
+    Ok(load(repo_dir)?.into_iter().find(|e| e.seq_num == seq_num))
+}
+
+/// Returns the last archived entry created on or before the specified date.
+pub fn find_by_date(repo_dir: &Path, date: NaiveDate) -> Result<Option<ArchivedEntry>, Error> {
+    Ok(load(repo_dir)?.into_iter().rev().find(|e| e.date <= date))
+}
+
+/// Returns the "nth" most recent entry, counting the entries in the repository directory
+/// before archived entries, like `LogRepository::nth_from_latest`.
+/// Returns `None` if `n` selects an entry in the repository directory.
+pub fn nth_from_latest(repo: &LogRepository, n: usize) -> Result<Option<ArchivedEntry>, Error> {
+    let num_live = repo.list()?.len();
+    if n < num_live {
+        return Ok(None);
+    }
+    Ok(load(repo.path())?.into_iter().rev().nth(n - num_live))
+}
+
+/// Returns the greatest sequence number of any archived entry.
+pub fn max_seq_num(repo_dir: &Path) -> Result<Option<usize>, Error> {
+    Ok(load(repo_dir)?.last().map(|e| e.seq_num))
+}
+
+fn format_archive(entries: &[ArchivedEntry]) -> String {
+    let mut s = String::new();
+    for e in entries {
+        s.push_str(&format!(
+            "{} {:09} {} {}\n",
+            HEADER_PREFIX,
+            e.seq_num,
+            e.date.format("%Y-%m-%d"),
+            e.contents.len()
+        ));
+        s.push_str(&e.contents);
+    }
+    s
+}
+
+fn load_archive(path: &Path) -> Result<Vec<ArchivedEntry>, Error> {
+    let invalid = || {
+        IOError::new(
+            ErrorKind::InvalidData,
+            format!("{:?} is not a valid devlog archive", path),
+        )
+    };
+    let bytes = read(path)?;
+    let mut entries = Vec::new();
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let header_len = rest.iter().position(|&b| b == b'\n').ok_or_else(invalid)?;
+        let header = std::str::from_utf8(&rest[..header_len]).map_err(|_| invalid())?;
+        let (seq_num, date, len) = parse_header(header).ok_or_else(invalid)?;
+        rest = &rest[header_len + 1..];
+        if len > rest.len() {
+            return Err(invalid().into());
+        }
+        let contents = std::str::from_utf8(&rest[..len]).map_err(|_| invalid())?;
+        entries.push(ArchivedEntry {
+            seq_num,
+            date,
+            contents: contents.to_string(),
+            archive_path: path.to_path_buf(),
+        });
+        rest = &rest[len..];
+    }
+    Ok(entries)
+}
+
+fn parse_header(header: &str) -> Option<(usize, NaiveDate, usize)> {
+    let mut fields = header.split(' ');
+    if fields.next()? != HEADER_PREFIX {
+        return None;
+    }
+    let seq_num = fields.next()?.parse().ok()?;
+    let date = NaiveDate::parse_from_str(fields.next()?, "%Y-%m-%d").ok()?;
+    let len = fields.next()?.parse().ok()?;
+    match fields.next() {
+        None => Some((seq_num, date, len)),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::today;
+    use chrono::Duration;
+    use std::fs::write;
+    use tempfile::tempdir;
+
+    fn create_entries(repo: &LogRepository, count: usize) {
+        for seq in 1..=count {
+            let p = LogPath::new(repo.path(), seq);
+            write(p.path(), format!("* Entry {}\n", seq)).unwrap();
+        }
+    }
+
+    fn seq_nums(paths: &[LogPath]) -> Vec<usize> {
+        paths.iter().map(|p| p.seq_num()).collect()
+    }
+
+    #[test]
+    fn test_format_and_load_archive() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("2022.devlog-archive");
+        let entry = |seq_num, contents: &str| ArchivedEntry {
+            seq_num,
+            date: NaiveDate::from_ymd(2022, 5, seq_num as u32),
+            contents: contents.to_string(),
+            archive_path: path.clone(),
+        };
+        // Contents may contain lines that look like headers, multibyte characters,
+        // or no trailing newline.
+        let entries = vec![
+            entry(1, "* Foo\ndevlog-entry 000000009 2022-05-09 3\n"),
+            entry(2, ""),
+            entry(3, "* Café ☕"),
+        ];
+        let s = format_archive(&entries);
+        assert!(s.starts_with("devlog-entry 000000001 2022-05-01 42\n* Foo\n"));
+        write(&path, &s).unwrap();
+        assert_eq!(load_archive(&path).unwrap(), entries);
+
+        write(&path, "devlog-entry 000000001 2022-05-01 100\n* Foo\n").unwrap();
+        assert!(load_archive(&path).is_err());
+        write(&path, "* Foo\n").unwrap();
+        assert!(load_archive(&path).is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        create_entries(&repo, 4);
+
+        assert_eq!(
+            seq_nums(&select(&repo, Cutoff::SeqNum(3)).unwrap()),
+            vec![1, 2]
+        );
+        assert_eq!(
+            seq_nums(&select(&repo, Cutoff::SeqNum(10)).unwrap()),
+            vec![1, 2, 3]
+        );
+        assert!(select(&repo, Cutoff::Date(today())).unwrap().is_empty());
+        let tomorrow = today() + Duration::days(1);
+        assert_eq!(
+            seq_nums(&select(&repo, Cutoff::Date(tomorrow)).unwrap()),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_parse_cutoff() {
+        let today = NaiveDate::from_ymd(2022, 5, 8);
+        assert_eq!(Cutoff::parse("120", today), Some(Cutoff::SeqNum(120)));
+        assert_eq!(
+            Cutoff::parse("2022-01-01", today),
+            Some(Cutoff::Date(NaiveDate::from_ymd(2022, 1, 1)))
+        );
+        assert_eq!(
+            Cutoff::parse("monday", today),
+            Some(Cutoff::Date(NaiveDate::from_ymd(2022, 5, 2)))
+        );
+        assert_eq!(Cutoff::parse("someday", today), None);
+    }
+
+    #[test]
+    fn test_archive_and_read() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        create_entries(&repo, 5);
+
+        let paths = select(&repo, Cutoff::SeqNum(3)).unwrap();
+        let year = today().year();
+        assert_eq!(archive(&repo, &paths).unwrap(), vec![(year, 2)]);
+        let paths = select(&repo, Cutoff::SeqNum(4)).unwrap();
+        assert_eq!(archive(&repo, &paths).unwrap(), vec![(year, 1)]);
+
+        // The repository directory keeps the recent entries.
+        let mut live = repo.list().unwrap();
+        live.sort();
+        assert_eq!(seq_nums(&live), vec![4, 5]);
+        assert_eq!(repo.latest().unwrap().unwrap().seq_num(), 5);
+
+        let archived = load(dir.path()).unwrap();
+        let archived_seq_nums: Vec<usize> = archived.iter().map(|e| e.seq_num()).collect();
+        assert_eq!(archived_seq_nums, vec![1, 2, 3]);
+        assert_eq!(archived[0].contents(), "* Entry 1\n");
+        assert_eq!(archived[0].date(), today());
+        assert_eq!(archived[0].archive_path(), archive_path(dir.path(), year));
+
+        assert_eq!(
+            find(dir.path(), 2).unwrap().unwrap().contents(),
+            "* Entry 2\n"
+        );
+        assert_eq!(find(dir.path(), 4).unwrap(), None);
+        assert_eq!(
+            find_by_date(dir.path(), today())
+                .unwrap()
+                .unwrap()
+                .seq_num(),
+            3
+        );
+        assert_eq!(max_seq_num(dir.path()).unwrap(), Some(3));
+
+        assert_eq!(nth_from_latest(&repo, 1).unwrap(), None);
+        assert_eq!(nth_from_latest(&repo, 2).unwrap().unwrap().seq_num(), 3);
+        assert_eq!(nth_from_latest(&repo, 4).unwrap().unwrap().seq_num(), 1);
+        assert_eq!(nth_from_latest(&repo, 5).unwrap(), None);
+    }
+
+    #[test]
+    fn test_archive_entries_already_archived() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        create_entries(&repo, 3);
+
+        // Simulate an archive that failed after writing the archive but before removing entries.
+        let paths = select(&repo, Cutoff::SeqNum(3)).unwrap();
+        let copies: Vec<PathBuf> = paths
+            .iter()
+            .map(|p| {
+                let copy = p.path().with_extension("copy");
+                std::fs::copy(p.path(), &copy).unwrap();
+                copy
+            })
+            .collect();
+        archive(&repo, &paths).unwrap();
+        for (p, copy) in paths.iter().zip(&copies) {
+            std::fs::rename(copy, p.path()).unwrap();
+        }
+
+        archive(&repo, &paths).unwrap();
+        assert_eq!(load(dir.path()).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,150 @@
+//! Atomic file writes, so a crash or full disk never leaves a half-written devlog file.
+//!
+//! Contents are first written to a temporary file in the same directory,
+//! then moved into place, so the destination holds either the old or the new contents.
+//! Temporary files are hidden and end with ".tmp", so they are never mistaken for entries.
+
+use std::fs::{hard_link, metadata, remove_file, rename, set_permissions, File, OpenOptions};
+use std::io::{Error as IOError, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Distinguishes temporary files written concurrently by threads in the same process.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Replaces the contents of the file at `path`, creating it if it does not exist.
+/// An existing file keeps its permissions.
+pub fn write(path: &Path, contents: &str) -> Result<(), IOError> {
+    let tmp = write_temp(path, contents)?;
+    let result = copy_permissions(path, &tmp).and_then(|_| rename(&tmp, path));
+    if result.is_err() {
+        let _ = remove_file(&tmp);
+    }
+    result?;
+    sync_parent_dir(path)
+}
+
+/// Creates a file at `path` with the specified contents.
+/// Like `OpenOptions::create_new`, this fails with `ErrorKind::AlreadyExists`
+/// if the file already exists, even if another process creates it concurrently.
+pub fn create_new(path: &Path, contents: &str) -> Result<(), IOError> {
+    let tmp = write_temp(path, contents)?;
+    // Unlike rename, linking fails if the destination exists.
+    let result = hard_link(&tmp, path);
+    let _ = remove_file(&tmp);
+    result?;
+    sync_parent_dir(path)
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    }
+}
+
+/// Writes the contents to a new temporary file next to `path` and flushes it to disk.
+fn write_temp(path: &Path, contents: &str) -> Result<PathBuf, IOError> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| IOError::new(ErrorKind::InvalidInput, "path has no file name"))?;
+    let tmp = parent_dir(path).join(format!(
+        ".{}.{}-{}.tmp",
+        name.to_string_lossy(),
+        process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp)
+        .and_then(|mut f| {
+            f.write_all(contents.as_bytes())?;
+            f.sync_all()
+        });
+    match result {
+        Ok(()) => Ok(tmp),
+        Err(err) => {
+            let _ = remove_file(&tmp);
+            Err(err)
+        }
+    }
+}
+
+fn copy_permissions(from: &Path, to: &Path) -> Result<(), IOError> {
+    match metadata(from) {
+        Ok(m) => set_permissions(to, m.permissions()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Flushes the directory entry for `path`, so the new file survives a crash.
+fn sync_parent_dir(path: &Path) -> Result<(), IOError> {
+    File::open(parent_dir(path))?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{read_dir, read_to_string, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_write_replaces_contents() {
+        let dir = tempdir().unwrap();
+        let p = dir.path().join("000000001.devlog");
+        write(&p, "* Foo\n").unwrap();
+        assert_eq!(read_to_string(&p).unwrap(), "* Foo\n");
+        write(&p, "+ Foo\n").unwrap();
+        assert_eq!(read_to_string(&p).unwrap(), "+ Foo\n");
+        assert_eq!(file_names(dir.path()), vec!["000000001.devlog"]);
+    }
+
+    #[test]
+    fn test_write_preserves_permissions() {
+        let dir = tempdir().unwrap();
+        let p = dir.path().join("000000001.devlog");
+        write(&p, "* Foo\n").unwrap();
+        set_permissions(&p, Permissions::from_mode(0o640)).unwrap();
+        write(&p, "+ Foo\n").unwrap();
+        assert_eq!(metadata(&p).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_create_new() {
+        let dir = tempdir().unwrap();
+        let p = dir.path().join("000000001.devlog");
+        create_new(&p, "* Foo\n").unwrap();
+        let err = create_new(&p, "* Bar\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(read_to_string(&p).unwrap(), "* Foo\n");
+        assert_eq!(file_names(dir.path()), vec!["000000001.devlog"]);
+    }
+
+    #[test]
+    fn test_failed_write_removes_temp_file() {
+        let dir = tempdir().unwrap();
+        let p = dir.path().join("000000001.devlog");
+        std::fs::create_dir(&p).unwrap();
+        write(&p.join("inner"), "").unwrap();
+
+        // A file can't replace a non-empty directory.
+        assert!(write(&p, "* Foo\n").is_err());
+        assert!(create_new(&p, "* Foo\n").is_err());
+        assert_eq!(file_names(dir.path()), vec!["000000001.devlog"]);
+        assert_eq!(file_names(&p), vec!["inner"]);
+    }
+}
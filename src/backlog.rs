@@ -0,0 +1,134 @@
+//! Move tasks between the latest devlog entry and the repository's backlog.
+//!
+//! The backlog holds "someday" tasks, so they don't need to be rolled over into every entry.
+//! Tasks are selected by a query that matches their content (see `Task::matches`),
+//! and move along with their subtasks and bodies.
+
+use crate::error::Error;
+use crate::file::{LogFile, ParseOptions};
+use crate::repository::LogRepository;
+use crate::task::Task;
+use std::path::Path;
+
+/// Moves the top-level tasks matching `query` from the latest entry to the end of the backlog.
+/// Returns the moved tasks, which are empty if no tasks matched.
+pub fn push(repo: &LogRepository, options: &ParseOptions, query: &str) -> Result<Vec<Task>, Error> {
+    let latest = repo
+        .latest()?
+        .ok_or(Error::InvalidArg("no devlog entries"))?;
+    move_tasks(latest.path(), &repo.backlog_path(), options, query)
+}
+
+/// Moves the top-level tasks matching `query` from the backlog to the end of the latest entry.
+/// Returns the moved tasks, which are empty if no tasks matched.
+pub fn pull(repo: &LogRepository, options: &ParseOptions, query: &str) -> Result<Vec<Task>, Error> {
+    let latest = repo
+        .latest()?
+        .ok_or(Error::InvalidArg("no devlog entries"))?;
+    move_tasks(&repo.backlog_path(), latest.path(), options, query)
+}
+
+fn move_tasks(
+    from: &Path,
+    to: &Path,
+    options: &ParseOptions,
+    query: &str,
+) -> Result<Vec<Task>, Error> {
+    let mut src = load_or_empty(from, options)?;
+    let mut moved = Vec::new();
+    let mut i = 0;
+    while i < src.tasks().len() {
+        let t = &src.tasks()[i];
+        if t.depth() == 0 && t.matches(query) {
+            moved.extend(src.remove_subtree(i));
+        } else {
+            i += 1;
+        }
+    }
+    if moved.is_empty() {
+        return Ok(moved);
+    }
+
+    let mut dst = load_or_empty(to, options)?;
+    dst.add_tasks(&moved);
+    dst.save(to)?;
+    src.save(from)?;
+    Ok(moved)
+}
+
+fn load_or_empty(path: &Path, options: &ParseOptions) -> Result<LogFile, Error> {
+    if path.is_file() {
+        Ok(LogFile::load_with(path, options)?)
+    } else {
+        Ok(LogFile::parse_with("", options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rollover::rollover;
+    use std::fs::{read_to_string, write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_push_and_pull() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        write(
+            logpath.path(),
+            "Notes\n* Learn Rust\n    read the book\n^ Fix bug\n* Learn Go\n",
+        )
+        .unwrap();
+        let options = ParseOptions::default();
+
+        let moved = push(&repo, &options, "learn").unwrap();
+        assert_eq!(moved.len(), 2);
+        assert_eq!(
+            read_to_string(logpath.path()).unwrap(),
+            "Notes\n^ Fix bug\n"
+        );
+        assert_eq!(
+            read_to_string(repo.backlog_path()).unwrap(),
+            "* Learn Rust\n    read the book\n* Learn Go\n"
+        );
+
+        let moved = pull(&repo, &options, "rust").unwrap();
+        assert_eq!(moved[0].content(), "Learn Rust");
+        assert_eq!(
+            read_to_string(logpath.path()).unwrap(),
+            "Notes\n^ Fix bug\n* Learn Rust\n    read the book\n"
+        );
+        assert_eq!(read_to_string(repo.backlog_path()).unwrap(), "* Learn Go\n");
+
+        assert!(pull(&repo, &options, "nothing").unwrap().is_empty());
+
+        // Rollover copies tasks from the latest entry, but not the backlog.
+        let config = Config::new(dir.path(), "");
+        let (next, _) = rollover(&mut Vec::new(), &config, &logpath).unwrap();
+        assert_eq!(
+            read_to_string(next.path()).unwrap(),
+            "^ Fix bug\n* Learn Rust\n    read the book\n"
+        );
+        assert_eq!(read_to_string(repo.backlog_path()).unwrap(), "* Learn Go\n");
+    }
+
+    #[test]
+    fn test_push_with_subtasks() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        write(logpath.path(), "* Foo\n    * Foo 1\n* Bar\n").unwrap();
+        let options = ParseOptions::default().with_subtasks(true);
+
+        let moved = push(&repo, &options, "foo").unwrap();
+        assert_eq!(moved.len(), 2);
+        assert_eq!(read_to_string(logpath.path()).unwrap(), "* Bar\n");
+        assert_eq!(
+            read_to_string(repo.backlog_path()).unwrap(),
+            "* Foo\n    * Foo 1\n"
+        );
+    }
+}
@@ -1,19 +1,50 @@
 extern crate clap;
 extern crate devlog;
 
+use chrono::{Local, NaiveDate};
 use clap::{Arg, ArgMatches, Command};
-use devlog::{editor, hook, rollover, status, Config, Error, LogRepository, TaskStatus};
-use std::fs::File;
+use devlog::hook::{HookState, HookType};
+use devlog::lock::RepoLock;
+use devlog::{
+    agenda, archive, atomic, backlog, date, deferred, editor, focus, fsck, hook, lint, lock, lsp,
+    renumber, rollover, serve, status, sync, timelog, tui, Config, Error, LogPath, LogRepository,
+    ParseOptions, Task,
+};
+use std::ffi::OsStr;
+use std::fs::{read_to_string, File};
 use std::io::{copy, stdin, stdout, Write};
 use std::process::exit;
+use std::thread::sleep;
+use std::time::Duration;
 
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-const MAIN_INFO: &'static str =
+const MAIN_INFO: &str =
     "Devlog files are created in the directory at $DEVLOG_REPO, which defaults to $HOME/devlogs if not set.";
 
-const EDIT_INFO: &'static str =
-    "Uses the editor program $DEVLOG_EDITOR, which defaults to nano if not set.";
+const EDIT_INFO: &str =
+    "Uses the editor program $DEVLOG_EDITOR, which defaults to nano if not set.
+The editor may include arguments (for example, \"code --wait\").
+Use {file} and {line} in the editor command to control where the devlog path and line number are placed.";
+
+const LINT_INFO: &str = "Checks the most recent devlog file unless another is selected.
+Exits with status 1 if any problems remain, so it can be used in a pre-commit hook.";
+
+const SERVE_INFO: &str =
+    "Endpoints: GET /entries, GET /entries/{seq}, GET|POST /entries/{seq}/tasks,
+PUT /entries/{seq}/tasks/{index}, GET /status, POST /rollover.
+Requests must use a localhost Host header and no Origin header, and POST and PUT requests
+must have the content type application/json, so web pages in your browser can't use the API.
+The server has no authentication, so only bind to addresses you trust.";
+
+const FSCK_INFO: &str = "Repairs move files that can't be fixed into the \"quarantine\" directory of the devlog repository.
+Gaps in the sequence of entries are reported, but not repaired; use `devlog renumber` to close them.";
+
+const ARCHIVE_INFO: &str = "The most recent devlog file is never archived.
+Archived devlog files can still be viewed with `devlog show`.";
+
+const HOOKS_INFO: &str =
+    "Hooks are executable files in the \"hooks\" directory of the devlog repository.";
 
 fn main() -> Result<(), Error> {
     let yes_arg = Arg::new("yes")
@@ -21,6 +52,20 @@ fn main() -> Result<(), Error> {
         .long("yes")
         .help("Automatically answer \"yes\" in response to all prompts.");
 
+    let wait_arg = Arg::new("wait")
+        .long("wait")
+        .help("If another devlog command is modifying the repository, wait for it to finish");
+
+    let hook_name_arg = Arg::new("name")
+        .value_name("NAME")
+        .required(true)
+        .help("Name of the hook, as shown by `devlog hooks list`");
+
+    let query_arg = Arg::new("query")
+        .value_name("QUERY")
+        .required(true)
+        .help("Move top-level tasks containing this text (ignoring case), with their subtasks");
+
     let m = Command::new("devlog")
         .about("Track daily development work")
         .after_help(MAIN_INFO)
@@ -29,18 +74,45 @@ fn main() -> Result<(), Error> {
         .subcommand(
             Command::new("init")
                 .about("Initialize a new devlog repository if it does not already exist.")
-                .arg(yes_arg.clone()),
+                .arg(yes_arg.clone())
+                .arg(wait_arg.clone())
+                .arg(
+                    Arg::new("git")
+                        .long("git")
+                        .help("Set up the repository for `devlog sync` by making it a git repository"),
+                )
+                .arg(
+                    Arg::new("remote")
+                        .long("remote")
+                        .takes_value(true)
+                        .value_name("URL")
+                        .requires("git")
+                        .help("URL of the git remote to sync with"),
+                ),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Commit changes to the devlog repository, then pull from and push to its git remote")
+                .arg(wait_arg.clone()),
         )
         .subcommand(
             Command::new("edit")
                 .about("Edit the most recent devlog file")
                 .after_help(EDIT_INFO)
-                .arg(yes_arg.clone()),
+                .arg(yes_arg.clone())
+                .arg(wait_arg.clone())
+                .args(entry_selector_args()),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Show a single devlog file")
+                .args(entry_selector_args()),
         )
         .subcommand(
             Command::new("rollover")
                 .about("Create new devlog file with incomplete and blocked tasks from the current devlog file")
-                .arg(yes_arg.clone()),
+                .arg(yes_arg.clone())
+                .arg(wait_arg.clone()),
         )
         .subcommand(
             Command::new("status")
@@ -51,9 +123,17 @@ fn main() -> Result<(), Error> {
                         .long("show")
                         .takes_value(true)
                         .value_name("SHOW")
-                        .possible_values(&["all", "todo", "started", "blocked", "done"])
                         .default_value("all")
-                        .help("Sections to show"),
+                        .help("Sections to show: \"all\" or a status name (todo, started, blocked, done, or a custom status)"),
+                )
+                .arg(
+                    Arg::new("sort")
+                        .long("sort")
+                        .takes_value(true)
+                        .value_name("SORT")
+                        .possible_values(["file", "priority", "age"])
+                        .default_value("file")
+                        .help("Order of tasks in each section"),
                 )
                 .arg(
                     Arg::new("back")
@@ -63,8 +143,95 @@ fn main() -> Result<(), Error> {
                         .value_name("BACK")
                         .default_value("0")
                         .help("Show tasks from a previous devlog"),
+                )
+                .arg(
+                    Arg::new("backlog")
+                        .long("backlog")
+                        .conflicts_with("back")
+                        .help("Show tasks from the backlog instead of a devlog"),
+                ),
+        )
+        .subcommand(
+            Command::new("push")
+                .about("Move tasks from the most recent devlog file to the backlog")
+                .arg(query_arg.clone())
+                .arg(wait_arg.clone()),
+        )
+        .subcommand(
+            Command::new("pull")
+                .about("Move tasks from the backlog to the most recent devlog file")
+                .arg(query_arg)
+                .arg(wait_arg.clone()),
+        )
+        .subcommand(
+            Command::new("start")
+                .about("Mark a task in the most recent devlog file as started and start its timer")
+                .arg(
+                    Arg::new("query")
+                        .value_name("QUERY")
+                        .required(true)
+                        .help("Text that the task contains (ignoring case)"),
+                )
+                .arg(wait_arg.clone()),
+        )
+        .subcommand(
+            Command::new("stop")
+                .about("Stop the running timer")
+                .arg(wait_arg.clone()),
+        )
+        .subcommand(
+            Command::new("focus")
+                .about("Mark a task as started and focus on it for a fixed number of minutes")
+                .arg(
+                    Arg::new("query")
+                        .value_name("QUERY")
+                        .required(true)
+                        .help("Text that the task contains (ignoring case)"),
+                )
+                .arg(
+                    Arg::new("minutes")
+                        .short('m')
+                        .long("minutes")
+                        .takes_value(true)
+                        .value_name("MINUTES")
+                        .default_value("25")
+                        .help("Length of the focus session"),
+                )
+                .arg(yes_arg.clone())
+                .arg(wait_arg.clone()),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Show focus time per day")
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("DATE")
+                        .default_value("monday")
+                        .help("Include sessions started on or after this date"),
+                ),
+        )
+        .subcommand(
+            Command::new("time")
+                .about("Show time tracked per task and tag")
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("DATE")
+                        .default_value("today")
+                        .help("Include timers started on or after this date (e.g. \"monday\" or \"2022-05-08\")"),
                 ),
         )
+        .subcommand(
+            Command::new("agenda")
+                .about("Show upcoming deadlines from the most recent devlog file"),
+        )
+        .subcommand(
+            Command::new("deferred")
+                .about("Show tasks parked until a later date by a defer: attribute"),
+        )
         .subcommand(
             Command::new("tail")
                 .about("Show recent devlogs")
@@ -78,19 +245,237 @@ fn main() -> Result<(), Error> {
                         .default_value("2"),
                 ),
         )
+        .subcommand(Command::new("tui").about("Open an interactive view of recent tasks"))
+        .subcommand(
+            Command::new("lint")
+                .about("Check devlog files for lines that look like tasks but are not parsed as tasks")
+                .after_help(LINT_INFO)
+                .args(entry_selector_args())
+                .arg(
+                    Arg::new("all")
+                        .short('a')
+                        .long("all")
+                        .conflicts_with_all(&["back", "seq", "date"])
+                        .help("Check every devlog file in the repository"),
+                )
+                .arg(
+                    Arg::new("fix")
+                        .long("fix")
+                        .help("Rewrite suspicious lines using the suggested fixes"),
+                )
+                .arg(wait_arg.clone()),
+        )
+        .subcommand(
+            Command::new("fsck")
+                .about("Check the devlog repository for misnamed, copied, or unreadable files")
+                .after_help(FSCK_INFO)
+                .arg(
+                    Arg::new("repair")
+                        .long("repair")
+                        .help("Rename misnamed entries, quarantine files that can't be fixed, and fix hook permissions"),
+                )
+                .arg(wait_arg.clone()),
+        )
+        .subcommand(
+            Command::new("renumber")
+                .about("Renumber devlog files so their sequence numbers have no gaps")
+                .arg(
+                    Arg::new("start")
+                        .long("start")
+                        .takes_value(true)
+                        .value_name("SEQ")
+                        .help("Sequence number for the oldest devlog file (defaults to 1, or the number after the last archived entry)"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Show how devlog files would be renumbered without renaming them"),
+                )
+                .arg(yes_arg.clone())
+                .arg(wait_arg.clone()),
+        )
+        .subcommand(
+            Command::new("archive")
+                .about("Move old devlog files into yearly archives")
+                .after_help(ARCHIVE_INFO)
+                .arg(
+                    Arg::new("before")
+                        .long("before")
+                        .takes_value(true)
+                        .value_name("DATE|SEQ")
+                        .required(true)
+                        .help("Archive devlog files created before this date, or numbered before this sequence number"),
+                )
+                .arg(yes_arg.clone())
+                .arg(wait_arg),
+        )
+        .subcommand(Command::new("lsp").about("Run a language server for devlog files over stdio"))
+        .subcommand(
+            Command::new("serve")
+                .about("Serve a local HTTP/JSON API for devlog entries")
+                .after_help(SERVE_INFO)
+                .arg(
+                    Arg::new("bind")
+                        .long("bind")
+                        .takes_value(true)
+                        .value_name("ADDR")
+                        .help("Address to listen on")
+                        .default_value("127.0.0.1:8080"),
+                ),
+        )
+        .subcommand(
+            Command::new("hooks")
+                .about("Manage hooks")
+                .after_help(HOOKS_INFO)
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("Show available hooks and whether they are enabled"))
+                .subcommand(
+                    Command::new("enable")
+                        .about("Enable a hook, creating it from a template if necessary")
+                        .arg(hook_name_arg.clone()),
+                )
+                .subcommand(
+                    Command::new("disable")
+                        .about("Disable a hook")
+                        .arg(hook_name_arg.clone()),
+                )
+                .subcommand(
+                    Command::new("run")
+                        .about("Run a hook with the specified arguments")
+                        .arg(hook_name_arg.clone())
+                        .arg(
+                            Arg::new("args")
+                                .value_name("ARGS")
+                                .multiple_values(true)
+                                .help("Arguments passed to the hook"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("test")
+                        .about("Run a hook with sample arguments, without editing or rolling over any devlogs")
+                        .arg(hook_name_arg.clone())
+                        .arg(
+                            Arg::new("back")
+                                .short('b')
+                                .long("back")
+                                .takes_value(true)
+                                .value_name("BACK")
+                                .default_value("0")
+                                .help("Use a previous devlog as the sample entry"),
+                        ),
+                ),
+        )
         .get_matches();
 
     let mut w = stdout();
     match m.subcommand() {
         Some(("init", m)) => init_cmd(&mut w, m),
+        Some(("sync", m)) => sync_cmd(&mut w, m),
         Some(("edit", m)) => edit_cmd(&mut w, m),
+        Some(("show", m)) => show_cmd(&mut w, m),
         Some(("rollover", m)) => rollover_cmd(&mut w, m),
         Some(("status", m)) => status_cmd(&mut w, m),
+        Some(("push", m)) => push_cmd(&mut w, m),
+        Some(("pull", m)) => pull_cmd(&mut w, m),
+        Some(("start", m)) => start_cmd(&mut w, m),
+        Some(("stop", m)) => stop_cmd(&mut w, m),
+        Some(("focus", m)) => focus_cmd(&mut w, m),
+        Some(("stats", m)) => stats_cmd(&mut w, m),
+        Some(("time", m)) => time_cmd(&mut w, m),
+        Some(("agenda", _)) => agenda_cmd(&mut w),
+        Some(("deferred", _)) => deferred_cmd(&mut w),
         Some(("tail", m)) => tail_cmd(&mut w, m),
+        Some(("tui", _)) => tui_cmd(&mut w),
+        Some(("serve", m)) => serve_cmd(&mut w, m),
+        Some(("lint", m)) => lint_cmd(&mut w, m),
+        Some(("fsck", m)) => fsck_cmd(&mut w, m),
+        Some(("renumber", m)) => renumber_cmd(&mut w, m),
+        Some(("archive", m)) => archive_cmd(&mut w, m),
+        Some(("lsp", _)) => lsp_cmd(),
+        Some(("hooks", m)) => hooks_cmd(&mut w, m),
         _ => panic!("No subcommand"),
     }
 }
 
+fn entry_selector_args<'a>() -> [Arg<'a>; 3] {
+    [
+        Arg::new("back")
+            .short('b')
+            .long("back")
+            .takes_value(true)
+            .value_name("BACK")
+            .conflicts_with_all(&["seq", "date"])
+            .help("Select a previous devlog, counting back from the most recent"),
+        Arg::new("seq")
+            .long("seq")
+            .takes_value(true)
+            .value_name("SEQ")
+            .conflicts_with("date")
+            .help("Select the devlog with this sequence number"),
+        Arg::new("date")
+            .short('d')
+            .long("date")
+            .takes_value(true)
+            .value_name("DATE")
+            .help("Select the most recent devlog as of DATE (today, yesterday, the most recent weekday like \"friday\", or YYYY-MM-DD)"),
+    ]
+}
+
+/// A devlog entry chosen by the arguments from `entry_selector_args`.
+enum EntrySelection {
+    SeqNum(usize),
+    Date(NaiveDate),
+    Back(usize),
+}
+
+fn entry_selection(m: &ArgMatches) -> Result<EntrySelection, Error> {
+    if let Some(seq) = m.value_of("seq") {
+        let seq_num = seq
+            .parse::<usize>()
+            .map_err(|_| Error::InvalidArg("seq must be an integer"))?;
+        Ok(EntrySelection::SeqNum(seq_num))
+    } else if let Some(d) = m.value_of("date") {
+        let date = date::parse_past_date(d, date::today()).ok_or(Error::InvalidArg(
+            "date must be today, yesterday, a weekday, or YYYY-MM-DD",
+        ))?;
+        Ok(EntrySelection::Date(date))
+    } else {
+        let num_back = m
+            .value_of("back")
+            .unwrap_or("0")
+            .parse::<usize>()
+            .map_err(|_| Error::InvalidArg("back must be an integer"))?;
+        Ok(EntrySelection::Back(num_back))
+    }
+}
+
+/// Finds the devlog entry chosen by the arguments from `entry_selector_args`,
+/// defaulting to the most recent entry.
+fn select_entry(repo: &LogRepository, m: &ArgMatches) -> Result<Option<LogPath>, Error> {
+    match entry_selection(m)? {
+        EntrySelection::SeqNum(seq_num) => repo.get(seq_num),
+        EntrySelection::Date(date) => repo.find_by_date(date),
+        EntrySelection::Back(num_back) => repo.nth_from_latest(num_back),
+    }
+}
+
+/// Finds the archived entry chosen by the arguments from `entry_selector_args`,
+/// for selections that no entry in the repository directory matches.
+fn select_archived_entry(
+    repo: &LogRepository,
+    m: &ArgMatches,
+) -> Result<Option<archive::ArchivedEntry>, Error> {
+    match entry_selection(m)? {
+        EntrySelection::SeqNum(seq_num) => archive::find(repo.path(), seq_num),
+        EntrySelection::Date(date) => archive::find_by_date(repo.path(), date),
+        EntrySelection::Back(num_back) => archive::nth_from_latest(repo, num_back),
+    }
+}
+
+fn selects_latest(m: &ArgMatches) -> bool {
+    !m.is_present("back") && !m.is_present("seq") && !m.is_present("date")
+}
+
 fn prompt_confirm<W: Write>(w: &mut W, msg: &str, m: &ArgMatches) -> Result<bool, Error> {
     if m.is_present("yes") {
         return Ok(true);
@@ -117,6 +502,27 @@ fn abort_if_not_initialized<W: Write>(w: &mut W, repo: &LogRepository) -> Result
     Ok(())
 }
 
+/// Acquires the repository lock, waiting for it if the "wait" arg is present.
+/// Exits with a message if another command holds the lock.
+/// Since `exit` skips destructors, callers must release the lock before exiting.
+fn lock_repo<W: Write>(w: &mut W, repo: &LogRepository, m: &ArgMatches) -> Result<RepoLock, Error> {
+    match RepoLock::acquire(repo.path()) {
+        Err(Error::RepositoryLocked(_)) if m.is_present("wait") => {
+            writeln!(w, "Waiting for another devlog command to finish...")?;
+            w.flush()?;
+            RepoLock::wait(repo.path())
+        }
+        Err(Error::RepositoryLocked(p)) => {
+            let holder = lock::holder(repo.path())
+                .map(|pid| format!(" (process {})", pid))
+                .unwrap_or_default();
+            write!(w, "Another devlog command{} is modifying the repository at {:?}.\nRun the command again with --wait to wait for it to finish, or delete {:?} if no other devlog command is running.\n", holder, repo.path(), p)?;
+            exit(1)
+        }
+        result => result,
+    }
+}
+
 fn initialize_if_necessary<W: Write>(
     w: &mut W,
     repo: &LogRepository,
@@ -127,8 +533,12 @@ fn initialize_if_necessary<W: Write>(
     } else {
         let msg = format!("Initialize devlog repository at {:?}?", repo.path());
         if prompt_confirm(w, &msg, m)? {
-            repo.init()?;
-            hook::init_hooks(repo.path())?;
+            let _lock = lock_repo(w, repo, m)?;
+            // Another command may have initialized the repository while we waited for the prompt.
+            if !repo.initialized()? {
+                repo.init()?;
+                hook::init_hooks(repo.path())?;
+            }
         } else {
             exit(0);
         }
@@ -137,57 +547,142 @@ fn initialize_if_necessary<W: Write>(
 }
 
 fn init_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
-    let config = Config::load();
+    let config = Config::try_load()?;
     let repo = LogRepository::new(config.repo_dir());
     initialize_if_necessary(w, &repo, m).and_then(|created| {
+        let _lock = lock_repo(w, &repo, m)?;
         if created {
-            write!(
+            writeln!(
                 w,
-                "Success!  Now you can open your devlog using `devlog edit`\n",
-            )
-            .map_err(From::from)
+                "Success!  Now you can open your devlog using `devlog edit`",
+            )?;
         } else {
-            write!(w, "Devlog repository already exists at {:?}\n", repo.path()).map_err(From::from)
+            writeln!(w, "Devlog repository already exists at {:?}", repo.path())?;
+            // Add templates for any hook types introduced since the repository was created.
+            for hook_type in hook::init_hooks(repo.path())? {
+                writeln!(w, "Created template for {} hook", hook_type.name())?;
+            }
         }
+        if m.is_present("git") {
+            sync::init_git(repo.path(), m.value_of("remote"))?;
+            writeln!(
+                w,
+                "Initialized git repository; run `devlog sync` to sync it"
+            )?;
+        }
+        Ok(())
     })
 }
 
+fn sync_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+    let _lock = lock_repo(w, &repo, m)?;
+    let summary = sync::sync(repo.path())?;
+    if let Some(msg) = summary.commit_message() {
+        writeln!(w, "Committed: {}", msg)?;
+    }
+    for (old, new) in summary.renumbered() {
+        writeln!(
+            w,
+            "Renumbered entry {} to {} to avoid a conflict with the remote",
+            old, new
+        )?;
+    }
+    if summary.remote() {
+        writeln!(w, "Synced with remote")?;
+    } else {
+        writeln!(
+            w,
+            "No remote configured, so changes were only committed locally"
+        )?;
+    }
+    Ok(())
+}
+
 fn edit_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
-    let config = Config::load();
+    let config = Config::try_load()?;
     let repo = LogRepository::new(config.repo_dir());
-    initialize_if_necessary(w, &repo, m).and_then(|_| match repo.latest()? {
-        Some(logpath) => editor::open(w, &config, logpath.path()),
-        None => {
+    initialize_if_necessary(w, &repo, m)?;
+    let logpath = match select_entry(&repo, m)? {
+        Some(logpath) => logpath,
+        None if selects_latest(m) => {
             // The user already confirmed initialization of the repo,
             // so if we don't find it we initialize it again to ensure it exists.
-            repo.init()
-                .and_then(|logpath| editor::open(w, &config, logpath.path()))
+            repo.init()?
         }
-    })
+        None => {
+            writeln!(w, "Could not find the selected devlog file")?;
+            exit(1)
+        }
+    };
+
+    if Some(&logpath) != repo.latest()?.as_ref() {
+        writeln!(
+            w,
+            "Warning: {:?} is not the most recent devlog file",
+            logpath.path()
+        )?;
+    }
+
+    let _lock = lock_repo(w, &repo, m)?;
+    let diff = editor::open(w, &config, logpath.path())?;
+
+    if !diff.is_empty() {
+        writeln!(w, "{}", diff)?;
+    }
+    Ok(())
 }
 
-fn rollover_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
-    let config = Config::load();
-    let repo = LogRepository::new(config.repo_dir());
-    abort_if_not_initialized(w, &repo).and_then(|()| {
-        match repo.latest()? {
-            Some(p) => {
-                if prompt_confirm(w, "Rollover incomplete tasks?", m)? {
-                    let (logpath, count) = rollover::rollover(w, &config, &p)?;
-                    write!(w, "Imported {} tasks into {:?}\n", count, logpath.path())?;
-                }
+fn show_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo).and_then(|_| match select_entry(&repo, m)? {
+        Some(logpath) => {
+            let mut f = File::open(logpath.path())?;
+            copy(&mut f, w)?;
+            Ok(())
+        }
+        None => match select_archived_entry(&repo, m)? {
+            Some(entry) => {
+                write!(w, "{}", entry.contents())?;
                 Ok(())
             }
             None => {
-                // This will only occur if something deleted the repo
-                // right after we checked that it was initialized (unlikely)
-                write!(w, "Could not find devlog file to rollover\n")?;
+                writeln!(w, "Could not find the selected devlog file")?;
                 exit(1)
             }
-        }
+        },
     })
 }
 
+fn rollover_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+    if !prompt_confirm(w, "Rollover incomplete tasks?", m)? {
+        return Ok(());
+    }
+    let lock = lock_repo(w, &repo, m)?;
+    // Find the latest entry only once we hold the lock,
+    // in case another command rolled over while we waited.
+    match repo.latest()? {
+        Some(p) => {
+            let (logpath, count) = rollover::rollover(w, &config, &p)?;
+            writeln!(w, "Imported {} tasks into {:?}", count, logpath.path())?;
+            Ok(())
+        }
+        None => {
+            // This will only occur if something deleted the repo
+            // right after we checked that it was initialized (unlikely)
+            drop(lock);
+            writeln!(w, "Could not find devlog file to rollover")?;
+            exit(1)
+        }
+    }
+}
+
 fn status_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
     let num_back = m
         .value_of("back")
@@ -195,18 +690,221 @@ fn status_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
         .parse::<usize>()
         .map_err(|_| Error::InvalidArg("back must be an integer"))?;
 
+    let config = Config::try_load()?;
     let display_mode = match m.value_of("show") {
         Some("all") => status::DisplayMode::ShowAll,
-        Some("todo") => status::DisplayMode::ShowOnly(TaskStatus::ToDo),
-        Some("started") => status::DisplayMode::ShowOnly(TaskStatus::Started),
-        Some("blocked") => status::DisplayMode::ShowOnly(TaskStatus::Blocked),
-        Some("done") => status::DisplayMode::ShowOnly(TaskStatus::Done),
-        _ => panic!("Invalid value for show arg"),
+        Some(name) => match config.parse_options().status_named(name) {
+            Some(s) => status::DisplayMode::ShowOnly(s),
+            None => return Err(Error::InvalidArg("show must be \"all\" or a status name")),
+        },
+        None => panic!("Show arg has a default"),
+    };
+    let sort = m
+        .value_of("sort")
+        .and_then(status::SortOrder::from_name)
+        .expect("Invalid value for sort arg");
+
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo).and_then(|_| {
+        if m.is_present("backlog") {
+            status::print_backlog(w, &repo, config.parse_options(), display_mode, sort)
+        } else {
+            status::print(
+                w,
+                &repo,
+                config.parse_options(),
+                num_back,
+                display_mode,
+                sort,
+            )
+        }
+    })
+}
+
+fn push_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    move_tasks_cmd(w, m, backlog::push, "to the backlog")
+}
+
+fn pull_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    move_tasks_cmd(w, m, backlog::pull, "from the backlog")
+}
+
+fn move_tasks_cmd<W, F>(w: &mut W, m: &ArgMatches, move_fn: F, direction: &str) -> Result<(), Error>
+where
+    W: Write,
+    F: Fn(&LogRepository, &ParseOptions, &str) -> Result<Vec<Task>, Error>,
+{
+    let query = m.value_of("query").expect("Query is required");
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+    let moved = {
+        let _lock = lock_repo(w, &repo, m)?;
+        move_fn(&repo, config.parse_options(), query)?
+    };
+    if moved.is_empty() {
+        writeln!(w, "No tasks match {:?}", query)?;
+        w.flush()?;
+        exit(1)
+    }
+    for t in &moved {
+        writeln!(w, "{}", t)?;
+    }
+    writeln!(w, "Moved {} tasks {}", moved.len(), direction)?;
+    Ok(())
+}
+
+fn start_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let query = m.value_of("query").expect("Query is required");
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+    let now = Local::now();
+    let (running, matches) = {
+        let _lock = lock_repo(w, &repo, m)?;
+        let running = timelog::running(repo.path())?;
+        let matches = timelog::start_task(&repo, config.parse_options(), query, now)?;
+        (running, matches)
     };
+    let t = single_match(w, query, matches)?;
+    if let Some(prev) = running {
+        writeln!(
+            w,
+            "Stopped {:?} after {}",
+            prev.content(),
+            timelog::format_duration(prev.duration(now))
+        )?;
+    }
+    writeln!(w, "Started {:?}", t.content())?;
+    Ok(())
+}
+
+/// Returns the only task that matched `query`, or exits with a message if zero or several matched.
+fn single_match<W: Write>(w: &mut W, query: &str, mut matches: Vec<Task>) -> Result<Task, Error> {
+    match matches.len() {
+        1 => Ok(matches.remove(0)),
+        0 => {
+            writeln!(w, "No tasks match {:?}", query)?;
+            w.flush()?;
+            exit(1)
+        }
+        _ => {
+            writeln!(w, "Multiple tasks match {:?}:", query)?;
+            for t in &matches {
+                writeln!(w, "{}", t)?;
+            }
+            w.flush()?;
+            exit(1)
+        }
+    }
+}
+
+fn focus_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let query = m.value_of("query").expect("Query is required");
+    let minutes = m
+        .value_of("minutes")
+        .unwrap()
+        .parse::<i64>()
+        .map_err(|_| Error::InvalidArg("minutes must be an integer"))?;
+    if minutes < 1 {
+        return Err(Error::InvalidArg("minutes must be >= 1"));
+    }
+
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+    let matches = {
+        let _lock = lock_repo(w, &repo, m)?;
+        timelog::mark_started(&repo, config.parse_options(), query)?
+    };
+    let t = single_match(w, query, matches)?;
+
+    writeln!(w, "Focusing on {:?} for {} minutes", t.content(), minutes)?;
+    let start = Local::now();
+    for remaining in (1..=minutes * 60).rev() {
+        write!(
+            w,
+            "\r{:02}:{:02} remaining ",
+            remaining / 60,
+            remaining % 60
+        )?;
+        w.flush()?;
+        sleep(Duration::from_secs(1));
+    }
+    writeln!(w, "\r\x07Time's up!         ")?;
+
+    let session = focus::Session::new(start, minutes, t.content());
+    let done = prompt_confirm(w, "Is the task done?", m)?;
+    let _lock = lock_repo(w, &repo, m)?;
+    focus::log_session(repo.path(), &session)?;
+    if !focus::finish_task(&repo, config.parse_options(), &session, done)? {
+        writeln!(
+            w,
+            "Could not find {:?} in the most recent devlog file",
+            t.content()
+        )?;
+    }
+    Ok(())
+}
 
-    let config = Config::load();
+fn stats_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let since = m
+        .value_of("since")
+        .and_then(|s| date::parse_past_date(s, date::today()))
+        .ok_or(Error::InvalidArg(
+            "since must be a date like \"2022-05-08\", \"yesterday\", or \"monday\"",
+        ))?;
+    let config = Config::try_load()?;
     let repo = LogRepository::new(config.repo_dir());
-    abort_if_not_initialized(w, &repo).and_then(|_| status::print(w, &repo, num_back, display_mode))
+    abort_if_not_initialized(w, &repo)?;
+    let sessions = focus::load_sessions(repo.path())?;
+    focus::print_stats(w, &sessions, since)
+}
+
+fn stop_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+    let _lock = lock_repo(w, &repo, m)?;
+    let now = Local::now();
+    match timelog::stop(repo.path(), now)? {
+        Some(interval) => writeln!(
+            w,
+            "Stopped {:?} after {}",
+            interval.content(),
+            timelog::format_duration(interval.duration(now))
+        )?,
+        None => writeln!(w, "No timer is running")?,
+    }
+    Ok(())
+}
+
+fn time_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let since = m
+        .value_of("since")
+        .and_then(|s| date::parse_past_date(s, date::today()))
+        .ok_or(Error::InvalidArg(
+            "since must be a date like \"2022-05-08\", \"yesterday\", or \"monday\"",
+        ))?;
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+    let intervals = timelog::load(repo.path())?;
+    timelog::print_report(w, &intervals, since, Local::now())
+}
+
+fn agenda_cmd<W: Write>(w: &mut W) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)
+        .and_then(|_| agenda::print(w, &repo, config.parse_options(), date::today()))
+}
+
+fn deferred_cmd<W: Write>(w: &mut W) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)
+        .and_then(|_| deferred::print(w, repo.path(), config.parse_options()))
 }
 
 fn parse_limit_arg(m: &ArgMatches) -> Result<usize, Error> {
@@ -224,7 +922,7 @@ fn parse_limit_arg(m: &ArgMatches) -> Result<usize, Error> {
 
 fn tail_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
     let limit = parse_limit_arg(m)?;
-    let config = Config::load();
+    let config = Config::try_load()?;
     let repo = LogRepository::new(config.repo_dir());
     abort_if_not_initialized(w, &repo).and_then(|_| {
         let paths = repo.tail(limit)?;
@@ -238,3 +936,333 @@ fn tail_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
         Ok(())
     })
 }
+
+fn tui_cmd<W: Write>(w: &mut W) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo).and_then(|_| {
+        let mut backend = tui::TerminalBackend::new(stdout())?;
+        tui::run(&mut backend, &config, &repo)
+    })
+}
+
+fn lint_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+    let mut paths = if m.is_present("all") {
+        repo.list()?
+    } else {
+        match select_entry(&repo, m)? {
+            Some(p) => vec![p],
+            None => {
+                writeln!(w, "Could not find the selected devlog file")?;
+                exit(1)
+            }
+        }
+    };
+    paths.sort();
+
+    let mut num_remaining = 0;
+    {
+        let _lock = if m.is_present("fix") {
+            Some(lock_repo(w, &repo, m)?)
+        } else {
+            None
+        };
+        for p in &paths {
+            let contents = read_to_string(p.path())?;
+            let problems = lint::check(&contents, config.parse_options());
+            if m.is_present("fix") && problems.iter().any(|p| p.suggestion().is_some()) {
+                let (fixed, _) = lint::fix(&contents, config.parse_options());
+                atomic::write(p.path(), &fixed)?;
+            }
+            for problem in problems {
+                let path = p.path().display();
+                match problem.suggestion() {
+                    Some(s) if m.is_present("fix") => {
+                        writeln!(w, "{}:{}: fixed: {}", path, problem.line_number(), s)?;
+                    }
+                    Some(s) => {
+                        num_remaining += 1;
+                        writeln!(
+                            w,
+                            "{}:{}: {}",
+                            path,
+                            problem.line_number(),
+                            problem.message()
+                        )?;
+                        writeln!(w, "    suggestion: {}", s)?;
+                    }
+                    None => {
+                        num_remaining += 1;
+                        writeln!(
+                            w,
+                            "{}:{}: {}",
+                            path,
+                            problem.line_number(),
+                            problem.message()
+                        )?;
+                    }
+                }
+            }
+        }
+    }
+
+    if num_remaining > 0 {
+        w.flush()?;
+        exit(1)
+    }
+    Ok(())
+}
+
+fn fsck_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    if !repo.path().is_dir() {
+        writeln!(w, "Repository at {:?} does not exist.", repo.path())?;
+        exit(1)
+    }
+
+    let mut num_remaining = 0;
+    {
+        let _lock = if m.is_present("repair") {
+            Some(lock_repo(w, &repo, m)?)
+        } else {
+            None
+        };
+        for problem in fsck::check(repo.path())? {
+            writeln!(w, "{}: {}", problem.path().display(), problem.message())?;
+            let repaired = if m.is_present("repair") {
+                fsck::repair(repo.path(), &problem)?
+            } else {
+                None
+            };
+            match repaired {
+                Some(action) => writeln!(w, "    repaired: {}", action)?,
+                None => num_remaining += 1,
+            }
+        }
+    }
+
+    if num_remaining > 0 {
+        w.flush()?;
+        exit(1)
+    }
+    Ok(())
+}
+
+fn renumber_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+    let start = match m.value_of("start") {
+        Some(s) => s
+            .parse::<usize>()
+            .map_err(|_| Error::InvalidArg("start must be an integer"))?,
+        None => archive::max_seq_num(repo.path())?.map_or(1, |max| max + 1),
+    };
+
+    let print_plan = |w: &mut W, renumberings: &[renumber::Renumbering]| -> Result<(), Error> {
+        for r in renumberings {
+            writeln!(w, "{} -> {}", r.from().seq_num(), r.to().seq_num())?;
+        }
+        Ok(())
+    };
+
+    if m.is_present("dry-run") {
+        let renumberings = renumber::plan(&repo, start)?;
+        print_plan(w, &renumberings)?;
+        writeln!(w, "Would renumber {} devlog files", renumberings.len())?;
+        return Ok(());
+    }
+
+    let _lock = lock_repo(w, &repo, m)?;
+    let renumberings = renumber::plan(&repo, start)?;
+    if renumberings.is_empty() {
+        writeln!(w, "Devlog files are already numbered from {}", start)?;
+        return Ok(());
+    }
+    print_plan(w, &renumberings)?;
+    let msg = format!("Renumber {} devlog files?", renumberings.len());
+    if prompt_confirm(w, &msg, m)? {
+        renumber::apply(&renumberings)?;
+        writeln!(w, "Renumbered {} devlog files", renumberings.len())?;
+    }
+    Ok(())
+}
+
+fn archive_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let cutoff = m
+        .value_of("before")
+        .and_then(|s| archive::Cutoff::parse(s, date::today()))
+        .ok_or(Error::InvalidArg(
+            "before must be a sequence number, or a date like \"2022-01-01\"",
+        ))?;
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo)?;
+
+    let _lock = lock_repo(w, &repo, m)?;
+    let paths = archive::select(&repo, cutoff)?;
+    if paths.is_empty() {
+        writeln!(w, "No devlog files to archive")?;
+        return Ok(());
+    }
+    let msg = format!(
+        "Archive {} devlog files, from {} to {}?",
+        paths.len(),
+        paths[0].seq_num(),
+        paths[paths.len() - 1].seq_num()
+    );
+    if prompt_confirm(w, &msg, m)? {
+        for (year, count) in archive::archive(&repo, &paths)? {
+            writeln!(
+                w,
+                "Archived {} devlog files into {:?}",
+                count,
+                archive::archive_path(repo.path(), year)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn lsp_cmd() -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let stdin = stdin();
+    lsp::run(&config, &mut stdin.lock(), &mut stdout())
+}
+
+fn serve_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo).and_then(|_| {
+        let addr = m.value_of("bind").expect("Bind address has a default");
+        let server = match serve::Server::bind(addr) {
+            Err(Error::IOError(err)) => {
+                writeln!(w, "Could not listen on {}: {}", addr, err)?;
+                w.flush()?;
+                exit(1)
+            }
+            result => result?,
+        };
+        if let Some(addr) = server.local_addr() {
+            writeln!(w, "Serving devlog API at http://{}", addr)?;
+            w.flush()?;
+        }
+        server.run(&config)
+    })
+}
+
+fn parse_hook_name_arg(m: &ArgMatches) -> Result<HookType, Error> {
+    m.value_of("name")
+        .and_then(HookType::from_name)
+        .ok_or(Error::InvalidArg("unknown hook name"))
+}
+
+fn hooks_cmd<W: Write>(w: &mut W, m: &ArgMatches) -> Result<(), Error> {
+    let config = Config::try_load()?;
+    let repo = LogRepository::new(config.repo_dir());
+    abort_if_not_initialized(w, &repo).and_then(|_| match m.subcommand() {
+        Some(("list", _)) => hooks_list_cmd(w, &repo),
+        Some(("enable", m)) => {
+            let hook_type = parse_hook_name_arg(m)?;
+            hook::enable_hook(repo.path(), &hook_type)?;
+            writeln!(w, "Enabled {} hook", hook_type.name()).map_err(From::from)
+        }
+        Some(("disable", m)) => {
+            let hook_type = parse_hook_name_arg(m)?;
+            hook::disable_hook(repo.path(), &hook_type)?;
+            writeln!(w, "Disabled {} hook", hook_type.name()).map_err(From::from)
+        }
+        Some(("run", m)) => {
+            let hook_type = parse_hook_name_arg(m)?;
+            let args: Vec<&str> = m.values_of("args").map(|v| v.collect()).unwrap_or_default();
+            hooks_run(w, &repo, &hook_type, &args)
+        }
+        Some(("test", m)) => {
+            let hook_type = parse_hook_name_arg(m)?;
+            let num_back = m
+                .value_of("back")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| Error::InvalidArg("back must be an integer"))?;
+            hooks_test(w, &repo, &hook_type, num_back)
+        }
+        _ => panic!("No hooks subcommand"),
+    })
+}
+
+fn hooks_list_cmd<W: Write>(w: &mut W, repo: &LogRepository) -> Result<(), Error> {
+    for hook_type in hook::ALL_HOOK_TYPES {
+        let state = match hook::hook_state(repo.path(), hook_type)? {
+            HookState::Missing => "missing",
+            HookState::Disabled => "disabled",
+            HookState::Enabled => "enabled",
+        };
+        writeln!(w, "{:<16} {}", hook_type.name(), state)?;
+    }
+    Ok(())
+}
+
+fn hooks_run<W: Write, S: AsRef<OsStr>>(
+    w: &mut W,
+    repo: &LogRepository,
+    hook_type: &HookType,
+    args: &[S],
+) -> Result<(), Error> {
+    match hook::hook_cmd(repo.path(), hook_type)? {
+        Some(mut cmd) => {
+            let status = cmd.args(args).status()?;
+            match status.code() {
+                Some(0) => Ok(()),
+                Some(code) => {
+                    writeln!(w, "{} hook exited with status {}", hook_type.name(), code)?;
+                    exit(code)
+                }
+                None => {
+                    writeln!(w, "Process terminated by signal")?;
+                    exit(1)
+                }
+            }
+        }
+        None => {
+            writeln!(
+                w,
+                "The {} hook is not enabled.\nPlease run `devlog hooks enable {}` to enable it.",
+                hook_type.name(),
+                hook_type.name()
+            )?;
+            exit(1)
+        }
+    }
+}
+
+fn hooks_test<W: Write>(
+    w: &mut W,
+    repo: &LogRepository,
+    hook_type: &HookType,
+    num_back: usize,
+) -> Result<(), Error> {
+    let p = match repo.nth_from_latest(num_back)? {
+        Some(p) => p,
+        None => {
+            writeln!(w, "Could not find devlog file to use as a sample entry")?;
+            exit(1)
+        }
+    };
+    let args = hook_type.sample_args(&p)?;
+    let arg_strs: Vec<String> = args
+        .iter()
+        .map(|a| a.to_string_lossy().to_string())
+        .collect();
+    writeln!(
+        w,
+        "Running {} hook with arguments: {}",
+        hook_type.name(),
+        arg_strs.join(" ")
+    )?;
+    hooks_run(w, repo, hook_type, &args)?;
+    writeln!(w, "{} hook succeeded", hook_type.name()).map_err(From::from)
+}
@@ -1,18 +1,25 @@
-//! Load configuration values from environment variables.
+//! Load configuration values from environment variables and the repository's configuration files.
 
+use crate::error::Error;
+use crate::file::ParseOptions;
+use crate::hook::{FileHooks, Hook, HookRegistry};
+use crate::settings::load_settings;
+use crate::status_config::load_statuses;
 use dirs;
 use std::env;
 use std::path::{Path, PathBuf};
 
-const DEVLOG_REPO_ENV_VAR: &'static str = "DEVLOG_REPO";
-const DEFAULT_HOME_DIR: &'static str = "devlogs";
-const DEVLOG_EDITOR_ENV_VAR: &'static str = "DEVLOG_EDITOR";
-const EDITOR_ENV_VAR: &'static str = "EDITOR";
-const DEFAULT_EDITOR: &'static str = "nano";
+const DEVLOG_REPO_ENV_VAR: &str = "DEVLOG_REPO";
+const DEFAULT_HOME_DIR: &str = "devlogs";
+const DEVLOG_EDITOR_ENV_VAR: &str = "DEVLOG_EDITOR";
+const EDITOR_ENV_VAR: &str = "EDITOR";
+const DEFAULT_EDITOR: &str = "nano";
 
 pub struct Config {
     repo_dir: PathBuf,
     editor_prog: String,
+    hooks: HookRegistry,
+    parse_options: ParseOptions,
 }
 
 impl Config {
@@ -22,11 +29,14 @@ impl Config {
         Config {
             repo_dir: repo_dir.to_path_buf(),
             editor_prog: editor_prog.to_string(),
+            hooks: default_hooks(repo_dir),
+            parse_options: ParseOptions::default(),
         }
     }
 
     /// Load configuration from environment variables,
     /// providing defaults if the environment variables are not defined.
+    /// This does not read the repository's configuration files; see `try_load`.
     pub fn load() -> Config {
         let repo_dir_str = env::var(DEVLOG_REPO_ENV_VAR)
             .ok()
@@ -38,23 +48,69 @@ impl Config {
             .or_else(|_| env::var(EDITOR_ENV_VAR))
             .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
 
+        let hooks = default_hooks(&repo_dir);
         Config {
             repo_dir,
             editor_prog,
+            hooks,
+            parse_options: ParseOptions::default(),
         }
     }
 
+    /// Load configuration like `load`, including the settings in the repository's
+    /// `settings.conf` and the custom statuses defined in its `statuses.conf`.
+    /// Returns an error if either file is invalid.
+    pub fn try_load() -> Result<Config, Error> {
+        let mut config = Config::load();
+        let settings = load_settings(&config.repo_dir)?;
+        let statuses = load_statuses(&config.repo_dir)?;
+        config.parse_options = ParseOptions::default()
+            .with_subtasks(settings.subtasks())
+            .with_statuses(statuses);
+        Ok(config)
+    }
+
     /// The directory of the user's devlog repository, which may or may not exist.
     /// Defaults to "$HOME/devlogs"
     pub fn repo_dir(&self) -> &Path {
         self.repo_dir.as_path()
     }
 
-    /// The text editor program for editing devlog entry files.
+    /// The text editor command for editing devlog entry files,
+    /// which may include arguments (e.g. "code --wait").
     /// Defaults to "nano".
     pub fn editor_prog(&self) -> &str {
         &self.editor_prog
     }
+
+    /// Hooks invoked by devlog operations.
+    /// By default, this contains only the hook files in the repository's `hooks` directory.
+    pub fn hooks(&self) -> &HookRegistry {
+        &self.hooks
+    }
+
+    /// Options for parsing devlog entry files.
+    /// Subtasks are enabled in the repository's `settings.conf`,
+    /// and custom statuses are defined in the repository's `statuses.conf`.
+    pub fn parse_options(&self) -> &ParseOptions {
+        &self.parse_options
+    }
+
+    /// Replaces the options for parsing devlog entry files.
+    pub fn set_parse_options(&mut self, options: ParseOptions) {
+        self.parse_options = options;
+    }
+
+    /// Registers an in-process hook, which is invoked after any previously registered hooks.
+    pub fn register_hook(&mut self, hook: Box<dyn Hook>) {
+        self.hooks.register(hook);
+    }
+}
+
+fn default_hooks(repo_dir: &Path) -> HookRegistry {
+    let mut hooks = HookRegistry::new();
+    hooks.register(Box::new(FileHooks::new(repo_dir)));
+    hooks
 }
 
 fn default_repo_dir() -> String {
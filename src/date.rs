@@ -0,0 +1,276 @@
+//! Parse dates entered by the user and determine when devlog entries were created.
+//!
+//! The date when each entry was created is recorded in the `dates.log` file
+//! of the devlog repository, because file times change whenever a file is replaced,
+//! for example by an editor that saves by renaming, or by cloning the repository.
+
+use crate::atomic;
+use crate::error::Error;
+use crate::path::LogPath;
+use crate::repository::LogRepository;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Weekday};
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+const ENTRY_DATES_FILE_NAME: &str = "dates.log";
+
+/// Returns the current date in the local timezone.
+pub fn today() -> NaiveDate {
+    Local::today().naive_local()
+}
+
+/// Parses a date relative to `today`.
+/// Accepts "today", "yesterday", "tomorrow", dates formatted like "2022-05-08",
+/// and weekday names like "friday" or "fri", which refer to the next such day
+/// on or after `today`.  Returns `None` if the string is not a valid date.
+pub fn parse_date(s: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let s = s.trim().to_lowercase();
+    match s.as_str() {
+        "today" => Some(today),
+        "yesterday" => Some(today - Duration::days(1)),
+        "tomorrow" => Some(today + Duration::days(1)),
+        _ => NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+            .ok()
+            .or_else(|| parse_weekday(&s).map(|wd| next_weekday(today, wd))),
+    }
+}
+
+/// Parses a date relative to `today`, like `parse_date`,
+/// except that weekday names refer to the most recent such day on or before `today`.
+/// This suits arguments that select a starting point, like "since monday".
+pub fn parse_past_date(s: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match parse_weekday(&s.trim().to_lowercase()) {
+        Some(wd) => Some(prev_weekday(today, wd)),
+        None => parse_date(s, today),
+    }
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    match s {
+        "monday" | "mon" => Some(Weekday::Mon),
+        "tuesday" | "tue" | "tues" => Some(Weekday::Tue),
+        "wednesday" | "wed" => Some(Weekday::Wed),
+        "thursday" | "thu" | "thurs" => Some(Weekday::Thu),
+        "friday" | "fri" => Some(Weekday::Fri),
+        "saturday" | "sat" => Some(Weekday::Sat),
+        "sunday" | "sun" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn next_weekday(from: NaiveDate, wd: Weekday) -> NaiveDate {
+    let days_ahead =
+        (7 + wd.num_days_from_monday() as i64 - from.weekday().num_days_from_monday() as i64) % 7;
+    from + Duration::days(days_ahead)
+}
+
+fn prev_weekday(from: NaiveDate, wd: Weekday) -> NaiveDate {
+    let days_back =
+        (7 + from.weekday().num_days_from_monday() as i64 - wd.num_days_from_monday() as i64) % 7;
+    from - Duration::days(days_back)
+}
+
+/// Returns the date when the devlog entry at `path` was created (see `EntryDates::get`).
+/// To look up the dates of many entries, load `EntryDates` once instead.
+pub fn entry_date(path: &Path) -> Result<NaiveDate, Error> {
+    let repo_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    EntryDates::load(repo_dir)?.get(path)
+}
+
+/// Returns the path to the file that records when each entry was created,
+/// which may or may not exist.
+pub fn entry_dates_path(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(ENTRY_DATES_FILE_NAME)
+}
+
+/// The recorded dates when the entries in a devlog repository were created.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryDates {
+    dates: BTreeMap<usize, NaiveDate>,
+}
+
+impl EntryDates {
+    /// Loads the recorded dates for the repository at `repo_dir`.
+    /// Malformed lines are ignored, and if an entry is recorded more than once,
+    /// the last date wins.
+    pub fn load(repo_dir: &Path) -> Result<EntryDates, Error> {
+        let p = entry_dates_path(repo_dir);
+        let mut dates = BTreeMap::new();
+        if p.is_file() {
+            for line in read_to_string(&p)?.lines() {
+                if let Some((seq, d)) = line.split_once('\t') {
+                    if let (Ok(seq), Ok(d)) =
+                        (seq.parse(), NaiveDate::parse_from_str(d, "%Y-%m-%d"))
+                    {
+                        dates.insert(seq, d);
+                    }
+                }
+            }
+        }
+        Ok(EntryDates { dates })
+    }
+
+    /// Returns the date when the devlog entry at `path` was created.
+    /// Entries without a recorded date, such as entries created by older versions of devlog,
+    /// fall back to the file's creation date, or to its last modification date
+    /// on filesystems that do not record creation times.
+    pub fn get(&self, path: &Path) -> Result<NaiveDate, Error> {
+        let recorded = LogPath::from_path(path.to_path_buf())
+            .and_then(|p| self.dates.get(&p.seq_num()).copied());
+        match recorded {
+            Some(d) => Ok(d),
+            None => file_date(path),
+        }
+    }
+
+    fn save(&self, repo_dir: &Path) -> Result<(), Error> {
+        let mut s = String::new();
+        for (seq, d) in &self.dates {
+            s.push_str(&format!("{:09}\t{}\n", seq, d.format("%Y-%m-%d")));
+        }
+        atomic::write(&entry_dates_path(repo_dir), &s)?;
+        Ok(())
+    }
+}
+
+fn file_date(path: &Path) -> Result<NaiveDate, Error> {
+    let metadata = path.metadata()?;
+    let t = metadata.created().or_else(|_| metadata.modified())?;
+    let dt: DateTime<Local> = t.into();
+    Ok(dt.naive_local().date())
+}
+
+/// Records that the entry with sequence number `seq_num` was created on `date`.
+/// Other entries in the repository without a recorded date are recorded
+/// from their file times first, so replacing their files later doesn't change their dates.
+pub fn record_entry_date(repo_dir: &Path, seq_num: usize, date: NaiveDate) -> Result<(), Error> {
+    let mut dates = EntryDates::load(repo_dir)?;
+    for p in LogRepository::new(repo_dir).list()? {
+        if let Entry::Vacant(e) = dates.dates.entry(p.seq_num()) {
+            e.insert(file_date(p.path())?);
+        }
+    }
+    dates.dates.insert(seq_num, date);
+    dates.save(repo_dir)
+}
+
+/// Moves recorded dates to the new sequence numbers of renumbered entries,
+/// given pairs of the old and new sequence numbers.
+pub fn renumber_entry_dates(repo_dir: &Path, renumbered: &[(usize, usize)]) -> Result<(), Error> {
+    let mut dates = EntryDates::load(repo_dir)?;
+    let moved: Vec<(usize, Option<NaiveDate>)> = renumbered
+        .iter()
+        .map(|&(old, new)| (new, dates.dates.remove(&old)))
+        .collect();
+    if moved.iter().all(|(_, d)| d.is_none()) {
+        return Ok(());
+    }
+    for (new, d) in moved {
+        match d {
+            Some(d) => dates.dates.insert(new, d),
+            None => dates.dates.remove(&new),
+        };
+    }
+    dates.save(repo_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    #[test]
+    fn test_parse_relative_dates() {
+        let today = date(2022, 5, 8);
+        assert_eq!(parse_date("today", today), Some(today));
+        assert_eq!(parse_date("Yesterday", today), Some(date(2022, 5, 7)));
+        assert_eq!(parse_date("tomorrow", today), Some(date(2022, 5, 9)));
+    }
+
+    #[test]
+    fn test_parse_iso_date() {
+        let today = date(2022, 5, 8);
+        assert_eq!(parse_date("2021-12-31", today), Some(date(2021, 12, 31)));
+        assert_eq!(parse_date("2021-13-31", today), None);
+    }
+
+    #[test]
+    fn test_parse_weekday() {
+        // 2022-05-08 is a Sunday
+        let today = date(2022, 5, 8);
+        assert_eq!(parse_date("sunday", today), Some(today));
+        assert_eq!(parse_date("mon", today), Some(date(2022, 5, 9)));
+        assert_eq!(parse_date("Friday", today), Some(date(2022, 5, 13)));
+        assert_eq!(parse_date("sat", today), Some(date(2022, 5, 14)));
+    }
+
+    #[test]
+    fn test_parse_past_date() {
+        // 2022-05-08 is a Sunday
+        let today = date(2022, 5, 8);
+        assert_eq!(parse_past_date("sunday", today), Some(today));
+        assert_eq!(parse_past_date("mon", today), Some(date(2022, 5, 2)));
+        assert_eq!(parse_past_date("Friday", today), Some(date(2022, 5, 6)));
+        assert_eq!(parse_past_date("yesterday", today), Some(date(2022, 5, 7)));
+    }
+
+    #[test]
+    fn test_record_entry_dates() {
+        let dir = tempfile::tempdir().unwrap();
+        let p1 = LogPath::new(dir.path(), 1);
+        let p2 = LogPath::new(dir.path(), 2);
+        std::fs::write(p1.path(), "* Foo\n").unwrap();
+        std::fs::write(p2.path(), "* Foo\n").unwrap();
+        let created = file_date(p1.path()).unwrap();
+        assert_eq!(
+            entry_date(p2.path()).unwrap(),
+            file_date(p2.path()).unwrap()
+        );
+
+        record_entry_date(dir.path(), 2, date(2022, 5, 8)).unwrap();
+        atomic::write(p2.path(), "+ Foo\n").unwrap();
+        assert_eq!(entry_date(p2.path()).unwrap(), date(2022, 5, 8));
+        let dates = EntryDates::load(dir.path()).unwrap();
+        assert_eq!(dates.get(p1.path()).unwrap(), created);
+        assert_eq!(
+            read_to_string(entry_dates_path(dir.path())).unwrap(),
+            format!(
+                "000000001\t{}\n000000002\t2022-05-08\n",
+                created.format("%Y-%m-%d")
+            )
+        );
+    }
+
+    #[test]
+    fn test_renumber_entry_dates() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            entry_dates_path(dir.path()),
+            "000000001\t2022-05-01\nnot a date\n000000003\t2022-05-03\n000000004\t2022-05-04\n",
+        )
+        .unwrap();
+        renumber_entry_dates(dir.path(), &[(3, 2), (4, 3)]).unwrap();
+        let dates = EntryDates::load(dir.path()).unwrap();
+        let recorded: Vec<(usize, NaiveDate)> =
+            dates.dates.iter().map(|(&seq, &d)| (seq, d)).collect();
+        assert_eq!(
+            recorded,
+            vec![
+                (1, date(2022, 5, 1)),
+                (2, date(2022, 5, 3)),
+                (3, date(2022, 5, 4))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let today = date(2022, 5, 8);
+        assert_eq!(parse_date("someday", today), None);
+        assert_eq!(parse_date("", today), None);
+    }
+}
@@ -0,0 +1,139 @@
+//! Deferred tasks are incomplete tasks that are not actionable until a later date,
+//! written with a "defer:" attribute like "defer:2022-05-20" or "defer:monday".
+//!
+//! Instead of copying deferred tasks to every new entry, rollover parks them
+//! in the `deferred.devlog` file in the devlog repository.  The first rollover
+//! on or after a task's defer date moves the task back into the new entry.
+//! Only top-level tasks can be deferred; subtasks are parked along with their parent.
+
+use crate::atomic;
+use crate::date;
+use crate::error::Error;
+use crate::file::{LogFile, ParseOptions};
+use crate::task::Task;
+use chrono::NaiveDate;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const DEFERRED_FILE_NAME: &str = "deferred.devlog";
+
+/// Returns the path to the file of parked tasks, which may or may not exist.
+pub fn deferred_path(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(DEFERRED_FILE_NAME)
+}
+
+/// Loads all parked tasks, including subtasks, in the order they were parked.
+pub fn load(repo_dir: &Path, options: &ParseOptions) -> Result<Vec<Task>, Error> {
+    let p = deferred_path(repo_dir);
+    if p.is_file() {
+        Ok(LogFile::load_with(&p, options)?.tasks().to_vec())
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Loads the parked tasks whose defer date is on or before `today`,
+/// which the next rollover will move into the new entry.
+pub fn load_ready(
+    repo_dir: &Path,
+    options: &ParseOptions,
+    today: NaiveDate,
+) -> Result<Vec<Task>, Error> {
+    let (ready, _) = split_ready(&load(repo_dir, options)?, today);
+    Ok(ready)
+}
+
+/// Splits parked tasks into those that are ready on `today` and those that are still waiting.
+/// Subtasks stay with their parent.
+pub fn split_ready(tasks: &[Task], today: NaiveDate) -> (Vec<Task>, Vec<Task>) {
+    let mut ready = Vec::new();
+    let mut waiting = Vec::new();
+    let mut is_ready = false;
+    for t in tasks {
+        if t.depth() == 0 {
+            // Parked tasks always have absolute dates, so `today` only resolves invalid ones.
+            is_ready = t.defer_date(today).is_none_or(|d| d <= today);
+        }
+        if is_ready {
+            ready.push(t.clone());
+        } else {
+            waiting.push(t.clone());
+        }
+    }
+    (ready, waiting)
+}
+
+/// Replaces the parked tasks with `tasks`.
+pub fn save(repo_dir: &Path, tasks: &[Task]) -> Result<(), Error> {
+    let mut s = String::new();
+    for t in tasks {
+        s.push_str(&t.format_with_body());
+        s.push('\n');
+    }
+    atomic::write(&deferred_path(repo_dir), &s)?;
+    Ok(())
+}
+
+/// Prints the parked top-level tasks, ordered by the date when they will return.
+pub fn print<W: Write>(w: &mut W, repo_dir: &Path, options: &ParseOptions) -> Result<(), Error> {
+    let today = date::today();
+    let mut tasks: Vec<(NaiveDate, Task)> = load(repo_dir, options)?
+        .into_iter()
+        .filter(|t| t.depth() == 0)
+        .map(|t| (t.defer_date(today).unwrap_or(today), t))
+        .collect();
+    tasks.sort_by_key(|(d, _)| *d);
+    for (d, t) in tasks {
+        writeln!(w, "{}  {}", d.format("%a %Y-%m-%d"), t)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::TaskStatus;
+    use tempfile::tempdir;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    #[test]
+    fn test_split_ready() {
+        let tasks = vec![
+            Task::new(TaskStatus::ToDo, "Foo defer:2022-05-01"),
+            Task::new(TaskStatus::ToDo, "Foo 1").with_depth(1),
+            Task::new(TaskStatus::ToDo, "Bar defer:2022-05-20"),
+            Task::new(TaskStatus::ToDo, "Baz defer:2022-05-08"),
+        ];
+        let (ready, waiting) = split_ready(&tasks, date(2022, 5, 8));
+        assert_eq!(
+            ready,
+            vec![tasks[0].clone(), tasks[1].clone(), tasks[3].clone()]
+        );
+        assert_eq!(waiting, vec![tasks[2].clone()]);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempdir().unwrap();
+        let options = ParseOptions::default();
+        assert!(load(dir.path(), &options).unwrap().is_empty());
+
+        let tasks = vec![
+            Task::new(TaskStatus::ToDo, "Foo defer:2022-05-20")
+                .with_body(vec!["notes".to_string()]),
+            Task::new(TaskStatus::Blocked, "Bar defer:2022-05-21"),
+        ];
+        save(dir.path(), &tasks).unwrap();
+        assert_eq!(load(dir.path(), &options).unwrap(), tasks);
+
+        let mut buf = Vec::new();
+        print(&mut buf, dir.path(), &options).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "Fri 2022-05-20  * Foo defer:2022-05-20\nSat 2022-05-21  - Bar defer:2022-05-21\n"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Compare the tasks in two versions of a devlog entry,
+//! for example before and after the user edits it.
+
+use crate::task::{Task, TaskStatus};
+use std::fmt;
+
+/// The differences between two versions of a devlog entry's tasks.
+/// Tasks are matched by their content, so editing a task's content
+/// appears as removing the old task and adding a new one.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EntryDiff {
+    added: Vec<Task>,
+    removed: Vec<Task>,
+    changed: Vec<(Task, Task)>,
+}
+
+impl EntryDiff {
+    /// Compares the tasks `before` an operation with the tasks `after` the operation.
+    pub fn between(before: &[Task], after: &[Task]) -> EntryDiff {
+        let mut unmatched: Vec<Option<&Task>> = before.iter().map(Some).collect();
+        let mut diff = EntryDiff::default();
+
+        for t in after {
+            let matched = unmatched
+                .iter_mut()
+                .find(|prev| prev.is_some_and(|p| p.content() == t.content()))
+                .and_then(|prev| prev.take());
+            match matched {
+                Some(prev) if prev.status() != t.status() => {
+                    diff.changed.push((prev.clone(), t.clone()))
+                }
+                Some(_) => {}
+                None => diff.added.push(t.clone()),
+            }
+        }
+
+        diff.removed = unmatched.into_iter().flatten().cloned().collect();
+        diff
+    }
+
+    /// Tasks that exist only in the newer version.
+    pub fn added(&self) -> &[Task] {
+        &self.added
+    }
+
+    /// Tasks that exist only in the older version.
+    pub fn removed(&self) -> &[Task] {
+        &self.removed
+    }
+
+    /// Tasks whose status changed, as pairs of (old task, new task).
+    pub fn changed(&self) -> &[(Task, Task)] {
+        &self.changed
+    }
+
+    /// Returns true if no tasks were added, removed, or changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Returns the number of tasks that changed to the specified status.
+    pub fn num_changed_to(&self, status: TaskStatus) -> usize {
+        self.changed
+            .iter()
+            .filter(|(_, t)| t.status() == status)
+            .count()
+    }
+
+    /// Returns a short, human-readable summary of the changes,
+    /// such as "2 tasks completed, 1 added, 1 now blocked".
+    pub fn summary(&self) -> String {
+        let mut counts = vec![
+            (
+                self.num_changed_to(TaskStatus::Done),
+                "completed".to_string(),
+            ),
+            (self.added.len(), "added".to_string()),
+            (self.removed.len(), "removed".to_string()),
+            (
+                self.num_changed_to(TaskStatus::Started),
+                "started".to_string(),
+            ),
+            (
+                self.num_changed_to(TaskStatus::Blocked),
+                "now blocked".to_string(),
+            ),
+            (
+                self.num_changed_to(TaskStatus::ToDo),
+                "reopened".to_string(),
+            ),
+        ];
+
+        let mut custom: Vec<TaskStatus> = Vec::new();
+        for (_, t) in &self.changed {
+            if let TaskStatus::Custom(_) = t.status() {
+                if !custom.contains(&t.status()) {
+                    custom.push(t.status());
+                }
+            }
+        }
+        for status in custom {
+            let desc = format!("now {}", status.display_name().to_lowercase());
+            counts.push((self.num_changed_to(status), desc));
+        }
+
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(n, _)| *n > 0)
+            .enumerate()
+            .map(|(i, (n, desc))| {
+                if i == 0 {
+                    let noun = if *n == 1 { "task" } else { "tasks" };
+                    format!("{} {} {}", n, noun, desc)
+                } else {
+                    format!("{} {}", n, desc)
+                }
+            })
+            .collect();
+
+        if parts.is_empty() {
+            "No tasks changed".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+impl fmt::Display for EntryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.summary())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_changes() {
+        let tasks = vec![
+            Task::new(TaskStatus::ToDo, "Foo"),
+            Task::new(TaskStatus::Done, "Bar"),
+        ];
+        let diff = EntryDiff::between(&tasks, &tasks);
+        assert!(diff.is_empty());
+        assert_eq!(diff.summary(), "No tasks changed");
+    }
+
+    #[test]
+    fn test_added_and_removed() {
+        let before = vec![Task::new(TaskStatus::ToDo, "Foo")];
+        let after = vec![Task::new(TaskStatus::ToDo, "Bar")];
+        let diff = EntryDiff::between(&before, &after);
+        assert_eq!(diff.added(), &[Task::new(TaskStatus::ToDo, "Bar")]);
+        assert_eq!(diff.removed(), &[Task::new(TaskStatus::ToDo, "Foo")]);
+        assert!(diff.changed().is_empty());
+        assert_eq!(diff.summary(), "1 task added, 1 removed");
+    }
+
+    #[test]
+    fn test_status_changes() {
+        let before = vec![
+            Task::new(TaskStatus::ToDo, "Foo"),
+            Task::new(TaskStatus::Started, "Bar"),
+            Task::new(TaskStatus::ToDo, "Baz"),
+        ];
+        let after = vec![
+            Task::new(TaskStatus::Done, "Foo"),
+            Task::new(TaskStatus::Done, "Bar"),
+            Task::new(TaskStatus::Blocked, "Baz"),
+            Task::new(TaskStatus::ToDo, "Boo"),
+        ];
+        let diff = EntryDiff::between(&before, &after);
+        assert_eq!(
+            diff.changed(),
+            &[
+                (
+                    Task::new(TaskStatus::ToDo, "Foo"),
+                    Task::new(TaskStatus::Done, "Foo")
+                ),
+                (
+                    Task::new(TaskStatus::Started, "Bar"),
+                    Task::new(TaskStatus::Done, "Bar")
+                ),
+                (
+                    Task::new(TaskStatus::ToDo, "Baz"),
+                    Task::new(TaskStatus::Blocked, "Baz")
+                ),
+            ]
+        );
+        assert_eq!(diff.summary(), "2 tasks completed, 1 added, 1 now blocked");
+        assert_eq!(format!("{}", diff), diff.summary());
+    }
+
+    #[test]
+    fn test_duplicate_content() {
+        let before = vec![
+            Task::new(TaskStatus::ToDo, "Foo"),
+            Task::new(TaskStatus::ToDo, "Foo"),
+        ];
+        let after = vec![
+            Task::new(TaskStatus::Done, "Foo"),
+            Task::new(TaskStatus::ToDo, "Foo"),
+            Task::new(TaskStatus::ToDo, "Foo"),
+        ];
+        let diff = EntryDiff::between(&before, &after);
+        assert_eq!(diff.num_changed_to(TaskStatus::Done), 1);
+        assert_eq!(diff.added(), &[Task::new(TaskStatus::ToDo, "Foo")]);
+        assert!(diff.removed().is_empty());
+    }
+
+    #[test]
+    fn test_custom_status_changes() {
+        let review =
+            crate::task::CustomStatus::new("review", '%', "In Review", false, true).into_status();
+        let before = vec![Task::new(TaskStatus::Started, "Foo")];
+        let after = vec![Task::new(review, "Foo")];
+        let diff = EntryDiff::between(&before, &after);
+        assert_eq!(diff.summary(), "1 task now in review");
+    }
+}
@@ -1,38 +1,354 @@
 //! Open a file using a text editor program (e.g. vim or nano)
+//!
+//! The editor is configured as a command line, which is split into words like a shell would
+//! (so "code --wait" works).  For editors that devlog recognizes, the file is opened at the
+//! first incomplete task, or at the end of the file if there are no incomplete tasks.
+//! Other editors can use the `{file}` and `{line}` placeholders, for example "myedit {file}:{line}".
 
 use crate::config::Config;
+use crate::diff::EntryDiff;
 use crate::error::Error;
-use crate::hook::{execute_hook, HookType};
+use crate::file::{LogFile, ParseOptions};
+use crate::hook::Hook;
+use crate::task::TaskStatus;
+use std::ffi::OsString;
+use std::fs::read_to_string;
 use std::io::Write;
 use std::path::Path;
 use std::process::Command;
 
-/// Opens the specified file in a text editor program.
-/// If available, the before-edit and after-edit hooks are invoked.
-pub fn open<W: Write>(w: &mut W, config: &Config, path: &Path) -> Result<(), Error> {
-    execute_hook(w, config, &HookType::BeforeEdit, &[path.as_os_str()])?;
+const FILE_PLACEHOLDER: &str = "{file}";
+const LINE_PLACEHOLDER: &str = "{line}";
+
+/// Opens the specified file in a text editor program,
+/// and returns the changes the user made to the file's tasks.
+/// The before-edit and after-edit hooks registered in `config` are invoked,
+/// followed by the tasks-changed hooks if any tasks changed.
+pub fn open<W: Write>(w: &mut W, config: &Config, path: &Path) -> Result<EntryDiff, Error> {
+    config.hooks().before_edit(w, path)?;
+    let before = LogFile::load_with(path, config.parse_options())?;
     open_in_editor(w, config, path)?;
-    execute_hook(w, config, &HookType::AfterEdit, &[path.as_os_str()])?;
-    Ok(())
+    let after = LogFile::load_with(path, config.parse_options())?;
+    config.hooks().after_edit(w, path)?;
+
+    let diff = EntryDiff::between(before.tasks(), after.tasks());
+    if !diff.is_empty() {
+        config.hooks().tasks_changed(w, path, &diff)?;
+    }
+    Ok(diff)
 }
 
 fn open_in_editor<W: Write>(w: &mut W, config: &Config, path: &Path) -> Result<(), Error> {
-    let prog = config.editor_prog();
-    let status = Command::new(prog).arg(&path).status()?;
+    let line = initial_line(path, config.parse_options())?;
+    let args = editor_args(config.editor_prog(), path, line)?;
+    let status = Command::new(&args[0]).args(&args[1..]).status()?;
 
     if status.success() {
         Ok(())
     } else {
         match status.code() {
-            Some(code) => write!(
+            Some(code) => writeln!(
                 w,
-                "Command `{} {}` exited with status {}\n",
-                prog,
-                path.to_string_lossy(),
+                "Command `{}` exited with status {}",
+                args.iter()
+                    .map(|a| a.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" "),
                 code
             )
             .map_err(From::from),
-            None => write!(w, "Process terminated by signal\n").map_err(From::from),
+            None => writeln!(w, "Process terminated by signal").map_err(From::from),
+        }
+    }
+}
+
+/// Returns the line number (starting from one) where the editor should place the cursor:
+/// the first incomplete task that isn't blocked, parsed with `options`,
+/// or the last line if there is no such task.
+pub fn initial_line(path: &Path, options: &ParseOptions) -> Result<usize, Error> {
+    let contents = read_to_string(path)?;
+    let f = LogFile::parse_with(&contents, options);
+    let first = f
+        .tasks()
+        .iter()
+        .position(|t| !t.status().is_complete() && t.status() != TaskStatus::Blocked);
+    match first {
+        Some(i) => Ok(f.line_number(i)),
+        None => Ok(contents.lines().count().max(1)),
+    }
+}
+
+/// Builds the program and arguments used to open `path` at `line`
+/// from the configured editor command.  The first element is the program.
+pub fn editor_args(editor: &str, path: &Path, line: usize) -> Result<Vec<OsString>, Error> {
+    let words = split_command(editor)?;
+    if words.is_empty() {
+        return Err(Error::InvalidArg("editor command must not be empty"));
+    }
+
+    let mut args: Vec<OsString> = Vec::with_capacity(words.len() + 2);
+    if words
+        .iter()
+        .any(|w| w.contains(FILE_PLACEHOLDER) || w.contains(LINE_PLACEHOLDER))
+    {
+        let mut has_file = false;
+        for word in &words {
+            has_file |= word.contains(FILE_PLACEHOLDER);
+            args.push(substitute_placeholders(word, path, line));
+        }
+        if !has_file {
+            args.push(path.as_os_str().to_os_string());
+        }
+        return Ok(args);
+    }
+
+    args.extend(words.iter().map(OsString::from));
+    let prog_name = Path::new(&words[0])
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("");
+    match prog_name {
+        "vi" | "vim" | "nvim" | "gvim" | "nano" | "emacs" | "emacsclient" | "kak" => {
+            args.push(OsString::from(format!("+{}", line)));
+            args.push(path.as_os_str().to_os_string());
+        }
+        "code" | "code-insiders" | "codium" => {
+            args.push(OsString::from("--goto"));
+            args.push(path_with_line(path, line));
+        }
+        "hx" | "helix" | "subl" => {
+            args.push(path_with_line(path, line));
+        }
+        _ => {
+            args.push(path.as_os_str().to_os_string());
         }
     }
+    Ok(args)
+}
+
+fn path_with_line(path: &Path, line: usize) -> OsString {
+    let mut s = path.as_os_str().to_os_string();
+    s.push(format!(":{}", line));
+    s
+}
+
+fn substitute_placeholders(word: &str, path: &Path, line: usize) -> OsString {
+    let mut result = OsString::new();
+    for (i, part) in word.split(FILE_PLACEHOLDER).enumerate() {
+        if i > 0 {
+            result.push(path.as_os_str());
+        }
+        result.push(part.replace(LINE_PLACEHOLDER, &line.to_string()));
+    }
+    result
+}
+
+/// Splits a command line into words, using the same quoting rules as a POSIX shell:
+/// single quotes preserve everything literally, double quotes allow backslash escapes,
+/// and an unquoted backslash escapes the next character.
+pub fn split_command(s: &str) -> Result<Vec<String>, Error> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(Error::InvalidArg("unterminated quote in command")),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(Error::InvalidArg("unterminated quote in command")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(Error::InvalidArg("unterminated quote in command")),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::ALL_STATUSES;
+    use crate::task::{CustomStatus, Task};
+    use std::fs::OpenOptions;
+    use tempfile::tempdir;
+
+    fn args_to_strings(args: Vec<OsString>) -> Vec<String> {
+        args.into_iter()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect()
+    }
+
+    fn check_args(editor: &str, expected: &[&str]) {
+        let args = editor_args(editor, Path::new("/repo/000000001.devlog"), 7).unwrap();
+        assert_eq!(args_to_strings(args), expected);
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(split_command("nano").unwrap(), vec!["nano"]);
+        assert_eq!(
+            split_command("  code   --wait ").unwrap(),
+            vec!["code", "--wait"]
+        );
+        assert_eq!(
+            split_command("'/opt/my editor/bin/ed' -x").unwrap(),
+            vec!["/opt/my editor/bin/ed", "-x"]
+        );
+        assert_eq!(
+            split_command(r#"ed "a \"b\" c" d\ e ''"#).unwrap(),
+            vec!["ed", "a \"b\" c", "d e", ""]
+        );
+        assert!(split_command("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_split_command_unterminated_quote() {
+        assert!(split_command("vim 'foo").is_err());
+        assert!(split_command("vim \"foo").is_err());
+    }
+
+    #[test]
+    fn test_editor_args_known_editors() {
+        check_args("vim", &["vim", "+7", "/repo/000000001.devlog"]);
+        check_args(
+            "/usr/bin/nvim -u NONE",
+            &[
+                "/usr/bin/nvim",
+                "-u",
+                "NONE",
+                "+7",
+                "/repo/000000001.devlog",
+            ],
+        );
+        check_args("nano", &["nano", "+7", "/repo/000000001.devlog"]);
+        check_args(
+            "emacs -nw",
+            &["emacs", "-nw", "+7", "/repo/000000001.devlog"],
+        );
+        check_args(
+            "code --wait",
+            &["code", "--wait", "--goto", "/repo/000000001.devlog:7"],
+        );
+        check_args("hx", &["hx", "/repo/000000001.devlog:7"]);
+    }
+
+    #[test]
+    fn test_editor_args_unknown_editor() {
+        check_args("ed", &["ed", "/repo/000000001.devlog"]);
+    }
+
+    #[test]
+    fn test_editor_args_placeholders() {
+        check_args(
+            "myedit {file}:{line}",
+            &["myedit", "/repo/000000001.devlog:7"],
+        );
+        check_args(
+            "myedit -l {line}",
+            &["myedit", "-l", "7", "/repo/000000001.devlog"],
+        );
+    }
+
+    #[test]
+    fn test_editor_args_empty() {
+        assert!(editor_args("  ", Path::new("/foo"), 1).is_err());
+    }
+
+    fn write_file(contents: &str) -> (tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempdir().unwrap();
+        let p = dir.path().join("000000001.devlog");
+        let mut f = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&p)
+            .unwrap();
+        write!(f, "{}", contents).unwrap();
+        (dir, p)
+    }
+
+    #[test]
+    fn test_initial_line_first_incomplete_task() {
+        let (_dir, p) = write_file("Notes\n+ Done\n- Blocked\n^ Started\n* Todo\n");
+        assert_eq!(initial_line(&p, &ParseOptions::default()).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_initial_line_no_incomplete_tasks() {
+        let (_dir, p) = write_file("Notes\n+ Done\nMore notes\n");
+        assert_eq!(initial_line(&p, &ParseOptions::default()).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_initial_line_with_options() {
+        let review = CustomStatus::new("review", '%', "In Review", false, true).into_status();
+        let mut statuses = ALL_STATUSES.to_vec();
+        statuses.push(review);
+        let options = ParseOptions::default()
+            .with_statuses(statuses)
+            .with_subtasks(true);
+        let (_dir, p) = write_file("+ Done\n    * Subtask\n% Review\n");
+        assert_eq!(initial_line(&p, &options).unwrap(), 2);
+        let (_dir, p) = write_file("+ Done\n% Review\n* Todo\n");
+        assert_eq!(initial_line(&p, &options).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_initial_line_empty_file() {
+        let (_dir, p) = write_file("");
+        assert_eq!(initial_line(&p, &ParseOptions::default()).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_open_returns_diff() {
+        let (dir, p) = write_file("* Foo\n* Bar\n");
+        let editor = r#"sh -c 'sed -i "s/^\* Foo/+ Foo/" "$0" && echo "- Baz" >> "$0"'"#;
+        let config = Config::new(dir.path(), editor);
+        let mut out = Vec::new();
+        let diff = open(&mut out, &config, &p).unwrap();
+        assert_eq!(diff.num_changed_to(TaskStatus::Done), 1);
+        assert_eq!(diff.added(), &[Task::new(TaskStatus::Blocked, "Baz")]);
+        assert_eq!(diff.summary(), "1 task completed, 1 added");
+    }
 }
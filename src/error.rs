@@ -1,6 +1,7 @@
 //! Error type returned by the devlog library.
 
 use std::io::Error as IOError;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub enum Error {
@@ -11,6 +12,18 @@ pub enum Error {
     /// so no more can be created.
     LogFileLimitExceeded,
 
+    /// A hook vetoed the operation, for the specified reason.
+    HookVetoed(String),
+
+    /// A configuration file in the repository is invalid, for the specified reason.
+    InvalidConfig(String),
+
+    /// A git command failed while syncing the repository, with git's error output.
+    GitFailed(String),
+
+    /// Another command holds the lock on the repository, using the specified lock file.
+    RepositoryLocked(PathBuf),
+
     /// Wraps `io::Error`
     IOError(IOError),
 }
@@ -1,39 +1,377 @@
 //! Load and parse a devlog entry file.
 
-use crate::task::Task;
-use std::fs::File;
+use crate::atomic;
+use crate::status::ALL_STATUSES;
+use crate::task::{Task, TaskStatus, SUBTASK_INDENT};
+use std::fs::read_to_string;
 use std::io::Error as IOError;
-use std::io::{BufRead, BufReader};
+use std::ops::Range;
 use std::path::Path;
 
+/// Options that control how devlog entry files are parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    subtasks: bool,
+    statuses: Vec<TaskStatus>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            subtasks: false,
+            statuses: ALL_STATUSES.to_vec(),
+        }
+    }
+}
+
+impl ParseOptions {
+    /// The task statuses that may appear in devlog entries,
+    /// in the order they appear in the status report.
+    /// Defaults to `status::ALL_STATUSES`.
+    pub fn statuses(&self) -> &[TaskStatus] {
+        &self.statuses
+    }
+
+    /// Replaces the task statuses that may appear in devlog entries.
+    pub fn with_statuses(mut self, statuses: Vec<TaskStatus>) -> ParseOptions {
+        self.statuses = statuses;
+        self
+    }
+
+    /// Finds a task status by its identifier (see `TaskStatus::name`),
+    /// including custom statuses.
+    pub fn status_named(&self, name: &str) -> Option<TaskStatus> {
+        self.statuses.iter().find(|s| s.name() == name).cloned()
+    }
+
+    /// Finds a task status by its marker character (see `TaskStatus::marker`),
+    /// including custom statuses.
+    pub fn status_marked(&self, marker: char) -> Option<TaskStatus> {
+        self.statuses.iter().find(|s| s.marker() == marker).cloned()
+    }
+
+    /// Whether indented tasks are parsed as subtasks of the preceding, less-indented task.
+    /// This is disabled by default, because older entries may use indented task markers in notes.
+    pub fn subtasks(&self) -> bool {
+        self.subtasks
+    }
+
+    /// Enables or disables parsing indented tasks as subtasks.
+    pub fn with_subtasks(mut self, enabled: bool) -> ParseOptions {
+        self.subtasks = enabled;
+        self
+    }
+}
+
+/// Removes the indentation common to all `lines`.
+/// Lines indented with different whitespace characters only share their common prefix.
+fn unindent(lines: &[&str]) -> Vec<String> {
+    let indent = lines
+        .iter()
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .reduce(common_prefix)
+        .unwrap_or("");
+    lines
+        .iter()
+        .map(|l| l[indent.len()..].trim_end().to_string())
+        .collect()
+}
+
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let len = a
+        .char_indices()
+        .zip(b.chars())
+        .take_while(|((_, x), y)| x == y)
+        .last()
+        .map_or(0, |((i, c), _)| i + c.len_utf8());
+    &a[..len]
+}
+
 /// Represents a devlog entry file.
+/// Besides the tasks, a `LogFile` keeps every line of the entry,
+/// so tasks can be modified and saved without losing the user's notes.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LogFile {
     tasks: Vec<Task>,
+    lines: Vec<String>,
+    task_lines: Vec<usize>,
+    options: ParseOptions,
 }
 
 impl LogFile {
     /// Loads and parses the devlog entry file at `path`
     pub fn load(path: &Path) -> Result<LogFile, IOError> {
-        let f = File::open(path)?;
-        let r = BufReader::new(f);
-        let mut tasks = Vec::new();
-        for line in r.lines() {
-            if let Some(task) = Task::from_string(&line?) {
-                tasks.push(task)
+        LogFile::load_with(path, &ParseOptions::default())
+    }
+
+    /// Loads and parses the devlog entry file at `path` using the specified options.
+    pub fn load_with(path: &Path, options: &ParseOptions) -> Result<LogFile, IOError> {
+        let s = read_to_string(path)?;
+        Ok(LogFile::parse_with(&s, options))
+    }
+
+    /// Parses the contents of a devlog entry file.
+    pub fn parse(s: &str) -> LogFile {
+        LogFile::parse_with(s, &ParseOptions::default())
+    }
+
+    /// Parses the contents of a devlog entry file using the specified options.
+    pub fn parse_with(s: &str, options: &ParseOptions) -> LogFile {
+        let mut f = LogFile {
+            tasks: Vec::new(),
+            lines: Vec::new(),
+            task_lines: Vec::new(),
+            options: options.clone(),
+        };
+
+        // Indentation and depth of the tasks that may be parents of the next line.
+        let mut parents: Vec<(usize, usize)> = Vec::new();
+        // Index and indentation of the task that the next line may continue.
+        let mut continued: Option<(usize, usize)> = None;
+        let mut bodies: Vec<Vec<&str>> = Vec::new();
+        for line in s.lines() {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            let task = if indent == 0 {
+                if !line.is_empty() {
+                    parents.clear();
+                }
+                Task::from_string_with(line, &options.statuses)
+            } else if options.subtasks {
+                while parents.last().is_some_and(|&(i, _)| i >= indent) {
+                    parents.pop();
+                }
+                parents.last().and_then(|&(_, depth)| {
+                    Task::from_string_with(trimmed, &options.statuses)
+                        .map(|t| t.with_depth(depth + 1))
+                })
+            } else {
+                None
+            };
+
+            if let Some(task) = task {
+                parents.push((indent, task.depth()));
+                continued = Some((f.tasks.len(), indent));
+                f.tasks.push(task);
+                f.task_lines.push(f.lines.len());
+                bodies.push(Vec::new());
+            } else {
+                match continued {
+                    Some((i, task_indent)) if !trimmed.is_empty() && indent > task_indent => {
+                        bodies[i].push(line)
+                    }
+                    _ => continued = None,
+                }
             }
+            f.lines.push(line.to_string());
         }
-        Ok(LogFile { tasks })
+
+        for (t, body) in f.tasks.iter_mut().zip(bodies) {
+            if !body.is_empty() {
+                *t = t.clone().with_body(unindent(&body));
+            }
+        }
+        f
+    }
+
+    fn reparse(&mut self) {
+        *self = LogFile::parse_with(&self.contents(), &self.options);
     }
 
     /// Returns the tasks contained in the devlog entry file.
     pub fn tasks(&self) -> &[Task] {
         &self.tasks
     }
+
+    /// Returns the indices of the tasks whose content matches `query` (see `Task::matches`).
+    pub fn find_tasks(&self, query: &str) -> Vec<usize> {
+        (0..self.tasks.len())
+            .filter(|&i| self.tasks[i].matches(query))
+            .collect()
+    }
+
+    /// Returns the index of the first task with exactly the specified content.
+    pub fn find_task_by_content(&self, content: &str) -> Option<usize> {
+        self.tasks.iter().position(|t| t.content() == content)
+    }
+
+    /// Returns the line number (starting from one) of the task at index `i`.
+    pub fn line_number(&self, i: usize) -> usize {
+        self.task_lines[i] + 1
+    }
+
+    /// Returns the index of the task that the task at index `i` is a subtask of,
+    /// or `None` if it is a top-level task.
+    pub fn parent(&self, i: usize) -> Option<usize> {
+        let depth = self.tasks[i].depth();
+        if depth == 0 {
+            return None;
+        }
+        (0..i).rev().find(|&j| self.tasks[j].depth() < depth)
+    }
+
+    /// Returns the indices of the direct subtasks of the task at index `i`.
+    pub fn children(&self, i: usize) -> Vec<usize> {
+        let depth = self.tasks[i].depth();
+        (i + 1..self.tasks.len())
+            .take_while(|&j| self.tasks[j].depth() > depth)
+            .filter(|&j| self.tasks[j].depth() == depth + 1)
+            .collect()
+    }
+
+    /// Returns the status of the task at index `i`, derived from its subtasks:
+    /// a task whose subtasks are all complete is done.  Otherwise, this is the task's own status.
+    pub fn effective_status(&self, i: usize) -> TaskStatus {
+        let children = self.children(i);
+        if !children.is_empty()
+            && children
+                .iter()
+                .all(|&c| self.effective_status(c).is_complete())
+        {
+            TaskStatus::Done
+        } else {
+            self.tasks[i].status()
+        }
+    }
+
+    /// Changes the status of the task at index `i`.
+    pub fn set_status(&mut self, i: usize, status: TaskStatus) {
+        let t = Task::new(status, self.tasks[i].content());
+        self.replace_task(i, t);
+    }
+
+    /// Replaces the task at index `i`, keeping its position, indentation, and body in the file.
+    pub fn replace_task(&mut self, i: usize, task: Task) {
+        let line = &self.lines[self.task_lines[i]];
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let old = &self.tasks[i];
+        let task = task.with_depth(old.depth()).with_body(old.body().to_vec());
+        self.lines[self.task_lines[i]] = format!("{}{}", indent, task.to_string().trim_start());
+        self.tasks[i] = task;
+    }
+
+    /// Returns the range of line indices occupied by the task at index `i` and its body.
+    fn block(&self, i: usize) -> Range<usize> {
+        let start = self.task_lines[i];
+        start..start + 1 + self.tasks[i].body().len()
+    }
+
+    /// Adds a top-level task after the last task and its body,
+    /// or at the end of the file if there are no tasks.
+    /// Returns the index of the new task.
+    pub fn add_task(&mut self, task: Task) -> usize {
+        let line = match self.tasks.len() {
+            0 => self.lines.len(),
+            n => self.block(n - 1).end,
+        };
+        let task = task.with_depth(0);
+        let text = task.format_with_body();
+        let new_lines = text.lines().map(|l| l.to_string());
+        self.lines.splice(line..line, new_lines);
+        self.reparse();
+        self.task_lines
+            .iter()
+            .position(|&l| l == line)
+            .expect("added task should be parsed")
+    }
+
+    /// Adds a line to the end of the body of the task at index `i`, indented beneath the task.
+    pub fn append_to_body(&mut self, i: usize, line: &str) {
+        let end = self.block(i).end;
+        let indent = SUBTASK_INDENT.repeat(self.tasks[i].depth() + 1);
+        self.lines.insert(end, format!("{}{}", indent, line));
+        self.reparse();
+    }
+
+    /// Adds tasks after the last task and its body, or at the end of the file if there are no tasks.
+    /// Unlike `add_task`, this keeps the depth of each task, so a task followed by its subtasks
+    /// is added with its subtasks.
+    pub fn add_tasks(&mut self, tasks: &[Task]) {
+        let line = match self.tasks.len() {
+            0 => self.lines.len(),
+            n => self.block(n - 1).end,
+        };
+        let new_lines: Vec<String> = tasks
+            .iter()
+            .flat_map(|t| {
+                let text = t.format_with_body();
+                text.lines().map(|l| l.to_string()).collect::<Vec<String>>()
+            })
+            .collect();
+        self.lines.splice(line..line, new_lines);
+        self.reparse();
+    }
+
+    /// Returns the indices of the task at index `i` followed by all of its subtasks, at any depth.
+    pub fn subtree(&self, i: usize) -> Range<usize> {
+        let depth = self.tasks[i].depth();
+        let num_subtasks = self.tasks[i + 1..]
+            .iter()
+            .take_while(|t| t.depth() > depth)
+            .count();
+        i..i + 1 + num_subtasks
+    }
+
+    /// Removes the task at index `i` along with its subtasks and their bodies,
+    /// and returns the removed tasks.
+    pub fn remove_subtree(&mut self, i: usize) -> Vec<Task> {
+        let indices = self.subtree(i);
+        let tasks = self.tasks[indices.clone()].to_vec();
+        let lines = self.task_lines[indices.start]..self.block(indices.end - 1).end;
+        self.lines.drain(lines);
+        self.reparse();
+        tasks
+    }
+
+    /// Removes the task at index `i`, including its body, and returns it.
+    pub fn remove_task(&mut self, i: usize) -> Task {
+        let task = self.tasks[i].clone();
+        self.lines.drain(self.block(i));
+        self.reparse();
+        task
+    }
+
+    /// Swaps the positions of the tasks at indices `i` and `j`, along with their bodies.
+    /// Other lines, such as notes, stay where they are.
+    pub fn swap_tasks(&mut self, i: usize, j: usize) {
+        let (first, second) = if i < j {
+            (self.block(i), self.block(j))
+        } else {
+            (self.block(j), self.block(i))
+        };
+        if first == second {
+            return;
+        }
+        let mut lines: Vec<String> = self.lines[..first.start].to_vec();
+        lines.extend_from_slice(&self.lines[second.clone()]);
+        lines.extend_from_slice(&self.lines[first.end..second.start]);
+        lines.extend_from_slice(&self.lines[first]);
+        lines.extend_from_slice(&self.lines[second.end..]);
+        self.lines = lines;
+        self.reparse();
+    }
+
+    /// Returns the full contents of the file, including notes.
+    pub fn contents(&self) -> String {
+        let mut s = String::new();
+        for line in &self.lines {
+            s.push_str(line);
+            s.push('\n');
+        }
+        s
+    }
+
+    /// Writes the full contents of the file to `path`, replacing any existing file.
+    /// The file is replaced atomically, so it is never left partially written.
+    pub fn save(&self, path: &Path) -> Result<(), IOError> {
+        atomic::write(path, &self.contents())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::task::CustomStatus;
     use crate::task::{Task, TaskStatus};
     use std::fs::OpenOptions;
     use std::io::Write;
@@ -46,19 +384,245 @@ mod tests {
         let mut f = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(&p)
             .unwrap();
-        write!(f, "+ DONE\n").unwrap();
-        write!(f, "- BLOCKED\n").unwrap();
-        write!(f, "* INCOMPLETE\n").unwrap();
-        write!(f, "COMMENT\n").unwrap();
+        writeln!(f, "+ DONE").unwrap();
+        writeln!(f, "- BLOCKED").unwrap();
+        writeln!(f, "* INCOMPLETE").unwrap();
+        writeln!(f, "COMMENT").unwrap();
 
         let lf = LogFile::load(&p).unwrap();
-        let expected = vec![
+        let expected = [
             Task::new(TaskStatus::Done, "DONE"),
             Task::new(TaskStatus::Blocked, "BLOCKED"),
             Task::new(TaskStatus::ToDo, "INCOMPLETE"),
         ];
         assert_eq!(lf.tasks(), &expected[..]);
     }
+
+    const CONTENTS: &str = "Notes\n* Foo\n    about foo\n^ Bar\nMore notes\n";
+
+    #[test]
+    fn test_contents_roundtrip() {
+        let lf = LogFile::parse(CONTENTS);
+        assert_eq!(lf.contents(), CONTENTS);
+        assert_eq!(lf.line_number(0), 2);
+        assert_eq!(lf.line_number(1), 4);
+    }
+
+    #[test]
+    fn test_set_status() {
+        let mut lf = LogFile::parse(CONTENTS);
+        lf.set_status(0, TaskStatus::Done);
+        assert_eq!(lf.tasks()[0].status(), TaskStatus::Done);
+        assert_eq!(lf.tasks()[0].body(), &["about foo".to_string()]);
+        assert_eq!(
+            lf.contents(),
+            "Notes\n+ Foo\n    about foo\n^ Bar\nMore notes\n"
+        );
+    }
+
+    #[test]
+    fn test_add_task() {
+        let mut lf = LogFile::parse(CONTENTS);
+        let i = lf.add_task(Task::new(TaskStatus::ToDo, "Baz"));
+        assert_eq!(i, 2);
+        assert_eq!(
+            lf.contents(),
+            "Notes\n* Foo\n    about foo\n^ Bar\n* Baz\nMore notes\n"
+        );
+
+        let mut lf = LogFile::parse("Notes\n");
+        lf.add_task(Task::new(TaskStatus::ToDo, "Baz"));
+        assert_eq!(lf.contents(), "Notes\n* Baz\n");
+    }
+
+    #[test]
+    fn test_remove_task() {
+        let mut lf = LogFile::parse(CONTENTS);
+        let t = lf.remove_task(0);
+        assert_eq!(t.content(), "Foo");
+        assert_eq!(lf.contents(), "Notes\n^ Bar\nMore notes\n");
+        assert_eq!(lf.line_number(0), 2);
+    }
+
+    #[test]
+    fn test_swap_tasks() {
+        let mut lf = LogFile::parse(CONTENTS);
+        lf.swap_tasks(0, 1);
+        assert_eq!(lf.tasks()[0], Task::new(TaskStatus::Started, "Bar"));
+        assert_eq!(
+            lf.contents(),
+            "Notes\n^ Bar\n* Foo\n    about foo\nMore notes\n"
+        );
+        lf.swap_tasks(1, 0);
+        assert_eq!(lf.contents(), CONTENTS);
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = tempdir().unwrap();
+        let p = dir.path().join("testlog");
+        let mut lf = LogFile::parse(CONTENTS);
+        lf.set_status(1, TaskStatus::Blocked);
+        lf.save(&p).unwrap();
+        assert_eq!(LogFile::load(&p).unwrap(), lf);
+    }
+
+    const SUBTASKS: &str =
+        "* Parent\n    ^ Child\n        * Grandchild\n    - Sibling\nNotes\n    * Not a subtask\n";
+
+    #[test]
+    fn test_subtasks_disabled() {
+        let lf = LogFile::parse(SUBTASKS);
+        assert_eq!(lf.tasks().len(), 1);
+        assert!(lf.children(0).is_empty());
+        assert_eq!(lf.contents(), SUBTASKS);
+    }
+
+    #[test]
+    fn test_parse_subtasks() {
+        let lf = LogFile::parse_with(SUBTASKS, &ParseOptions::default().with_subtasks(true));
+        let depths: Vec<usize> = lf.tasks().iter().map(|t| t.depth()).collect();
+        assert_eq!(depths, vec![0, 1, 2, 1]);
+        assert_eq!(lf.children(0), vec![1, 3]);
+        assert_eq!(lf.children(1), vec![2]);
+        assert_eq!(lf.parent(2), Some(1));
+        assert_eq!(lf.parent(3), Some(0));
+        assert_eq!(lf.parent(0), None);
+        assert_eq!(lf.contents(), SUBTASKS);
+    }
+
+    #[test]
+    fn test_effective_status() {
+        let options = ParseOptions::default().with_subtasks(true);
+        let mut lf = LogFile::parse_with(SUBTASKS, &options);
+        assert_eq!(lf.effective_status(0), TaskStatus::ToDo);
+        assert_eq!(lf.effective_status(1), TaskStatus::Started);
+
+        lf.set_status(2, TaskStatus::Done);
+        lf.set_status(3, TaskStatus::Done);
+        assert_eq!(lf.effective_status(1), TaskStatus::Done);
+        assert_eq!(lf.effective_status(0), TaskStatus::Done);
+        assert_eq!(lf.tasks()[0].status(), TaskStatus::ToDo);
+        assert_eq!(
+            lf.contents(),
+            "* Parent\n    ^ Child\n        + Grandchild\n    + Sibling\nNotes\n    * Not a subtask\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_body() {
+        let contents =
+            "* Foo\n    $ cargo build\n      --release\nNotes\n^ Bar\n\n    not part of bar\n";
+        let lf = LogFile::parse(contents);
+        assert_eq!(
+            lf.tasks()[0].body(),
+            &["$ cargo build".to_string(), "  --release".to_string()]
+        );
+        assert!(lf.tasks()[1].body().is_empty());
+        assert_eq!(lf.contents(), contents);
+    }
+
+    #[test]
+    fn test_parse_body_with_subtasks() {
+        let contents = "* Foo\n  about foo\n  * Bar\n      about bar\n";
+        let lf = LogFile::parse_with(contents, &ParseOptions::default().with_subtasks(true));
+        assert_eq!(lf.tasks().len(), 2);
+        assert_eq!(lf.tasks()[0].body(), &["about foo".to_string()]);
+        assert_eq!(lf.tasks()[1].body(), &["about bar".to_string()]);
+
+        let lf = LogFile::parse(contents);
+        assert_eq!(lf.tasks().len(), 1);
+        assert_eq!(lf.tasks()[0].body().len(), 3);
+    }
+
+    #[test]
+    fn test_parse_body_with_unicode_indentation() {
+        let contents = "* Foo\n\u{3000}x\n\u{a0}\u{a0}y\n";
+        let lf = LogFile::parse(contents);
+        assert_eq!(
+            lf.tasks()[0].body(),
+            &["\u{3000}x".to_string(), "\u{a0}\u{a0}y".to_string()]
+        );
+        assert_eq!(lf.contents(), contents);
+
+        let lf = LogFile::parse("* Foo\n\u{a0}\u{a0}x\n\u{a0}y\n");
+        assert_eq!(
+            lf.tasks()[0].body(),
+            &["\u{a0}x".to_string(), "y".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_find_tasks() {
+        let lf = LogFile::parse(CONTENTS);
+        assert_eq!(lf.find_tasks("BAR"), vec![1]);
+        assert_eq!(lf.find_tasks("o"), vec![0]);
+        assert_eq!(lf.find_task_by_content("Bar"), Some(1));
+        assert_eq!(lf.find_task_by_content("Ba"), None);
+    }
+
+    #[test]
+    fn test_append_to_body() {
+        let mut lf = LogFile::parse(CONTENTS);
+        lf.append_to_body(0, "more about foo");
+        lf.append_to_body(1, "about bar");
+        assert_eq!(
+            lf.contents(),
+            "Notes\n* Foo\n    about foo\n    more about foo\n^ Bar\n    about bar\nMore notes\n"
+        );
+        assert_eq!(lf.tasks()[0].body().len(), 2);
+    }
+
+    #[test]
+    fn test_move_subtree() {
+        let options = ParseOptions::default().with_subtasks(true);
+        let mut lf = LogFile::parse_with(
+            "* Foo\n    * Foo 1\n        about foo 1\n    * Foo 2\nNotes\n^ Bar\n",
+            &options,
+        );
+        assert_eq!(lf.subtree(0), 0..3);
+        assert_eq!(lf.subtree(3), 3..4);
+
+        let removed = lf.remove_subtree(0);
+        assert_eq!(removed.len(), 3);
+        assert_eq!(lf.contents(), "Notes\n^ Bar\n");
+
+        lf.add_tasks(&removed);
+        assert_eq!(
+            lf.contents(),
+            "Notes\n^ Bar\n* Foo\n    * Foo 1\n        about foo 1\n    * Foo 2\n"
+        );
+        assert_eq!(lf.children(1), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_add_task_after_body() {
+        let mut lf = LogFile::parse("* Foo\n    about foo\nNotes\n");
+        let body = vec!["about baz".to_string()];
+        let i = lf.add_task(Task::new(TaskStatus::ToDo, "Baz").with_body(body));
+        assert_eq!(i, 1);
+        assert_eq!(
+            lf.contents(),
+            "* Foo\n    about foo\n* Baz\n    about baz\nNotes\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_custom_statuses() {
+        let review = CustomStatus::new("review", '%', "In Review", false, true).into_status();
+        let mut statuses = ALL_STATUSES.to_vec();
+        statuses.push(review.clone());
+        let options = ParseOptions::default().with_statuses(statuses);
+        assert_eq!(options.status_named("review"), Some(review.clone()));
+        assert_eq!(options.status_marked('%'), Some(review.clone()));
+
+        let mut lf = LogFile::parse_with("% Foo\n* Bar\n", &options);
+        assert_eq!(lf.tasks()[0].status(), review);
+        lf.set_status(1, review);
+        assert_eq!(lf.contents(), "% Foo\n% Bar\n");
+        assert!(LogFile::parse("% Foo\n").tasks().is_empty());
+    }
 }
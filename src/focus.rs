@@ -0,0 +1,226 @@
+//! Focus sessions are fixed periods of uninterrupted work on a single task,
+//! like the Pomodoro technique.
+//!
+//! Completed sessions are logged in the `focus.log` file in the devlog repository,
+//! one per line.  Each line has three fields separated by tabs: the RFC 3339 start time,
+//! like `2022-05-08T09:00:00-07:00`, the length in minutes, like `25`,
+//! and the content of the task, like `Fix the build #ci`.
+
+use crate::error::Error;
+use crate::file::{LogFile, ParseOptions};
+use crate::repository::LogRepository;
+use crate::task::TaskStatus;
+use crate::timelog::format_duration;
+use chrono::{DateTime, Duration, Local, NaiveDate, SecondsFormat};
+use std::fs::{read_to_string, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const FOCUS_LOG_FILE_NAME: &str = "focus.log";
+
+/// A completed focus session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    start: DateTime<Local>,
+    minutes: i64,
+    content: String,
+}
+
+impl Session {
+    /// Creates a session that started at `start` and lasted `minutes`.
+    pub fn new(start: DateTime<Local>, minutes: i64, content: &str) -> Session {
+        Session {
+            start,
+            minutes,
+            content: content.to_string(),
+        }
+    }
+
+    /// When the session started.
+    pub fn start(&self) -> DateTime<Local> {
+        self.start
+    }
+
+    /// How long the session lasted.
+    pub fn duration(&self) -> Duration {
+        Duration::minutes(self.minutes)
+    }
+
+    /// The content of the task the session focused on.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+}
+
+/// Returns the path to the focus session log, which may or may not exist.
+pub fn focus_log_path(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(FOCUS_LOG_FILE_NAME)
+}
+
+/// Appends a completed session to the focus session log.
+pub fn log_session(repo_dir: &Path, session: &Session) -> Result<(), Error> {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(focus_log_path(repo_dir))?;
+    writeln!(
+        f,
+        "{}\t{}\t{}",
+        session.start.to_rfc3339_opts(SecondsFormat::Secs, false),
+        session.minutes,
+        session.content.replace(['\t', '\n'], " ")
+    )?;
+    f.sync_data()?;
+    Ok(())
+}
+
+/// Loads the logged sessions, ignoring malformed lines.
+pub fn load_sessions(repo_dir: &Path) -> Result<Vec<Session>, Error> {
+    let p = focus_log_path(repo_dir);
+    if !p.is_file() {
+        return Ok(Vec::new());
+    }
+    let sessions = read_to_string(&p)?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let start = DateTime::parse_from_rfc3339(fields.next()?).ok()?;
+            let minutes = fields.next()?.parse().ok()?;
+            Some(Session::new(
+                start.with_timezone(&Local),
+                minutes,
+                fields.next()?,
+            ))
+        })
+        .collect();
+    Ok(sessions)
+}
+
+/// Adds a timestamped note about a completed session beneath its task in the latest entry,
+/// and marks the task as done if `done` is true.
+/// Returns false if the task is no longer in the latest entry, for example because it was renamed.
+pub fn finish_task(
+    repo: &LogRepository,
+    options: &ParseOptions,
+    session: &Session,
+    done: bool,
+) -> Result<bool, Error> {
+    let latest = repo
+        .latest()?
+        .ok_or(Error::InvalidArg("no devlog entries"))?;
+    let mut f = LogFile::load_with(latest.path(), options)?;
+    let i = match f.find_task_by_content(session.content()) {
+        Some(i) => i,
+        None => return Ok(false),
+    };
+    let note = format!(
+        "Focused for {}m at {}",
+        session.minutes,
+        session.start.format("%Y-%m-%d %H:%M")
+    );
+    f.append_to_body(i, &note);
+    if done {
+        f.set_status(i, TaskStatus::Done);
+    }
+    f.save(latest.path())?;
+    Ok(true)
+}
+
+/// Prints the total focus time and number of sessions for each day on or after `since`.
+pub fn print_stats<W: Write>(
+    w: &mut W,
+    sessions: &[Session],
+    since: NaiveDate,
+) -> Result<(), Error> {
+    let mut days: Vec<(NaiveDate, Duration, usize)> = Vec::new();
+    for s in sessions {
+        let day = s.start.naive_local().date();
+        if day < since {
+            continue;
+        }
+        match days.iter_mut().find(|(d, _, _)| *d == day) {
+            Some((_, total, count)) => {
+                *total = *total + s.duration();
+                *count += 1;
+            }
+            None => days.push((day, s.duration(), 1)),
+        }
+    }
+    days.sort_by_key(|(d, _, _)| *d);
+
+    for (day, total, count) in days {
+        writeln!(
+            w,
+            "{}  {:>7}  ({} {})",
+            day.format("%a %Y-%m-%d"),
+            format_duration(total),
+            count,
+            if count == 1 { "session" } else { "sessions" }
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timelog::mark_started;
+    use chrono::TimeZone;
+    use std::str;
+    use tempfile::tempdir;
+
+    fn at(d: u32, h: u32, m: u32) -> DateTime<Local> {
+        Local.ymd(2022, 5, d).and_hms(h, m, 0)
+    }
+
+    #[test]
+    fn test_log_sessions() {
+        let dir = tempdir().unwrap();
+        assert!(load_sessions(dir.path()).unwrap().is_empty());
+
+        let sessions = vec![
+            Session::new(at(7, 9, 0), 25, "Foo"),
+            Session::new(at(8, 9, 0), 25, "Foo"),
+            Session::new(at(8, 10, 0), 50, "Bar"),
+        ];
+        for s in &sessions {
+            log_session(dir.path(), s).unwrap();
+        }
+        assert_eq!(load_sessions(dir.path()).unwrap(), sessions);
+
+        let mut buf = Vec::new();
+        print_stats(&mut buf, &sessions, NaiveDate::from_ymd(2022, 5, 1)).unwrap();
+        assert_eq!(
+            str::from_utf8(&buf).unwrap(),
+            "Sat 2022-05-07      25m  (1 session)\nSun 2022-05-08   1h 15m  (2 sessions)\n"
+        );
+    }
+
+    #[test]
+    fn test_finish_task() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        std::fs::write(logpath.path(), "* Foo\n    notes\n* Bar\n").unwrap();
+        let options = ParseOptions::default();
+
+        let matches = mark_started(&repo, &options, "foo").unwrap();
+        assert_eq!(matches.len(), 1);
+
+        let session = Session::new(at(8, 9, 0), 25, "Foo");
+        assert!(finish_task(&repo, &options, &session, false).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(logpath.path()).unwrap(),
+            "^ Foo\n    notes\n    Focused for 25m at 2022-05-08 09:00\n* Bar\n"
+        );
+
+        let session = Session::new(at(8, 9, 30), 25, "Foo");
+        assert!(finish_task(&repo, &options, &session, true).unwrap());
+        assert!(std::fs::read_to_string(logpath.path())
+            .unwrap()
+            .starts_with("+ Foo\n"));
+
+        let session = Session::new(at(8, 10, 0), 25, "Missing");
+        assert!(!finish_task(&repo, &options, &session, true).unwrap());
+    }
+}
@@ -0,0 +1,471 @@
+//! Check a devlog repository for files that devlog silently ignores or can't use,
+//! and repair them.
+//!
+//! `LogRepository::list` only sees files named exactly like "000000123.devlog",
+//! so a misnamed entry or an editor's copy of an entry is otherwise never noticed.
+//! Repairs rename misnamed entries, move files that can't be fixed into the
+//! `quarantine` directory of the repository, and fix hook file permissions.
+
+use crate::error::Error;
+use crate::hook::hook_dir_path;
+use crate::path::{LogPath, MAX_SEQ_NUM, NUM_DIGITS};
+use std::fs::{create_dir_all, read, read_dir, rename, set_permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+const QUARANTINE_DIR_NAME: &str = "quarantine";
+
+/// Suffixes of swap, backup, and temporary files left behind by editors and crashed writes.
+const EDITOR_FILE_SUFFIXES: &[&str] = &[".swp", ".swo", ".swx", "~", ".bak", ".orig", ".tmp"];
+
+/// The kinds of problems that `check` can find.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// Entries are missing from the sequence before this entry.
+    Gap,
+
+    /// The file looks like an entry, but its name isn't formatted like "000000123.devlog".
+    MisnamedEntry,
+
+    /// The file looks like a copy of an entry, such as "000000123 (1).devlog".
+    DuplicateEntry,
+
+    /// The entry could not be read or is not valid UTF-8.
+    UnreadableEntry,
+
+    /// The file is an editor swap or backup file, or a leftover temporary file.
+    EditorFile,
+
+    /// The hook file has permissions that are unsafe or stop it from running.
+    HookPermissions,
+}
+
+/// A problem with a file in a devlog repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    path: PathBuf,
+    kind: ProblemKind,
+    message: String,
+}
+
+impl Problem {
+    fn new(path: PathBuf, kind: ProblemKind, message: String) -> Problem {
+        Problem {
+            path,
+            kind,
+            message,
+        }
+    }
+
+    /// The file with the problem.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The kind of problem.
+    pub fn kind(&self) -> ProblemKind {
+        self.kind
+    }
+
+    /// Describes what is wrong with the file.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Returns the path to the directory where repairs move problem files.
+pub fn quarantine_dir(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(QUARANTINE_DIR_NAME)
+}
+
+/// Checks the files in the repository, returning the problems ordered by path.
+/// Only the repository directory and its hooks are checked,
+/// so the quarantine directory and other subdirectories are ignored.
+pub fn check(repo_dir: &Path) -> Result<Vec<Problem>, Error> {
+    let mut problems = Vec::new();
+    let mut seq_nums = Vec::new();
+    for entry in read_dir(repo_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let path = entry.path();
+        if let Some(p) = LogPath::from_path(path.clone()) {
+            seq_nums.push(p.seq_num());
+            if let Some(problem) = check_entry(&p) {
+                problems.push(problem);
+            }
+        } else if let Some(problem) = check_other_file(repo_dir, &path) {
+            problems.push(problem);
+        }
+    }
+
+    seq_nums.sort_unstable();
+    for pair in seq_nums.windows(2) {
+        let (prev, next) = (pair[0], pair[1]);
+        if next > prev + 1 {
+            let missing = if next == prev + 2 {
+                format!("entry {} is missing", prev + 1)
+            } else {
+                format!("entries {} to {} are missing", prev + 1, next - 1)
+            };
+            let p = LogPath::new(repo_dir, next);
+            problems.push(Problem::new(
+                p.path().to_path_buf(),
+                ProblemKind::Gap,
+                format!("{} before this entry", missing),
+            ));
+        }
+    }
+
+    let hook_dir = hook_dir_path(repo_dir);
+    if hook_dir.is_dir() {
+        for entry in read_dir(&hook_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                let mode = entry.metadata()?.permissions().mode();
+                if let Some(message) = check_hook_mode(mode) {
+                    problems.push(Problem::new(
+                        entry.path(),
+                        ProblemKind::HookPermissions,
+                        message.to_string(),
+                    ));
+                }
+            }
+        }
+    }
+
+    problems.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(problems)
+}
+
+fn check_entry(p: &LogPath) -> Option<Problem> {
+    let message = match read(p.path()) {
+        Ok(bytes) if std::str::from_utf8(&bytes).is_ok() => return None,
+        Ok(_) => "entry is not valid UTF-8".to_string(),
+        Err(err) => format!("entry could not be read: {}", err),
+    };
+    Some(Problem::new(
+        p.path().to_path_buf(),
+        ProblemKind::UnreadableEntry,
+        message,
+    ))
+}
+
+fn check_other_file(repo_dir: &Path, path: &Path) -> Option<Problem> {
+    let name = path.file_name()?.to_str()?;
+    let problem = |kind, message: String| Some(Problem::new(path.to_path_buf(), kind, message));
+
+    let is_editor_file = EDITOR_FILE_SUFFIXES.iter().any(|s| name.ends_with(s))
+        || name.starts_with(".#")
+        || (name.len() > 1 && name.starts_with('#') && name.ends_with('#'));
+    if is_editor_file {
+        return problem(
+            ProblemKind::EditorFile,
+            "editor swap or backup file, or leftover temporary file".to_string(),
+        );
+    }
+
+    let leading_digits = name.chars().take_while(|c| c.is_ascii_digit()).count();
+    if leading_digits == 0 {
+        return None;
+    }
+    match name.strip_suffix(".devlog") {
+        Some(stem) if stem.len() == leading_digits => match misnamed_seq_num(stem) {
+            Some(seq_num) if LogPath::new(repo_dir, seq_num).path().exists() => problem(
+                ProblemKind::DuplicateEntry,
+                format!(
+                    "entry name is misformatted, and entry {} already exists",
+                    seq_num
+                ),
+            ),
+            Some(seq_num) => problem(
+                ProblemKind::MisnamedEntry,
+                format!(
+                    "entry name should have {} digits, like {:?}",
+                    NUM_DIGITS,
+                    entry_file_name(seq_num)
+                ),
+            ),
+            None => problem(
+                ProblemKind::MisnamedEntry,
+                format!("sequence number must be from 1 to {}", MAX_SEQ_NUM),
+            ),
+        },
+        _ if name.contains(".devlog") => problem(
+            ProblemKind::DuplicateEntry,
+            "file looks like a copy of an entry".to_string(),
+        ),
+        _ => None,
+    }
+}
+
+fn misnamed_seq_num(stem: &str) -> Option<usize> {
+    stem.parse()
+        .ok()
+        .filter(|&seq_num| seq_num > 0 && seq_num <= MAX_SEQ_NUM)
+}
+
+fn entry_file_name(seq_num: usize) -> String {
+    format!("{:0width$}.devlog", seq_num, width = NUM_DIGITS)
+}
+
+fn check_hook_mode(mode: u32) -> Option<&'static str> {
+    if mode & 0o022 != 0 {
+        Some("hook is writable by other users")
+    } else if mode & 0o111 != 0 && mode & 0o100 == 0 {
+        Some("hook is enabled, but its owner can't execute it")
+    } else if mode & 0o100 != 0 && mode & 0o400 == 0 {
+        Some("hook is enabled, but its owner can't read it")
+    } else {
+        None
+    }
+}
+
+fn repaired_hook_mode(mode: u32) -> u32 {
+    let mut mode = mode & !0o022;
+    if mode & 0o111 != 0 {
+        mode |= 0o500;
+    }
+    mode
+}
+
+/// Repairs a problem, returning a description of what was done,
+/// or `None` if the problem can't be repaired automatically.
+/// Gaps are never repaired, since entries may have been deleted on purpose;
+/// `renumber` closes them.
+pub fn repair(repo_dir: &Path, problem: &Problem) -> Result<Option<String>, Error> {
+    let path = problem.path();
+    match problem.kind() {
+        ProblemKind::Gap => Ok(None),
+        ProblemKind::MisnamedEntry => {
+            let seq_num = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(misnamed_seq_num);
+            match seq_num {
+                // Another misnamed file with the same sequence number may have been renamed first.
+                Some(seq_num) if !LogPath::new(repo_dir, seq_num).path().exists() => {
+                    let p = LogPath::new(repo_dir, seq_num);
+                    rename(path, p.path())?;
+                    Ok(Some(format!("renamed to {:?}", entry_file_name(seq_num))))
+                }
+                _ => quarantine(repo_dir, path).map(Some),
+            }
+        }
+        ProblemKind::DuplicateEntry | ProblemKind::UnreadableEntry | ProblemKind::EditorFile => {
+            quarantine(repo_dir, path).map(Some)
+        }
+        ProblemKind::HookPermissions => {
+            let mut perm = path.metadata()?.permissions();
+            let mode = perm.mode() & 0o7777;
+            perm.set_mode(repaired_hook_mode(mode));
+            set_permissions(path, perm)?;
+            Ok(Some(format!(
+                "changed permissions from {:o} to {:o}",
+                mode,
+                repaired_hook_mode(mode)
+            )))
+        }
+    }
+}
+
+/// Moves a file into the quarantine directory, adding a numeric suffix to its name
+/// if a file with the same name was quarantined before.
+fn quarantine(repo_dir: &Path, path: &Path) -> Result<String, Error> {
+    let dir = quarantine_dir(repo_dir);
+    create_dir_all(&dir)?;
+    let name = path
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut dst = dir.join(&name);
+    let mut n = 1;
+    while dst.exists() {
+        dst = dir.join(format!("{}.{}", name, n));
+        n += 1;
+    }
+    rename(path, &dst)?;
+    Ok(format!("moved to {:?}", dst))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hook::init_hooks;
+    use crate::repository::LogRepository;
+    use std::fs::{write, Permissions};
+    use tempfile::tempdir;
+
+    fn kinds(problems: &[Problem]) -> Vec<(String, ProblemKind)> {
+        problems
+            .iter()
+            .map(|p| {
+                let name = p.path().file_name().unwrap().to_string_lossy().to_string();
+                (name, p.kind())
+            })
+            .collect()
+    }
+
+    fn write_files(dir: &Path, names: &[&str]) {
+        for name in names {
+            write(dir.join(name), "* Foo\n").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_healthy_repository() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        repo.init().unwrap();
+        init_hooks(dir.path()).unwrap();
+        write_files(
+            dir.path(),
+            &["000000002.devlog", "backlog.devlog", "notes.txt"],
+        );
+        assert_eq!(check(dir.path()).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_check_entries() {
+        let dir = tempdir().unwrap();
+        write_files(
+            dir.path(),
+            &[
+                "000000001.devlog",
+                "000000002.devlog",
+                "000000005.devlog",
+                "000000007.devlog",
+                "12.devlog",
+                "2.devlog",
+                "0000000000.devlog",
+                "000000002 (1).devlog",
+                "000000007.devlog.1",
+                ".000000007.devlog.swp",
+                "000000007.devlog~",
+                ".000000007.devlog.123-0.tmp",
+            ],
+        );
+        write(dir.path().join("000000006.devlog"), [0xff, 0xfe]).unwrap();
+
+        let problems = check(dir.path()).unwrap();
+        assert_eq!(
+            kinds(&problems),
+            vec![
+                (
+                    ".000000007.devlog.123-0.tmp".to_string(),
+                    ProblemKind::EditorFile
+                ),
+                (".000000007.devlog.swp".to_string(), ProblemKind::EditorFile),
+                ("0000000000.devlog".to_string(), ProblemKind::MisnamedEntry),
+                (
+                    "000000002 (1).devlog".to_string(),
+                    ProblemKind::DuplicateEntry
+                ),
+                ("000000005.devlog".to_string(), ProblemKind::Gap),
+                ("000000006.devlog".to_string(), ProblemKind::UnreadableEntry),
+                (
+                    "000000007.devlog.1".to_string(),
+                    ProblemKind::DuplicateEntry
+                ),
+                ("000000007.devlog~".to_string(), ProblemKind::EditorFile),
+                ("12.devlog".to_string(), ProblemKind::MisnamedEntry),
+                ("2.devlog".to_string(), ProblemKind::DuplicateEntry),
+            ]
+        );
+        assert_eq!(
+            problems[4].message(),
+            "entries 3 to 4 are missing before this entry"
+        );
+        assert_eq!(
+            problems[8].message(),
+            "entry name should have 9 digits, like \"000000012.devlog\""
+        );
+    }
+
+    #[test]
+    fn test_check_hooks() {
+        let dir = tempdir().unwrap();
+        init_hooks(dir.path()).unwrap();
+        let hook_dir = hook_dir_path(dir.path());
+        let set_mode = |name: &str, mode: u32| {
+            set_permissions(hook_dir.join(name), Permissions::from_mode(mode)).unwrap();
+        };
+        set_mode("before-edit", 0o755);
+        set_mode("after-edit", 0o775);
+        set_mode("before-rollover", 0o655);
+        set_mode("after-rollover", 0o300);
+
+        let problems = check(dir.path()).unwrap();
+        assert_eq!(
+            kinds(&problems),
+            vec![
+                ("after-edit".to_string(), ProblemKind::HookPermissions),
+                ("after-rollover".to_string(), ProblemKind::HookPermissions),
+                ("before-rollover".to_string(), ProblemKind::HookPermissions),
+            ]
+        );
+
+        for p in &problems {
+            assert!(repair(dir.path(), p).unwrap().is_some());
+        }
+        assert_eq!(check(dir.path()).unwrap(), Vec::new());
+        let mode =
+            |name: &str| hook_dir.join(name).metadata().unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("after-edit"), 0o755);
+        assert_eq!(mode("before-rollover"), 0o755);
+        assert_eq!(mode("after-rollover"), 0o700);
+    }
+
+    #[test]
+    fn test_repair() {
+        let dir = tempdir().unwrap();
+        write_files(
+            dir.path(),
+            &[
+                "000000001.devlog",
+                "000000003.devlog",
+                "12.devlog",
+                "0012.devlog",
+                "000000001 copy.devlog",
+                ".000000001.devlog.swp",
+            ],
+        );
+
+        let problems = check(dir.path()).unwrap();
+        let repairs: Vec<Option<String>> = problems
+            .iter()
+            .map(|p| repair(dir.path(), p).unwrap())
+            .collect();
+        assert_eq!(repairs.iter().filter(|r| r.is_none()).count(), 1);
+
+        // Only the gaps remain, including the one before the renamed entry.
+        let remaining = check(dir.path()).unwrap();
+        assert_eq!(
+            kinds(&remaining),
+            vec![
+                ("000000003.devlog".to_string(), ProblemKind::Gap),
+                ("000000012.devlog".to_string(), ProblemKind::Gap),
+            ]
+        );
+
+        let repo = LogRepository::new(dir.path());
+        let mut seq_nums: Vec<usize> = repo.list().unwrap().iter().map(|p| p.seq_num()).collect();
+        seq_nums.sort_unstable();
+        assert_eq!(seq_nums, vec![1, 3, 12]);
+
+        let mut quarantined: Vec<String> = read_dir(quarantine_dir(dir.path()))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        quarantined.sort();
+        assert_eq!(
+            quarantined,
+            vec![
+                ".000000001.devlog.swp",
+                "000000001 copy.devlog",
+                "12.devlog"
+            ]
+        );
+    }
+}
@@ -1,19 +1,26 @@
 //! A hook is an executable program called while executing a devlog command.
 //! It allows users to customize devlog for their workflows.
 //! Hooks are located in the `hooks` subdirectory of the devlog repository.
+//!
+//! Programs that use devlog as a library can also implement the `Hook` trait
+//! to observe operations in-process.  The executable hook files are one
+//! implementation of this trait (`FileHooks`).
 
 use crate::config::Config;
+use crate::diff::EntryDiff;
 use crate::error::Error;
+use crate::path::LogPath;
 use std::ffi::OsStr;
-use std::fs::{create_dir_all, OpenOptions};
+use std::fs::{create_dir_all, set_permissions, OpenOptions};
 use std::io::Write;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-const HOOK_DIR_NAME: &'static str = "hooks";
+const HOOK_DIR_NAME: &str = "hooks";
 
 /// Defines the types of hooks a user can configure.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum HookType {
     /// Invoked before opening a devlog entry in a text editor.
     /// It takes a single argument: the full path to the devlog entry file.
@@ -45,42 +52,273 @@ impl HookType {
         }
         .to_string()
     }
+
+    /// Looks up a hook type by name (e.g. "before-edit").
+    /// Returns `None` if no hook type has the specified name.
+    pub fn from_name(name: &str) -> Option<HookType> {
+        ALL_HOOK_TYPES
+            .iter()
+            .find(|hook_type| hook_type.name() == name)
+            .copied()
+    }
+
+    /// Returns example arguments for the hook, based on the devlog entry at `p`.
+    /// This is used to test a hook without performing the operation that usually invokes it.
+    pub fn sample_args(&self, p: &LogPath) -> Result<Vec<PathBuf>, Error> {
+        let path = p.path().to_path_buf();
+        match self {
+            HookType::AfterRollover => Ok(vec![path, p.next()?.path().to_path_buf()]),
+            _ => Ok(vec![path]),
+        }
+    }
+}
+
+/// Whether a hook file exists and is enabled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HookState {
+    /// There is no file for the hook in the hooks directory.
+    Missing,
+
+    /// The hook file exists, but it is not executable.
+    Disabled,
+
+    /// The hook file exists and is executable.
+    Enabled,
 }
 
-const ALL_HOOK_TYPES: &[HookType] = &[
+/// All hook types, in the order they are usually invoked.
+pub const ALL_HOOK_TYPES: &[HookType] = &[
     HookType::BeforeEdit,
     HookType::AfterEdit,
     HookType::BeforeRollover,
     HookType::AfterRollover,
 ];
 
-const HOOK_TEMPLATE: &'static str = "#!/usr/bin/env sh
+const HOOK_TEMPLATE: &str = "#!/usr/bin/env sh
 # To enable this hook, make this file executable.
 echo \"$0 $@\"
 ";
 
 /// Creates template hook files in the specified repository.
 /// By default, the hook files are non-executable, which means they are disabled.
-pub fn init_hooks(repo_dir: &Path) -> Result<(), Error> {
+/// Existing hook files are never modified, so this can be run again to add templates
+/// for hook types introduced after the repository was created.
+/// Returns the hook types for which a new template was created.
+pub fn init_hooks(repo_dir: &Path) -> Result<Vec<HookType>, Error> {
     let hook_dir = hook_dir_path(repo_dir);
     create_dir_all(&hook_dir)?;
+    let mut created = Vec::new();
     for hook_type in ALL_HOOK_TYPES {
-        let mut p = hook_dir.clone();
-        p.push(hook_type.name());
-
+        let p = hook_path(repo_dir, hook_type);
         if !p.exists() {
             let mut f = OpenOptions::new()
                 .create(true)
                 .truncate(true)
                 .write(true)
-                .open(&p)
-                .unwrap();
+                .open(&p)?;
             write!(f, "{}", HOOK_TEMPLATE)?;
+            created.push(*hook_type);
         }
     }
+    Ok(created)
+}
+
+/// Returns whether the hook file exists and whether it is enabled.
+pub fn hook_state(repo_dir: &Path, hook_type: &HookType) -> Result<HookState, Error> {
+    let p = hook_path(repo_dir, hook_type);
+    if !p.exists() {
+        Ok(HookState::Missing)
+    } else if is_executable(&p)? {
+        Ok(HookState::Enabled)
+    } else {
+        Ok(HookState::Disabled)
+    }
+}
+
+/// Enables a hook by making its file executable.
+/// If the hook file does not exist, it is first created from the template.
+pub fn enable_hook(repo_dir: &Path, hook_type: &HookType) -> Result<(), Error> {
+    let p = hook_path(repo_dir, hook_type);
+    if !p.exists() {
+        init_hooks(repo_dir)?;
+    }
+    let mut perm = p.metadata()?.permissions();
+    let mode = perm.mode();
+    // Like `chmod +x`, grant execute permission to everyone who can read the file.
+    perm.set_mode(mode | ((mode & 0o444) >> 2) | 0o100);
+    set_permissions(&p, perm).map_err(From::from)
+}
+
+/// Disables a hook by removing execute permissions from its file.
+/// This is a no-op if the hook file does not exist.
+pub fn disable_hook(repo_dir: &Path, hook_type: &HookType) -> Result<(), Error> {
+    let p = hook_path(repo_dir, hook_type);
+    if p.exists() {
+        let mut perm = p.metadata()?.permissions();
+        perm.set_mode(perm.mode() & !0o111);
+        set_permissions(&p, perm)?;
+    }
     Ok(())
 }
 
+/// Observes devlog operations in-process.
+/// Each method is invoked at the same point as the corresponding hook file,
+/// and the default implementations do nothing.
+/// Returning an error from a "before" method vetoes the operation;
+/// by convention, hooks use `Error::HookVetoed` for this.
+pub trait Hook {
+    /// Invoked before opening the devlog entry at `path` in a text editor.
+    fn before_edit(&self, _w: &mut dyn Write, _path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Invoked after the text editor program exits.
+    fn after_edit(&self, _w: &mut dyn Write, _path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Invoked after editing the devlog entry at `path` changed its tasks.
+    fn tasks_changed(
+        &self,
+        _w: &mut dyn Write,
+        _path: &Path,
+        _diff: &EntryDiff,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Invoked before rolling over the devlog entry at `path`.
+    fn before_rollover(&self, _w: &mut dyn Write, _path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Invoked after rolling over the devlog entry at `old_path` to `new_path`.
+    fn after_rollover(
+        &self,
+        _w: &mut dyn Write,
+        _old_path: &Path,
+        _new_path: &Path,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A `Hook` that executes the hook files in a repository's `hooks` directory.
+/// Hook files cannot veto operations; a non-zero exit status is reported but otherwise ignored.
+pub struct FileHooks {
+    repo_dir: PathBuf,
+}
+
+impl FileHooks {
+    /// Creates a provider for the hook files in the specified repository.
+    pub fn new(repo_dir: &Path) -> FileHooks {
+        FileHooks {
+            repo_dir: repo_dir.to_path_buf(),
+        }
+    }
+}
+
+impl Hook for FileHooks {
+    fn before_edit(&self, w: &mut dyn Write, path: &Path) -> Result<(), Error> {
+        execute_hook_file(
+            w,
+            &self.repo_dir,
+            &HookType::BeforeEdit,
+            &[path.as_os_str()],
+        )
+    }
+
+    fn after_edit(&self, w: &mut dyn Write, path: &Path) -> Result<(), Error> {
+        execute_hook_file(w, &self.repo_dir, &HookType::AfterEdit, &[path.as_os_str()])
+    }
+
+    fn before_rollover(&self, w: &mut dyn Write, path: &Path) -> Result<(), Error> {
+        execute_hook_file(
+            w,
+            &self.repo_dir,
+            &HookType::BeforeRollover,
+            &[path.as_os_str()],
+        )
+    }
+
+    fn after_rollover(
+        &self,
+        w: &mut dyn Write,
+        old_path: &Path,
+        new_path: &Path,
+    ) -> Result<(), Error> {
+        execute_hook_file(
+            w,
+            &self.repo_dir,
+            &HookType::AfterRollover,
+            &[old_path.as_os_str(), new_path.as_os_str()],
+        )
+    }
+}
+
+/// An ordered collection of hooks.
+/// Hooks are invoked in the order they were registered,
+/// and invocation stops at the first hook that returns an error.
+#[derive(Default)]
+pub struct HookRegistry {
+    hooks: Vec<Box<dyn Hook>>,
+}
+
+impl HookRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> HookRegistry {
+        HookRegistry { hooks: Vec::new() }
+    }
+
+    /// Adds a hook to the end of the registry.
+    pub fn register(&mut self, hook: Box<dyn Hook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Returns the number of registered hooks.
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    /// Returns whether no hooks are registered.
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+impl Hook for HookRegistry {
+    fn before_edit(&self, w: &mut dyn Write, path: &Path) -> Result<(), Error> {
+        self.hooks.iter().try_for_each(|h| h.before_edit(w, path))
+    }
+
+    fn after_edit(&self, w: &mut dyn Write, path: &Path) -> Result<(), Error> {
+        self.hooks.iter().try_for_each(|h| h.after_edit(w, path))
+    }
+
+    fn tasks_changed(&self, w: &mut dyn Write, path: &Path, diff: &EntryDiff) -> Result<(), Error> {
+        self.hooks
+            .iter()
+            .try_for_each(|h| h.tasks_changed(w, path, diff))
+    }
+
+    fn before_rollover(&self, w: &mut dyn Write, path: &Path) -> Result<(), Error> {
+        self.hooks
+            .iter()
+            .try_for_each(|h| h.before_rollover(w, path))
+    }
+
+    fn after_rollover(
+        &self,
+        w: &mut dyn Write,
+        old_path: &Path,
+        new_path: &Path,
+    ) -> Result<(), Error> {
+        self.hooks
+            .iter()
+            .try_for_each(|h| h.after_rollover(w, old_path, new_path))
+    }
+}
+
 /// Executes a hook command if available.
 /// If no hook is available (e.g. because the hook file is non-executable)
 /// then this is a no-op.
@@ -90,11 +328,20 @@ pub fn execute_hook<W: Write>(
     hook_type: &HookType,
     args: &[&OsStr],
 ) -> Result<(), Error> {
-    if let Some(mut cmd) = hook_cmd(config.repo_dir(), hook_type)? {
+    execute_hook_file(w, config.repo_dir(), hook_type, args)
+}
+
+fn execute_hook_file<W: Write + ?Sized>(
+    w: &mut W,
+    repo_dir: &Path,
+    hook_type: &HookType,
+    args: &[&OsStr],
+) -> Result<(), Error> {
+    if let Some(mut cmd) = hook_cmd(repo_dir, hook_type)? {
         let status = cmd.args(args).status()?;
         if !status.success() {
             if let Some(code) = status.code() {
-                write!(w, "{} hook exited with status {}\n", hook_type.name(), code)?;
+                writeln!(w, "{} hook exited with status {}", hook_type.name(), code)?;
             }
         }
     }
@@ -103,12 +350,19 @@ pub fn execute_hook<W: Write>(
 
 /// Retrieves the executable hook command if it exists.
 pub fn hook_cmd(repo_dir: &Path, hook_type: &HookType) -> Result<Option<Command>, Error> {
+    let p = hook_path(repo_dir, hook_type);
+    is_valid(&p).map(|valid| if valid { Some(Command::new(&p)) } else { None })
+}
+
+/// Returns the path to the hook file, which may or may not exist.
+pub fn hook_path(repo_dir: &Path, hook_type: &HookType) -> PathBuf {
     let mut p = hook_dir_path(repo_dir);
     p.push(hook_type.name());
-    is_valid(&p).map(|valid| if valid { Some(Command::new(&p)) } else { None })
+    p
 }
 
-fn hook_dir_path(repo_dir: &Path) -> PathBuf {
+/// Returns the path to the directory of hook files, which may or may not exist.
+pub fn hook_dir_path(repo_dir: &Path) -> PathBuf {
     let mut p = repo_dir.to_path_buf();
     p.push(HOOK_DIR_NAME);
     p
@@ -118,7 +372,8 @@ fn is_valid(p: &Path) -> Result<bool, Error> {
     Ok(p.exists() && is_executable(p)?)
 }
 
-fn is_executable(p: &Path) -> Result<bool, Error> {
+/// Checks whether any of the execute permission bits are set on the file at `p`.
+pub fn is_executable(p: &Path) -> Result<bool, Error> {
     p.metadata()
         .map(|metadata| {
             let perm = metadata.permissions();
@@ -130,9 +385,11 @@ fn is_executable(p: &Path) -> Result<bool, Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::{create_dir, set_permissions, File, Permissions};
+    use std::cell::RefCell;
+    use std::fs::{create_dir, remove_file, File, Permissions};
     use std::io::Read;
     use std::os::unix::fs::PermissionsExt;
+    use std::rc::Rc;
     use tempfile::tempdir;
 
     fn create_hook_dir(repo_dir: &Path) {
@@ -148,6 +405,7 @@ mod tests {
 
         let mut f = OpenOptions::new()
             .create(true)
+            .truncate(true)
             .write(true)
             .open(&p)
             .unwrap();
@@ -188,6 +446,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_init_hooks_rerun() {
+        let repo_dir = tempdir().unwrap();
+        let created = init_hooks(repo_dir.path()).unwrap();
+        assert_eq!(created, ALL_HOOK_TYPES);
+
+        // Simulate a hook type added after the repository was created
+        remove_file(hook_path(repo_dir.path(), &HookType::AfterRollover)).unwrap();
+        let created = init_hooks(repo_dir.path()).unwrap();
+        assert_eq!(created, vec![HookType::AfterRollover]);
+
+        let created = init_hooks(repo_dir.path()).unwrap();
+        assert!(created.is_empty());
+    }
+
     #[test]
     fn test_init_hooks_some_already_exist() {
         let repo_dir = tempdir().unwrap();
@@ -256,4 +529,143 @@ mod tests {
         let status = cmd.status().unwrap();
         assert!(status.success())
     }
+
+    #[test]
+    fn test_from_name() {
+        for hook_type in ALL_HOOK_TYPES {
+            assert_eq!(HookType::from_name(&hook_type.name()), Some(*hook_type));
+        }
+        assert_eq!(HookType::from_name("before-lunch"), None);
+    }
+
+    #[test]
+    fn test_sample_args() {
+        let p = LogPath::new(Path::new("/foo"), 3);
+        assert_eq!(
+            HookType::BeforeEdit.sample_args(&p).unwrap(),
+            vec![PathBuf::from("/foo/000000003.devlog")]
+        );
+        assert_eq!(
+            HookType::AfterRollover.sample_args(&p).unwrap(),
+            vec![
+                PathBuf::from("/foo/000000003.devlog"),
+                PathBuf::from("/foo/000000004.devlog")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_enable_and_disable_hook() {
+        let repo_dir = tempdir().unwrap();
+        let hook_type = HookType::BeforeRollover;
+        assert_eq!(
+            hook_state(repo_dir.path(), &hook_type).unwrap(),
+            HookState::Missing
+        );
+
+        // Enabling a missing hook creates it from the template
+        enable_hook(repo_dir.path(), &hook_type).unwrap();
+        assert_eq!(
+            hook_state(repo_dir.path(), &hook_type).unwrap(),
+            HookState::Enabled
+        );
+        assert!(hook_cmd(repo_dir.path(), &hook_type).unwrap().is_some());
+
+        disable_hook(repo_dir.path(), &hook_type).unwrap();
+        assert_eq!(
+            hook_state(repo_dir.path(), &hook_type).unwrap(),
+            HookState::Disabled
+        );
+        assert!(hook_cmd(repo_dir.path(), &hook_type).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_disable_missing_hook() {
+        let repo_dir = tempdir().unwrap();
+        disable_hook(repo_dir.path(), &HookType::AfterEdit).unwrap();
+        assert_eq!(
+            hook_state(repo_dir.path(), &HookType::AfterEdit).unwrap(),
+            HookState::Missing
+        );
+    }
+
+    struct RecordingHook {
+        name: &'static str,
+        calls: Rc<RefCell<Vec<String>>>,
+        veto: bool,
+    }
+
+    impl Hook for RecordingHook {
+        fn before_edit(&self, _w: &mut dyn Write, path: &Path) -> Result<(), Error> {
+            self.calls
+                .borrow_mut()
+                .push(format!("{} before-edit {}", self.name, path.display()));
+            if self.veto {
+                Err(Error::HookVetoed(self.name.to_string()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_registry_invokes_hooks_in_order() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut registry = HookRegistry::new();
+        for name in &["first", "second"] {
+            registry.register(Box::new(RecordingHook {
+                name,
+                calls: calls.clone(),
+                veto: false,
+            }));
+        }
+        assert_eq!(registry.len(), 2);
+
+        let mut out = Vec::new();
+        registry.before_edit(&mut out, Path::new("/foo")).unwrap();
+        registry.after_edit(&mut out, Path::new("/foo")).unwrap();
+        assert_eq!(
+            *calls.borrow(),
+            vec!["first before-edit /foo", "second before-edit /foo"]
+        );
+    }
+
+    #[test]
+    fn test_registry_stops_at_veto() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut registry = HookRegistry::new();
+        registry.register(Box::new(RecordingHook {
+            name: "veto",
+            calls: calls.clone(),
+            veto: true,
+        }));
+        registry.register(Box::new(RecordingHook {
+            name: "never",
+            calls: calls.clone(),
+            veto: false,
+        }));
+
+        let mut out = Vec::new();
+        match registry.before_edit(&mut out, Path::new("/foo")) {
+            Err(Error::HookVetoed(reason)) => assert_eq!(reason, "veto"),
+            _ => panic!("expected HookVetoed"),
+        }
+        assert_eq!(*calls.borrow(), vec!["veto before-edit /foo"]);
+    }
+
+    #[test]
+    fn test_file_hooks() {
+        let repo_dir = tempdir().unwrap();
+        create_hook_dir(repo_dir.path());
+        create_hook_file(repo_dir.path(), HookType::BeforeEdit, true);
+
+        let mut out = Vec::new();
+        let hooks = FileHooks::new(repo_dir.path());
+        hooks.before_edit(&mut out, Path::new("/foo")).unwrap();
+
+        // Missing hook files are a no-op
+        hooks
+            .after_rollover(&mut out, Path::new("/foo"), Path::new("/bar"))
+            .unwrap();
+    }
 }
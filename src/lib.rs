@@ -5,20 +5,41 @@
 //!
 //! This library provides a programmatic interface to the functionality of the devlog tool.
 
+pub mod agenda;
+pub mod archive;
+pub mod atomic;
+pub mod backlog;
 pub mod config;
+pub mod date;
+pub mod deferred;
+pub mod diff;
 pub mod editor;
 pub mod error;
 pub mod file;
+pub mod focus;
+pub mod fsck;
 pub mod hook;
+pub mod lint;
+pub mod lock;
+pub mod lsp;
 pub mod path;
+pub mod renumber;
 pub mod repository;
 pub mod rollover;
+pub mod serve;
+pub mod settings;
 pub mod status;
+pub mod status_config;
+pub mod sync;
 pub mod task;
+pub mod template;
+pub mod timelog;
+pub mod tui;
 
 pub use config::Config;
+pub use diff::EntryDiff;
 pub use error::Error;
-pub use file::LogFile;
+pub use file::{LogFile, ParseOptions};
 pub use path::LogPath;
 pub use repository::LogRepository;
-pub use task::{Task, TaskStatus};
+pub use task::{CustomStatus, Priority, Task, TaskStatus};
@@ -0,0 +1,260 @@
+//! Detect lines in devlog entries that look like tasks, but are not parsed as tasks
+//! the way the user probably intended, and suggest fixes for them.
+
+use crate::file::{LogFile, ParseOptions};
+use crate::task::TaskStatus;
+use std::collections::HashSet;
+
+/// A suspicious line in a devlog entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    line_number: usize,
+    message: String,
+    suggestion: Option<String>,
+}
+
+impl Problem {
+    /// The line number of the suspicious line, starting from one.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// Describes what is wrong with the line.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// A replacement for the line that fixes the problem, if there is one.
+    pub fn suggestion(&self) -> Option<&str> {
+        self.suggestion.as_deref()
+    }
+}
+
+/// Checks the contents of a devlog entry for suspicious lines.
+/// Indented tasks are only reported if `options` parses them as neither subtasks
+/// nor part of the body of the task above.
+pub fn check(contents: &str, options: &ParseOptions) -> Vec<Problem> {
+    let f = LogFile::parse_with(contents, options);
+    let mut task_lines: HashSet<usize> = HashSet::new();
+    for (i, t) in f.tasks().iter().enumerate() {
+        let line = f.line_number(i);
+        if t.depth() > 0 {
+            task_lines.insert(line);
+        }
+        task_lines.extend(line + 1..=line + t.body().len());
+    }
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(i, _)| !task_lines.contains(&(i + 1)))
+        .filter_map(|(i, line)| {
+            check_line(line, options).map(|(message, suggestion)| Problem {
+                line_number: i + 1,
+                message,
+                suggestion,
+            })
+        })
+        .collect()
+}
+
+/// Rewrites the suspicious lines in the contents of a devlog entry using their suggestions.
+/// Returns the fixed contents and the number of lines that were fixed.
+/// Problems without a suggestion are left for the user to fix.
+pub fn fix(contents: &str, options: &ParseOptions) -> (String, usize) {
+    let mut problems = check(contents, options).into_iter().peekable();
+    let mut num_fixed = 0;
+    let mut s = String::with_capacity(contents.len());
+    for (i, line) in contents.lines().enumerate() {
+        let problem = problems.next_if(|p| p.line_number() == i + 1);
+        match problem.as_ref().and_then(|p| p.suggestion()) {
+            Some(suggestion) => {
+                s.push_str(suggestion);
+                num_fixed += 1;
+            }
+            None => s.push_str(line),
+        }
+        s.push('\n');
+    }
+    if !contents.ends_with('\n') {
+        s.pop();
+    }
+    (s, num_fixed)
+}
+
+fn check_line(line: &str, options: &ParseOptions) -> Option<(String, Option<String>)> {
+    let trimmed = line.trim_start();
+    if trimmed.len() < line.len() && starts_with_marker(trimmed, options) {
+        return Some((
+            "Indented task is not parsed as a task; remove the leading whitespace".to_string(),
+            Some(trimmed.trim_end().to_string()),
+        ));
+    }
+
+    if let Some(marker) = line.chars().next() {
+        let rest = &line[marker.len_utf8()..];
+        if options.status_marked(marker).is_some() && looks_like_task(marker, rest) {
+            // Not fixed automatically, since prose can start the same way.
+            return Some((
+                format!("Task marker \"{}\" should be followed by a space", marker),
+                None,
+            ));
+        }
+    }
+
+    for (prefix, status) in LOOKALIKE_MARKERS {
+        // A custom status may use a lookalike as its marker.
+        if starts_with_marker(prefix, options) {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix(prefix) {
+            let content = rest.trim();
+            if !content.is_empty() {
+                return Some((
+                    format!(
+                        "\"{}\" is not a task marker; use \"{}\" for {} tasks",
+                        prefix.trim(),
+                        status.marker(),
+                        status.display_name()
+                    ),
+                    Some(format!("{} {}", status.marker(), content)),
+                ));
+            }
+        }
+    }
+    None
+}
+
+/// Checkboxes that people commonly use for tasks in other formats, and the statuses they mean.
+/// Prefixes that are also common in prose, like "x " or "o ", are not included.
+const LOOKALIKE_MARKERS: &[(&str, TaskStatus)] = &[
+    ("[ ] ", TaskStatus::ToDo),
+    ("[x] ", TaskStatus::Done),
+    ("[X] ", TaskStatus::Done),
+];
+
+/// Whether `rest`, which follows a task marker without a space, reads like the start of a task
+/// rather than prose such as "-v enables logging", "+1 from Sam", or "*really* important".
+fn looks_like_task(marker: char, rest: &str) -> bool {
+    let word = rest.split(char::is_whitespace).next().unwrap_or("");
+    word.starts_with(|c: char| c.is_uppercase())
+        && word.chars().any(|c| c.is_lowercase())
+        && !word.ends_with(marker)
+}
+
+fn starts_with_marker(s: &str, options: &ParseOptions) -> bool {
+    let mut chars = s.chars();
+    let marker = chars.next().and_then(|c| options.status_marked(c));
+    let space = chars.next().is_some_and(|c| c.is_whitespace());
+    marker.is_some() && space
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::ALL_STATUSES;
+    use crate::task::CustomStatus;
+
+    #[test]
+    fn test_no_problems() {
+        let contents = "Notes\n* Foo\n^ Bar\n\n+ Baz\n    indented notes\n";
+        assert!(check(contents, &ParseOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_indented_task() {
+        let problems = check("Notes\n  * Foo\n\t+ Bar  \n", &ParseOptions::default());
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].line_number(), 2);
+        assert_eq!(problems[0].suggestion(), Some("* Foo"));
+        assert_eq!(problems[1].line_number(), 3);
+        assert_eq!(problems[1].suggestion(), Some("+ Bar"));
+    }
+
+    #[test]
+    fn test_missing_space() {
+        let problems = check("*Foo\n^Bar baz \n--\n**\n", &ParseOptions::default());
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].line_number(), 1);
+        assert_eq!(problems[1].line_number(), 2);
+        assert_eq!(
+            problems[0].message(),
+            "Task marker \"*\" should be followed by a space"
+        );
+        assert!(problems.iter().all(|p| p.suggestion().is_none()));
+    }
+
+    #[test]
+    fn test_prose_is_not_a_problem() {
+        let contents = "Notes\n-v enables verbose logging\n+1 from Sam\nx = 5 in the repro\n\
+            *really* important\n*Really* important\n-DFOO=1 in the build\no well\nX marks the spot\n";
+        assert!(check(contents, &ParseOptions::default()).is_empty());
+        assert_eq!(
+            fix(contents, &ParseOptions::default()),
+            (contents.to_string(), 0)
+        );
+    }
+
+    #[test]
+    fn test_lookalike_markers() {
+        let problems = check("[ ] Bar\n[x] Baz\n[X] Boo\n[ ]\n", &ParseOptions::default());
+        let suggestions: Vec<Option<&str>> = problems.iter().map(|p| p.suggestion()).collect();
+        assert_eq!(
+            suggestions,
+            vec![Some("* Bar"), Some("+ Baz"), Some("+ Boo")]
+        );
+        assert_eq!(
+            problems[1].message(),
+            "\"[x]\" is not a task marker; use \"+\" for Done tasks"
+        );
+    }
+
+    #[test]
+    fn test_fix() {
+        let (s, n) = fix(
+            "Notes\n  * Foo\n[x] Bar\n^ Baz\n*Boo\n",
+            &ParseOptions::default(),
+        );
+        assert_eq!(s, "Notes\n* Foo\n+ Bar\n^ Baz\n*Boo\n");
+        assert_eq!(n, 2);
+
+        let (s, n) = fix("Notes\n  *  Foo", &ParseOptions::default());
+        assert_eq!(s, "Notes\n*  Foo");
+        assert_eq!(n, 1);
+    }
+
+    #[test]
+    fn test_subtasks_are_not_problems() {
+        let contents = "* Foo\n    * Bar\nNotes\n    * Baz\n";
+        let options = ParseOptions::default().with_subtasks(true);
+        let problems = check(contents, &options);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line_number(), 4);
+        assert_eq!(check(contents, &ParseOptions::default()).len(), 1);
+    }
+
+    #[test]
+    fn test_task_bodies_are_not_problems() {
+        let contents = "* Deploy\n    - step one\n    + step two\n";
+        assert!(check(contents, &ParseOptions::default()).is_empty());
+        assert_eq!(
+            fix(contents, &ParseOptions::default()),
+            (contents.to_string(), 0)
+        );
+
+        let problems = check("* Deploy\n\n    - step one\n", &ParseOptions::default());
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line_number(), 3);
+    }
+
+    #[test]
+    fn test_custom_markers() {
+        let cancelled = CustomStatus::new("cancelled", 'x', "Cancelled", true, false).into_status();
+        let mut statuses = ALL_STATUSES.to_vec();
+        statuses.push(cancelled);
+        let options = ParseOptions::default().with_statuses(statuses);
+        let problems = check("x Foo\nxBar\nNotes\n  x Baz\n", &options);
+        let lines: Vec<usize> = problems.iter().map(|p| p.line_number()).collect();
+        assert_eq!(lines, vec![2, 4]);
+        assert_eq!(problems[1].suggestion(), Some("x Baz"));
+    }
+}
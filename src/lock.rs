@@ -0,0 +1,261 @@
+//! Advisory lock that prevents devlog commands from modifying a repository concurrently.
+//!
+//! Commands that modify the repository hold the lock by creating a `devlog.lock` file
+//! containing their process ID.  The file is removed when the lock is released.
+//! If the process that created the file is no longer running, for example because
+//! its terminal was closed during `devlog edit`, the lock is stale and is taken over.
+//! The lock is advisory: it does not stop other programs from changing the files.
+
+use crate::error::Error;
+use std::fs::{create_dir_all, read_to_string, remove_file, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread::sleep;
+use std::time::Duration;
+
+const LOCK_FILE_NAME: &str = "devlog.lock";
+
+/// How long to sleep between attempts to acquire a lock held by another process.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Returns the path to the lock file, which exists only while the repository is locked.
+pub fn lock_path(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(LOCK_FILE_NAME)
+}
+
+/// Returns the process ID recorded in the lock file, if the repository is locked.
+pub fn holder(repo_dir: &Path) -> Option<u32> {
+    read_to_string(lock_path(repo_dir))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+}
+
+/// Exclusive lock on a devlog repository, released when dropped.
+#[derive(Debug)]
+pub struct RepoLock {
+    path: PathBuf,
+}
+
+impl RepoLock {
+    /// Acquires the lock, creating the repository directory if necessary.
+    /// A stale lock left by a process that is no longer running is removed.
+    /// Fails with `Error::RepositoryLocked` if another running command holds the lock.
+    pub fn acquire(repo_dir: &Path) -> Result<RepoLock, Error> {
+        create_dir_all(repo_dir)?;
+        let path = lock_path(repo_dir);
+        match RepoLock::create(&path) {
+            Err(Error::RepositoryLocked(_)) if remove_stale_lock(repo_dir)? => {
+                RepoLock::create(&path)
+            }
+            result => result,
+        }
+    }
+
+    fn create(path: &Path) -> Result<RepoLock, Error> {
+        let path = path.to_path_buf();
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut f) => {
+                // Hold the lock before writing, so the file is removed even if the write fails.
+                let lock = RepoLock { path };
+                writeln!(f, "{}", process::id())?;
+                Ok(lock)
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                Err(Error::RepositoryLocked(path))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Acquires the lock, waiting for as long as another running command holds it.
+    pub fn wait(repo_dir: &Path) -> Result<RepoLock, Error> {
+        loop {
+            match RepoLock::acquire(repo_dir) {
+                Err(Error::RepositoryLocked(_)) => sleep(POLL_INTERVAL),
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Removes the lock file if the process recorded in it is no longer running.
+/// Returns whether the lock was removed.
+fn remove_stale_lock(repo_dir: &Path) -> Result<bool, Error> {
+    let pid = match holder(repo_dir) {
+        // A lock without a process ID may still be being written by its holder.
+        Some(pid) if !is_running(pid) => pid,
+        _ => return Ok(false),
+    };
+    // Another command may have removed the stale lock and taken the lock since it was read.
+    if holder(repo_dir) != Some(pid) {
+        return Ok(false);
+    }
+    match remove_file(lock_path(repo_dir)) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(true),
+    }
+}
+
+fn is_running(pid: u32) -> bool {
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+    // Signal 0 checks whether the process exists without sending anything.
+    // EPERM means the process exists but belongs to another user.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        // Nothing useful can be done if this fails, and the error message
+        // for a held lock tells the user how to remove it.
+        let _ = remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::repository::LogRepository;
+    use crate::rollover::rollover;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_acquire_and_release() {
+        let dir = tempdir().unwrap();
+        let lock = RepoLock::acquire(dir.path()).unwrap();
+        assert_eq!(holder(dir.path()), Some(process::id()));
+
+        match RepoLock::acquire(dir.path()) {
+            Err(Error::RepositoryLocked(p)) => assert_eq!(p, lock_path(dir.path())),
+            _ => panic!("expected RepositoryLocked"),
+        }
+
+        drop(lock);
+        assert!(!lock_path(dir.path()).exists());
+        assert_eq!(holder(dir.path()), None);
+        RepoLock::acquire(dir.path()).unwrap();
+    }
+
+    #[test]
+    fn test_acquire_creates_dir() {
+        let dir = tempdir().unwrap();
+        let repo_dir = dir.path().join("devlogs");
+        let _lock = RepoLock::acquire(&repo_dir).unwrap();
+        assert!(lock_path(&repo_dir).is_file());
+    }
+
+    #[test]
+    fn test_wait_for_release() {
+        let dir = tempdir().unwrap();
+        let lock = RepoLock::acquire(dir.path()).unwrap();
+        let repo_dir = dir.path().to_path_buf();
+        let waiter = thread::spawn(move || RepoLock::wait(&repo_dir).map(|_| ()));
+        sleep(POLL_INTERVAL * 4);
+        assert!(!waiter.is_finished());
+        drop(lock);
+        waiter.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_stale_lock() {
+        let dir = tempdir().unwrap();
+        let mut child = process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        std::fs::write(lock_path(dir.path()), format!("{}\n", dead_pid)).unwrap();
+
+        let lock = RepoLock::acquire(dir.path()).unwrap();
+        assert_eq!(holder(dir.path()), Some(process::id()));
+        drop(lock);
+
+        std::fs::write(lock_path(dir.path()), format!("{}\n", dead_pid)).unwrap();
+        RepoLock::wait(dir.path()).unwrap();
+        assert!(!lock_path(dir.path()).exists());
+    }
+
+    #[test]
+    fn test_lock_held_by_running_process() {
+        let dir = tempdir().unwrap();
+        let mut child = process::Command::new("sleep").arg("10").spawn().unwrap();
+        std::fs::write(lock_path(dir.path()), format!("{}\n", child.id())).unwrap();
+        let result = RepoLock::acquire(dir.path());
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(matches!(result, Err(Error::RepositoryLocked(_))));
+        assert!(lock_path(dir.path()).exists());
+    }
+
+    #[test]
+    fn test_concurrent_acquire() {
+        let dir = tempdir().unwrap();
+        let n = 8;
+        let barrier = Arc::new(Barrier::new(n));
+        let holders = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..n)
+            .map(|_| {
+                let repo_dir = dir.path().to_path_buf();
+                let barrier = barrier.clone();
+                let holders = holders.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    match RepoLock::acquire(&repo_dir) {
+                        Ok(_lock) => {
+                            assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0);
+                            sleep(POLL_INTERVAL);
+                            holders.fetch_sub(1, Ordering::SeqCst);
+                            true
+                        }
+                        Err(Error::RepositoryLocked(_)) => false,
+                        Err(err) => panic!("unexpected error {:?}", err),
+                    }
+                })
+            })
+            .collect();
+        let acquired = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|&acquired| acquired)
+            .count();
+        assert!(acquired >= 1);
+        assert!(!lock_path(dir.path()).exists());
+    }
+
+    #[test]
+    fn test_concurrent_rollovers() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let first = repo.init().unwrap();
+        std::fs::write(first.path(), "* Foo\n").unwrap();
+
+        let n = 4;
+        let barrier = Arc::new(Barrier::new(n));
+        let handles: Vec<_> = (0..n)
+            .map(|_| {
+                let repo_dir = dir.path().to_path_buf();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let config = Config::new(&repo_dir, "");
+                    let repo = LogRepository::new(&repo_dir);
+                    barrier.wait();
+                    let _lock = RepoLock::wait(&repo_dir).unwrap();
+                    let latest = repo.latest().unwrap().unwrap();
+                    let mut out = Vec::new();
+                    rollover(&mut out, &config, &latest).unwrap().0.seq_num()
+                })
+            })
+            .collect();
+
+        let mut seq_nums: Vec<usize> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        seq_nums.sort_unstable();
+        assert_eq!(seq_nums, vec![2, 3, 4, 5]);
+        assert!(!lock_path(dir.path()).exists());
+    }
+}
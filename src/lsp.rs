@@ -0,0 +1,512 @@
+//! A language server for devlog entry files, using the Language Server Protocol over stdio.
+//!
+//! The server provides:
+//! * Diagnostics for lines that look like tasks, but are not parsed as tasks.
+//! * Code actions to change the status marker of a task, and to fix diagnostics.
+//! * Document symbols for tasks, grouped by status.
+//! * Completion for tags (like "#backend") used in open documents and recent entries.
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::file::{LogFile, ParseOptions};
+use crate::lint;
+use crate::repository::LogRepository;
+use crate::task::TaskStatus;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, Error as IOError, ErrorKind, Write};
+
+/// Number of recent devlog entries searched for tags to complete.
+const NUM_ENTRIES_FOR_TAGS: usize = 10;
+
+const TEXT_DOCUMENT_SYNC_FULL: u64 = 1;
+const SEVERITY_WARNING: u64 = 2;
+const SYMBOL_KIND_NAMESPACE: u64 = 3;
+const SYMBOL_KIND_EVENT: u64 = 24;
+const COMPLETION_KIND_KEYWORD: u64 = 14;
+const ERROR_PARSE: i64 = -32700;
+const ERROR_METHOD_NOT_FOUND: i64 = -32601;
+
+/// Runs the language server, reading messages from `r` and writing messages to `w`,
+/// until the client sends an "exit" notification or closes the input.
+pub fn run<R: BufRead, W: Write>(config: &Config, r: &mut R, w: &mut W) -> Result<(), Error> {
+    let mut server = Server::new(config);
+    while let Some(body) = read_message(r)? {
+        let replies = match serde_json::from_str::<Value>(&body) {
+            Ok(msg) => server.handle(&msg),
+            Err(_) => vec![error_response(Value::Null, ERROR_PARSE, "parse error")],
+        };
+        for reply in replies {
+            write_message(w, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn read_message<R: BufRead>(r: &mut R) -> Result<Option<String>, Error> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if r.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let len = content_length
+        .ok_or_else(|| IOError::new(ErrorKind::InvalidData, "missing Content-Length header"))?;
+    let mut body = vec![0; len];
+    r.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| Error::IOError(IOError::new(ErrorKind::InvalidData, e)))
+}
+
+fn write_message<W: Write>(w: &mut W, msg: &Value) -> Result<(), Error> {
+    let body = msg.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    w.flush()?;
+    Ok(())
+}
+
+fn response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error_response(id: Value, code: i64, msg: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": msg } })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+struct Server {
+    repo: LogRepository,
+    options: ParseOptions,
+    documents: HashMap<String, String>,
+    exited: bool,
+}
+
+impl Server {
+    fn new(config: &Config) -> Server {
+        Server {
+            repo: LogRepository::new(config.repo_dir()),
+            options: config.parse_options().clone(),
+            documents: HashMap::new(),
+            exited: false,
+        }
+    }
+
+    /// Handles a message from the client, returning messages to send back.
+    fn handle(&mut self, msg: &Value) -> Vec<Value> {
+        let method = msg["method"].as_str().unwrap_or("");
+        let params = &msg["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        match (method, msg.get("id").cloned()) {
+            ("initialize", Some(id)) => vec![response(id, capabilities())],
+            ("shutdown", Some(id)) => vec![response(id, Value::Null)],
+            ("exit", None) => {
+                self.exited = true;
+                vec![]
+            }
+            ("textDocument/didOpen", None) => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.to_string(), text.to_string());
+                vec![self.diagnostics(uri)]
+            }
+            ("textDocument/didChange", None) => {
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                vec![self.diagnostics(uri)]
+            }
+            ("textDocument/didClose", None) => {
+                self.documents.remove(uri);
+                vec![self.diagnostics(uri)]
+            }
+            ("textDocument/codeAction", Some(id)) => {
+                vec![response(id, self.code_actions(uri, params))]
+            }
+            ("textDocument/documentSymbol", Some(id)) => {
+                vec![response(id, self.document_symbols(uri))]
+            }
+            ("textDocument/completion", Some(id)) => {
+                vec![response(id, self.completions(uri, params))]
+            }
+            (_, Some(id)) if !method.is_empty() => {
+                vec![error_response(
+                    id,
+                    ERROR_METHOD_NOT_FOUND,
+                    "method not found",
+                )]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn document(&self, uri: &str) -> &str {
+        self.documents.get(uri).map(|s| s.as_str()).unwrap_or("")
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = self.document(uri);
+        let diagnostics: Vec<Value> = lint::check(text, &self.options)
+            .iter()
+            .map(|p| {
+                let line = p.line_number() - 1;
+                json!({
+                    "range": line_range(text, line),
+                    "severity": SEVERITY_WARNING,
+                    "source": "devlog",
+                    "message": p.message(),
+                })
+            })
+            .collect();
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    fn code_actions(&self, uri: &str, params: &Value) -> Value {
+        let text = self.document(uri);
+        let first = params["range"]["start"]["line"].as_u64().unwrap_or(0) as usize;
+        let last = params["range"]["end"]["line"].as_u64().unwrap_or(0) as usize;
+        let in_range = |line: usize| line >= first && line <= last;
+        let mut actions = Vec::new();
+
+        for p in lint::check(text, &self.options) {
+            let line = p.line_number() - 1;
+            if let (true, Some(fix)) = (in_range(line), p.suggestion()) {
+                actions.push(json!({
+                    "title": format!("Fix: {}", p.message()),
+                    "kind": "quickfix",
+                    "isPreferred": true,
+                    "edit": text_edit(uri, line_range(text, line), fix),
+                }));
+            }
+        }
+
+        let f = LogFile::parse_with(text, &self.options);
+        for (i, t) in f.tasks().iter().enumerate() {
+            let line = f.line_number(i) - 1;
+            if !in_range(line) {
+                continue;
+            }
+            let next = next_status(t.status());
+            let line_text = text.lines().nth(line).unwrap_or("");
+            let indent = utf16_len(&line_text[..line_text.len() - line_text.trim_start().len()]);
+            let marker_range = range(line, indent, line, indent + 1);
+            actions.push(json!({
+                "title": format!("Cycle status to {}", next.display_name()),
+                "kind": "refactor.rewrite",
+                "edit": text_edit(uri, marker_range.clone(), &next.marker().to_string()),
+            }));
+            for s in self.options.statuses() {
+                if *s != t.status() && *s != next {
+                    actions.push(json!({
+                        "title": format!("Mark as {}", s.display_name()),
+                        "kind": "refactor.rewrite",
+                        "edit": text_edit(uri, marker_range.clone(), &s.marker().to_string()),
+                    }));
+                }
+            }
+        }
+        Value::Array(actions)
+    }
+
+    fn document_symbols(&self, uri: &str) -> Value {
+        let text = self.document(uri);
+        let f = LogFile::parse_with(text, &self.options);
+        let mut groups = Vec::new();
+        for status in self.options.statuses() {
+            let lines: Vec<(usize, &str)> = f
+                .tasks()
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.status() == *status)
+                .map(|(i, t)| (f.line_number(i) - 1, t.content()))
+                .collect();
+            let (first, last) = match (lines.first(), lines.last()) {
+                (Some(first), Some(last)) => (first.0, last.0),
+                _ => continue,
+            };
+            let children: Vec<Value> = lines
+                .iter()
+                .map(|(line, content)| {
+                    json!({
+                        "name": if content.is_empty() { "(empty)" } else { content },
+                        "kind": SYMBOL_KIND_EVENT,
+                        "range": line_range(text, *line),
+                        "selectionRange": line_range(text, *line),
+                    })
+                })
+                .collect();
+            let end = line_range(text, last)["end"].clone();
+            groups.push(json!({
+                "name": status.display_name(),
+                "kind": SYMBOL_KIND_NAMESPACE,
+                "range": { "start": position(first, 0), "end": end },
+                "selectionRange": line_range(text, first),
+                "children": children,
+            }));
+        }
+        Value::Array(groups)
+    }
+
+    fn completions(&self, uri: &str, params: &Value) -> Value {
+        let text = self.document(uri);
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+        let line_text = text.lines().nth(line).unwrap_or("");
+        let before = &line_text[..byte_index(line_text, character)];
+        let word_start = before.rfind(char::is_whitespace).map_or(0, |i| {
+            i + before[i..].chars().next().map_or(1, |c| c.len_utf8())
+        });
+        let prefix = &before[word_start..];
+        if !prefix.starts_with('#') {
+            return json!([]);
+        }
+
+        let replace = range(line, utf16_len(&before[..word_start]), line, character);
+        let items: Vec<Value> = self
+            .known_tags()
+            .iter()
+            .filter(|tag| tag.starts_with(prefix) && *tag != prefix)
+            .map(|tag| {
+                json!({
+                    "label": tag,
+                    "kind": COMPLETION_KIND_KEYWORD,
+                    "textEdit": { "range": replace, "newText": tag },
+                })
+            })
+            .collect();
+        Value::Array(items)
+    }
+
+    fn known_tags(&self) -> BTreeSet<String> {
+        let mut texts: Vec<String> = self.documents.values().cloned().collect();
+        for p in self.repo.tail(NUM_ENTRIES_FOR_TAGS).unwrap_or_default() {
+            if let Ok(s) = std::fs::read_to_string(p.path()) {
+                texts.push(s);
+            }
+        }
+        let mut tags = BTreeSet::new();
+        for text in texts {
+            for t in LogFile::parse_with(&text, &self.options).tasks() {
+                tags.extend(t.tags().iter().map(|tag| tag.to_string()));
+            }
+        }
+        tags
+    }
+}
+
+fn capabilities() -> Value {
+    json!({
+        "capabilities": {
+            "textDocumentSync": TEXT_DOCUMENT_SYNC_FULL,
+            "codeActionProvider": true,
+            "documentSymbolProvider": true,
+            "completionProvider": { "triggerCharacters": ["#"] },
+        },
+        "serverInfo": { "name": "devlog", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+/// The status a task moves to when cycling its marker: to do, started, done, and back to to do.
+/// Blocked tasks become started again, and tasks with custom statuses become done or to do.
+fn next_status(s: TaskStatus) -> TaskStatus {
+    match s {
+        TaskStatus::ToDo => TaskStatus::Started,
+        TaskStatus::Started => TaskStatus::Done,
+        TaskStatus::Done => TaskStatus::ToDo,
+        TaskStatus::Blocked => TaskStatus::Started,
+        TaskStatus::Custom(_) if s.is_complete() => TaskStatus::ToDo,
+        TaskStatus::Custom(_) => TaskStatus::Done,
+    }
+}
+
+fn text_edit(uri: &str, range: Value, new_text: &str) -> Value {
+    json!({ "changes": { uri: [{ "range": range, "newText": new_text }] } })
+}
+
+fn position(line: usize, character: usize) -> Value {
+    json!({ "line": line, "character": character })
+}
+
+fn range(start_line: usize, start_char: usize, end_line: usize, end_char: usize) -> Value {
+    json!({ "start": position(start_line, start_char), "end": position(end_line, end_char) })
+}
+
+fn line_range(text: &str, line: usize) -> Value {
+    let len = text.lines().nth(line).map_or(0, utf16_len);
+    range(line, 0, line, len)
+}
+
+// Positions in the protocol count UTF-16 code units.
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+fn byte_index(s: &str, utf16_offset: usize) -> usize {
+    let mut count = 0;
+    for (i, c) in s.char_indices() {
+        if count >= utf16_offset {
+            return i;
+        }
+        count += c.len_utf16();
+    }
+    s.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    const URI: &str = "file:///tmp/000000001.devlog";
+    const TEXT: &str = "Notes\n* Foo #backend\n\n  ^ Bar\n+ Baz #bug\n- Boo\n";
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "text": text } },
+        }))
+    }
+
+    fn request(server: &mut Server, method: &str, params: Value) -> Value {
+        let mut replies = server.handle(&json!({
+            "jsonrpc": "2.0", "id": 1, "method": method, "params": params,
+        }));
+        assert_eq!(replies.len(), 1);
+        replies.remove(0)
+    }
+
+    fn with_server<F: FnOnce(&mut Server)>(f: F) {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path(), "");
+        f(&mut Server::new(&config));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        with_server(|server| {
+            let replies = open(server, TEXT);
+            assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+            let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0]["range"], range(3, 0, 3, 7));
+        });
+    }
+
+    #[test]
+    fn test_code_actions() {
+        with_server(|server| {
+            open(server, TEXT);
+            let params = json!({
+                "textDocument": { "uri": URI },
+                "range": range(1, 0, 3, 0),
+                "context": { "diagnostics": [] },
+            });
+            let reply = request(server, "textDocument/codeAction", params);
+            let actions = reply["result"].as_array().unwrap();
+            assert_eq!(actions[0]["kind"], "quickfix");
+            assert_eq!(actions[0]["edit"]["changes"][URI][0]["newText"], "^ Bar");
+            assert_eq!(actions[1]["title"], "Cycle status to In Progress");
+            assert_eq!(actions[1]["edit"]["changes"][URI][0]["newText"], "^");
+            assert_eq!(actions.len(), 4);
+        });
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        with_server(|server| {
+            open(server, TEXT);
+            let params = json!({ "textDocument": { "uri": URI } });
+            let reply = request(server, "textDocument/documentSymbol", params);
+            let groups = reply["result"].as_array().unwrap();
+            let names: Vec<&str> = groups.iter().map(|g| g["name"].as_str().unwrap()).collect();
+            assert_eq!(names, vec!["To Do", "Blocked", "Done"]);
+            assert_eq!(groups[0]["children"][0]["name"], "Foo #backend");
+        });
+    }
+
+    #[test]
+    fn test_tag_completion() {
+        with_server(|server| {
+            open(server, "* Foo #backend #bug\n* Bar #b");
+            let params = json!({
+                "textDocument": { "uri": URI },
+                "position": position(1, 8),
+            });
+            let reply = request(server, "textDocument/completion", params);
+            let labels: Vec<&str> = reply["result"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i["label"].as_str().unwrap())
+                .collect();
+            assert_eq!(labels, vec!["#backend", "#bug"]);
+            assert_eq!(reply["result"][0]["textEdit"]["range"], range(1, 6, 1, 8));
+
+            let params = json!({
+                "textDocument": { "uri": URI },
+                "position": position(1, 5),
+            });
+            let reply = request(server, "textDocument/completion", params);
+            assert_eq!(reply["result"], json!([]));
+        });
+    }
+
+    #[test]
+    fn test_unknown_method() {
+        with_server(|server| {
+            let reply = request(server, "workspace/symbol", json!({}));
+            assert_eq!(reply["error"]["code"], ERROR_METHOD_NOT_FOUND);
+        });
+    }
+
+    #[test]
+    fn test_run_over_stream() {
+        let dir = tempdir().unwrap();
+        let config = Config::new(dir.path(), "");
+        let mut input = Vec::new();
+        for msg in &[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ] {
+            write_message(&mut input, msg).unwrap();
+        }
+        let mut output = Vec::new();
+        run(&config, &mut Cursor::new(input), &mut output).unwrap();
+
+        let mut r = Cursor::new(output);
+        let init: Value = serde_json::from_str(&read_message(&mut r).unwrap().unwrap()).unwrap();
+        assert_eq!(
+            init["result"]["capabilities"]["documentSymbolProvider"],
+            true
+        );
+        let shutdown: Value =
+            serde_json::from_str(&read_message(&mut r).unwrap().unwrap()).unwrap();
+        assert_eq!(shutdown["id"], 2);
+        assert!(read_message(&mut r).unwrap().is_none());
+    }
+}
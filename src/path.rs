@@ -182,7 +182,7 @@ mod tests {
         let p = LogPath::new(&d, MAX_SEQ_NUM).next();
         match p {
             Err(Error::LogFileLimitExceeded) => {}
-            _ => assert!(false),
+            _ => panic!("expected LogFileLimitExceeded"),
         }
     }
 
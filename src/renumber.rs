@@ -0,0 +1,273 @@
+//! Renumber devlog entries to a contiguous sequence, closing gaps left by deleted entries.
+//!
+//! Entries keep their order, so the oldest entry gets the first sequence number.
+//! Renaming happens in two phases, first to temporary names and then to the new names,
+//! so an entry is never overwritten even when the new numbers overlap the old ones.
+//! If any rename fails, the entries that were already renamed are restored.
+
+use crate::archive;
+use crate::date;
+use crate::error::Error;
+use crate::path::{LogPath, MAX_SEQ_NUM};
+use crate::repository::LogRepository;
+use std::fs::rename;
+use std::io::{Error as IOError, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// A change of sequence number for one entry.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Renumbering {
+    from: LogPath,
+    to: LogPath,
+}
+
+impl Renumbering {
+    /// The entry's current path.
+    pub fn from(&self) -> &LogPath {
+        &self.from
+    }
+
+    /// The entry's path after renumbering.
+    pub fn to(&self) -> &LogPath {
+        &self.to
+    }
+}
+
+/// Plans how to renumber the entries in the repository so they are numbered
+/// contiguously from `start`.  Entries that already have the right number are omitted,
+/// so the plan is empty if there is nothing to do.
+/// Archived entries keep their numbers, so `start` must follow them.
+pub fn plan(repo: &LogRepository, start: usize) -> Result<Vec<Renumbering>, Error> {
+    if start == 0 {
+        return Err(Error::InvalidArg("start must be at least 1"));
+    }
+    if archive::max_seq_num(repo.path())?.is_some_and(|max| start <= max) {
+        return Err(Error::InvalidArg(
+            "start must be greater than the sequence numbers of archived entries",
+        ));
+    }
+    let mut paths = repo.list()?;
+    paths.sort();
+    if start - 1 > MAX_SEQ_NUM - paths.len() {
+        return Err(Error::LogFileLimitExceeded);
+    }
+    let renumberings = paths
+        .into_iter()
+        .enumerate()
+        .filter(|(i, p)| p.seq_num() != start + i)
+        .map(|(i, from)| Renumbering {
+            to: LogPath::new(repo.path(), start + i),
+            from,
+        })
+        .collect();
+    Ok(renumberings)
+}
+
+/// Renames the entries as planned.
+/// If a rename fails, the entries are restored to their original names and the error is returned.
+pub fn apply(renumberings: &[Renumbering]) -> Result<(), Error> {
+    apply_with(renumberings, |from, to| rename(from, to))
+}
+
+fn apply_with<F>(renumberings: &[Renumbering], mut rename_fn: F) -> Result<(), Error>
+where
+    F: FnMut(&Path, &Path) -> Result<(), IOError>,
+{
+    let temp_paths: Vec<PathBuf> = renumberings.iter().map(|r| temp_path(r.from())).collect();
+    let steps = renumberings
+        .iter()
+        .zip(&temp_paths)
+        .map(|(r, tmp)| (r.from().path(), tmp.as_path()))
+        .chain(
+            renumberings
+                .iter()
+                .zip(&temp_paths)
+                .map(|(r, tmp)| (tmp.as_path(), r.to().path())),
+        );
+
+    let mut done: Vec<(&Path, &Path)> = Vec::new();
+    let rollback = |done: &[(&Path, &Path)]| {
+        // Best effort: a failure here leaves the file at its temporary name,
+        // which `devlog fsck` reports.
+        for (from, to) in done.iter().rev() {
+            let _ = rename(to, from);
+        }
+    };
+    for (from, to) in steps {
+        let result = if to.exists() {
+            Err(IOError::new(
+                ErrorKind::AlreadyExists,
+                format!("{:?} already exists", to),
+            ))
+        } else {
+            rename_fn(from, to)
+        };
+        if let Err(err) = result {
+            rollback(&done);
+            return Err(err.into());
+        }
+        done.push((from, to));
+    }
+
+    if let Some(repo_dir) = renumberings.first().and_then(|r| r.from().path().parent()) {
+        let renumbered: Vec<(usize, usize)> = renumberings
+            .iter()
+            .map(|r| (r.from().seq_num(), r.to().seq_num()))
+            .collect();
+        if let Err(err) = date::renumber_entry_dates(repo_dir, &renumbered) {
+            rollback(&done);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// A hidden name for the entry during renumbering, which `LogRepository::list` ignores.
+fn temp_path(p: &LogPath) -> PathBuf {
+    let name = p
+        .path()
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    p.path().with_file_name(format!(".{}.renumber.tmp", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{read_dir, read_to_string, write};
+    use tempfile::tempdir;
+
+    fn create_entries(repo: &LogRepository, seq_nums: &[usize]) {
+        for &seq in seq_nums {
+            let p = LogPath::new(repo.path(), seq);
+            write(p.path(), format!("entry {}\n", seq)).unwrap();
+        }
+    }
+
+    /// Returns each entry's sequence number and contents, which record its original number.
+    fn entries(repo: &LogRepository) -> Vec<(usize, String)> {
+        let mut paths = repo.list().unwrap();
+        paths.sort();
+        paths
+            .iter()
+            .map(|p| (p.seq_num(), read_to_string(p.path()).unwrap()))
+            .collect()
+    }
+
+    fn mapping(renumberings: &[Renumbering]) -> Vec<(usize, usize)> {
+        renumberings
+            .iter()
+            .map(|r| (r.from().seq_num(), r.to().seq_num()))
+            .collect()
+    }
+
+    #[test]
+    fn test_close_gaps() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        create_entries(&repo, &[1, 3, 4, 9]);
+
+        let renumberings = plan(&repo, 1).unwrap();
+        assert_eq!(mapping(&renumberings), vec![(3, 2), (4, 3), (9, 4)]);
+        apply(&renumberings).unwrap();
+        assert_eq!(
+            entries(&repo),
+            vec![
+                (1, "entry 1\n".to_string()),
+                (2, "entry 3\n".to_string()),
+                (3, "entry 4\n".to_string()),
+                (4, "entry 9\n".to_string()),
+            ]
+        );
+        assert!(plan(&repo, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_renumber_from_offset() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        create_entries(&repo, &[1, 2, 3]);
+
+        // The new numbers overlap the old ones.
+        let renumberings = plan(&repo, 2).unwrap();
+        assert_eq!(mapping(&renumberings), vec![(1, 2), (2, 3), (3, 4)]);
+        apply(&renumberings).unwrap();
+        assert_eq!(
+            entries(&repo),
+            vec![
+                (2, "entry 1\n".to_string()),
+                (3, "entry 2\n".to_string()),
+                (4, "entry 3\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_start() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        create_entries(&repo, &[1, 2]);
+        assert!(matches!(plan(&repo, 0), Err(Error::InvalidArg(_))));
+        assert!(matches!(
+            plan(&repo, MAX_SEQ_NUM),
+            Err(Error::LogFileLimitExceeded)
+        ));
+        assert_eq!(mapping(&plan(&repo, MAX_SEQ_NUM - 1).unwrap()).len(), 2);
+    }
+
+    #[test]
+    fn test_start_after_archived_entries() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        create_entries(&repo, &[1, 2, 5, 6]);
+        let paths = archive::select(&repo, archive::Cutoff::SeqNum(3)).unwrap();
+        archive::archive(&repo, &paths).unwrap();
+
+        assert!(matches!(plan(&repo, 1), Err(Error::InvalidArg(_))));
+        assert!(matches!(plan(&repo, 2), Err(Error::InvalidArg(_))));
+        assert_eq!(mapping(&plan(&repo, 3).unwrap()), vec![(5, 3), (6, 4)]);
+    }
+
+    #[test]
+    fn test_dates_follow_entries() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        create_entries(&repo, &[1, 3]);
+        let day = chrono::NaiveDate::from_ymd(2022, 5, 3);
+        date::record_entry_date(repo.path(), 3, day).unwrap();
+
+        apply(&plan(&repo, 1).unwrap()).unwrap();
+        let p = LogPath::new(repo.path(), 2);
+        assert_eq!(date::entry_date(p.path()).unwrap(), day);
+    }
+
+    #[test]
+    fn test_rollback_on_failure() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        create_entries(&repo, &[2, 4, 6]);
+        let renumberings = plan(&repo, 1).unwrap();
+
+        // Fail partway through the second phase.
+        let mut calls = 0;
+        let result = apply_with(&renumberings, |from, to| {
+            calls += 1;
+            if calls == 5 {
+                Err(IOError::other("disk full"))
+            } else {
+                rename(from, to)
+            }
+        });
+        assert!(matches!(result, Err(Error::IOError(_))));
+        assert_eq!(
+            entries(&repo),
+            vec![
+                (2, "entry 2\n".to_string()),
+                (4, "entry 4\n".to_string()),
+                (6, "entry 6\n".to_string()),
+            ]
+        );
+        assert_eq!(read_dir(dir.path()).unwrap().count(), 3);
+    }
+}
@@ -1,13 +1,18 @@
 //! A devlog repository is a directory containing devlog entry files.
 
+use crate::atomic;
+use crate::date::{self, EntryDates};
 use crate::error::Error;
-use crate::path::LogPath;
+use crate::path::{LogPath, MAX_SEQ_NUM};
+use crate::template::{load_template, render};
+use chrono::NaiveDate;
 use std::collections::BinaryHeap;
-use std::fs::{create_dir_all, read_dir, OpenOptions};
-use std::io::Write;
+use std::fs::{create_dir_all, read_dir};
 use std::path::{Path, PathBuf};
 
-const HELP_MSG: &'static str = "Welcome to your devlog!
+const BACKLOG_FILE_NAME: &str = "backlog.devlog";
+
+const HELP_MSG: &str = "Welcome to your devlog!
 
 You can add tasks below using this format:
 * Use an asterisk (*) for each task you want to complete today.
@@ -48,12 +53,14 @@ impl LogRepository {
 
     /// Checks if the repository has been initialized.
     pub fn initialized(&self) -> Result<bool, Error> {
-        Ok(self.dir.exists() && self.list()?.len() > 0)
+        Ok(self.dir.exists() && !self.list()?.is_empty())
     }
 
     /// Initializes the repository.
     /// This creates the directory if it does not exist,
     /// as well as the first devlog entry file with sequence number one.
+    /// The first entry uses the repository's template if one exists,
+    /// otherwise it contains a help message.
     /// Fails with an `IOError` if the first devlog entry already exists.
     pub fn init(&self) -> Result<LogPath, Error> {
         // Ensure the directory exists
@@ -61,12 +68,13 @@ impl LogRepository {
 
         // Create the first logfile
         let p = LogPath::new(&self.dir, 1);
-        let mut f = OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(p.path())?;
-
-        write!(&mut f, "{}\n", HELP_MSG)?;
+        let today = date::today();
+        let contents = match load_template(&self.dir, today)? {
+            Some(t) => render(&t, today, p.seq_num(), &[]),
+            None => format!("{}\n", HELP_MSG),
+        };
+        atomic::create_new(p.path(), &contents)?;
+        date::record_entry_date(&self.dir, p.seq_num(), today)?;
 
         Ok(p)
     }
@@ -77,6 +85,13 @@ impl LogRepository {
         &self.dir
     }
 
+    /// Returns the path to the backlog file, which holds tasks that are not planned
+    /// for any particular day.  The file may or may not exist.
+    /// The backlog is not a devlog entry, so rollover never copies its tasks.
+    pub fn backlog_path(&self) -> PathBuf {
+        self.dir.join(BACKLOG_FILE_NAME)
+    }
+
     /// Returns all paths to devlog entry files in the repository.
     /// The paths are not necessarily ordered.
     pub fn list(&self) -> Result<Vec<LogPath>, Error> {
@@ -102,7 +117,7 @@ impl LogRepository {
             }
         }
 
-        return Ok(result);
+        Ok(result)
     }
 
     /// Returns the most recent devlog entry file path,
@@ -113,6 +128,29 @@ impl LogRepository {
         Ok(latest)
     }
 
+    /// Returns the path to the devlog entry file with the specified sequence number,
+    /// or `None` if there is no such entry.
+    pub fn get(&self, seq_num: usize) -> Result<Option<LogPath>, Error> {
+        if seq_num == 0 || seq_num > MAX_SEQ_NUM {
+            return Ok(None);
+        }
+        let p = LogPath::new(&self.dir, seq_num);
+        Ok(if p.path().is_file() { Some(p) } else { None })
+    }
+
+    /// Returns the devlog entry that was the most recent entry on the specified date;
+    /// that is, the last entry created on or before that date.
+    /// Returns `None` if every entry was created after the date.
+    pub fn find_by_date(&self, date: NaiveDate) -> Result<Option<LogPath>, Error> {
+        let dates = EntryDates::load(&self.dir)?;
+        let mut candidates = Vec::new();
+        for p in self.list()? {
+            let created = dates.get(p.path())?;
+            candidates.push((p, created));
+        }
+        Ok(latest_on_or_before(candidates, date))
+    }
+
     /// Returns the "nth" most recent devlog entry file path.
     /// For example, `n=0` is the most recent entry,
     /// `n=1` is the second most recent entry,
@@ -128,9 +166,18 @@ impl LogRepository {
     }
 }
 
+fn latest_on_or_before(candidates: Vec<(LogPath, NaiveDate)>, date: NaiveDate) -> Option<LogPath> {
+    candidates
+        .into_iter()
+        .filter(|(_, created)| *created <= date)
+        .map(|(p, _)| p)
+        .max()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs::OpenOptions;
     use std::io::Write;
     use tempfile::tempdir;
 
@@ -139,7 +186,11 @@ mod tests {
         for i in 0..count {
             let seq = i + 1;
             let p = LogPath::new(dir, seq);
-            let mut f = OpenOptions::new().write(true).create(true).open(p.path())?;
+            let mut f = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(p.path())?;
             write!(f, "+ DONE")?;
             paths.push(p);
         }
@@ -209,7 +260,7 @@ mod tests {
         let repo = LogRepository::new(dir.path());
         let latest = repo.latest().unwrap();
         match latest {
-            None => assert!(false),
+            None => panic!("expected latest logpath"),
             Some(p) => assert_eq!(paths[0], p),
         }
     }
@@ -233,4 +284,68 @@ mod tests {
         assert!(repo.nth_from_latest(3).unwrap().is_none());
         assert!(repo.nth_from_latest(4).unwrap().is_none());
     }
+
+    #[test]
+    fn test_get() {
+        let dir = tempdir().unwrap();
+        let paths = create_files(dir.path(), 3).unwrap();
+        let repo = LogRepository::new(dir.path());
+
+        assert_eq!(repo.get(1).unwrap().unwrap(), paths[2]);
+        assert_eq!(repo.get(3).unwrap().unwrap(), paths[0]);
+        assert!(repo.get(0).unwrap().is_none());
+        assert!(repo.get(4).unwrap().is_none());
+        assert!(repo.get(MAX_SEQ_NUM + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_find_by_date() {
+        let dir = tempdir().unwrap();
+        let paths = create_files(dir.path(), 3).unwrap();
+        let repo = LogRepository::new(dir.path());
+
+        let today = date::today();
+        let yesterday = today.pred();
+        assert_eq!(repo.find_by_date(today).unwrap().unwrap(), paths[0]);
+        assert!(repo.find_by_date(yesterday).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_latest_on_or_before() {
+        let dir = Path::new("/foo");
+        let d = |day| NaiveDate::from_ymd(2022, 5, day);
+        let candidates = || {
+            vec![
+                (LogPath::new(dir, 1), d(2)),
+                (LogPath::new(dir, 2), d(2)),
+                (LogPath::new(dir, 3), d(5)),
+            ]
+        };
+        assert!(latest_on_or_before(candidates(), d(1)).is_none());
+        assert_eq!(
+            latest_on_or_before(candidates(), d(2)).unwrap().seq_num(),
+            2
+        );
+        assert_eq!(
+            latest_on_or_before(candidates(), d(4)).unwrap().seq_num(),
+            2
+        );
+        assert_eq!(
+            latest_on_or_before(candidates(), d(5)).unwrap().seq_num(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_init_with_template() {
+        let dir = tempdir().unwrap();
+        let template_path = crate::template::template_path(dir.path(), "daily");
+        create_dir_all(template_path.parent().unwrap()).unwrap();
+        std::fs::write(&template_path, "Entry #{{seq}}\n").unwrap();
+
+        let repo = LogRepository::new(dir.path());
+        let p = repo.init().unwrap();
+        let contents = std::fs::read_to_string(p.path()).unwrap();
+        assert_eq!(contents, "Entry #1\n");
+    }
 }
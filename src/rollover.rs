@@ -1,19 +1,28 @@
 //! Rollover is an operation that copies incomplete
 //! tasks from the latest devlog entry file to a new devlog entry file.
 
+use crate::atomic;
 use crate::config::Config;
+use crate::date;
+use crate::deferred;
 use crate::error::Error;
-use crate::file::LogFile;
-use crate::hook::{execute_hook, HookType};
+use crate::file::{LogFile, ParseOptions};
+use crate::hook::Hook;
 use crate::path::LogPath;
-use crate::task::{Task, TaskStatus};
-use std::fs::OpenOptions;
+use crate::task::Task;
+use crate::template::{load_template, render};
+use chrono::NaiveDate;
 use std::io::Write;
 use std::path::Path;
 
 /// Copies incomplete tasks from the latest devlog entry file
 /// to a new devlog entry file with the next sequence number.
-/// If available, the before-rollover and after-rollover hooks are invoked.
+/// Deferred tasks are parked until their defer date instead of being copied,
+/// and parked tasks whose defer date has arrived are added to the new entry (see `deferred`).
+/// The before-rollover and after-rollover hooks registered in `config` are invoked.
+/// If a before-rollover hook returns an error, no new devlog entry file is created.
+/// Parked tasks are saved before the new entry is created, and restored if creating it fails,
+/// so a failure never leaves a task out of both.
 pub fn rollover<W: Write>(
     w: &mut W,
     config: &Config,
@@ -22,41 +31,102 @@ pub fn rollover<W: Write>(
     let path = p.path();
     let next = p.next()?;
     let next_path = next.path();
+    let repo_dir = config.repo_dir();
+    let options = config.parse_options();
+    let today = date::today();
 
-    execute_hook(w, config, &HookType::BeforeRollover, &[path.as_os_str()])?;
-    let tasks = load_carryover_tasks(path)?;
-    create_new_logfile(&next_path, &tasks)?;
-    execute_hook(
-        w,
-        config,
-        &HookType::AfterRollover,
-        &[path.as_os_str(), next_path.as_os_str()],
-    )?;
+    config.hooks().before_rollover(w, path)?;
+    let parked = deferred::load(repo_dir, options)?;
+    let (ready, mut waiting) = deferred::split_ready(&parked, today);
+    let newly_deferred = load_deferred_tasks(path, options, today)?;
+    let mut tasks = load_carryover_tasks(path, options, today)?;
+    let changes_deferred = !ready.is_empty() || !newly_deferred.is_empty();
+    tasks.extend(ready);
+
+    if changes_deferred {
+        waiting.extend(newly_deferred);
+        deferred::save(repo_dir, &waiting)?;
+    }
+    if let Err(err) = create_new_logfile(repo_dir, &next, &tasks) {
+        if changes_deferred {
+            // Best effort: the new entry doesn't have the ready tasks, so park them again.
+            let _ = deferred::save(repo_dir, &parked);
+        }
+        return Err(err);
+    }
+    date::record_entry_date(repo_dir, next.seq_num(), today)?;
+    config.hooks().after_rollover(w, path, next_path)?;
 
     Ok((next, tasks.len()))
 }
 
-fn load_carryover_tasks(path: &Path) -> Result<Vec<Task>, Error> {
-    let prev = LogFile::load(path)?;
-    let mut tasks = Vec::new();
-    prev.tasks().iter().for_each(|t| {
-        if let TaskStatus::ToDo | TaskStatus::Started | TaskStatus::Blocked = t.status() {
-            tasks.push(t.clone());
-        }
-    });
+/// Loads the tasks that a rollover of the devlog entry at `path` would copy to the new entry.
+/// A task is copied if its status carries over (see `TaskStatus::carries_over`)
+/// or it has subtasks that are copied, so completed subtasks are dropped
+/// while their incomplete siblings stay with the parent.
+/// Tasks deferred past `today` are not copied; see `load_deferred_tasks`.
+/// Relative due and defer dates are resolved against the entry's date (see `Task::resolve_dates`).
+pub fn load_carryover_tasks(
+    path: &Path,
+    options: &ParseOptions,
+    today: NaiveDate,
+) -> Result<Vec<Task>, Error> {
+    let prev = LogFile::load_with(path, options)?;
+    let entry_date = date::entry_date(path)?;
+    let tasks = (0..prev.tasks().len())
+        .filter(|&i| carries_over(&prev, i) && !is_deferred(&prev, i, entry_date, today))
+        .map(|i| prev.tasks()[i].resolve_dates(entry_date))
+        .collect();
+    Ok(tasks)
+}
+
+/// Loads the tasks in the devlog entry at `path` that would carry over,
+/// but are deferred past `today`, along with their subtasks.
+/// Rollover parks these tasks instead of copying them to the new entry,
+/// so their relative due and defer dates are resolved like those of `load_carryover_tasks`.
+pub fn load_deferred_tasks(
+    path: &Path,
+    options: &ParseOptions,
+    today: NaiveDate,
+) -> Result<Vec<Task>, Error> {
+    let prev = LogFile::load_with(path, options)?;
+    let entry_date = date::entry_date(path)?;
+    let tasks = (0..prev.tasks().len())
+        .filter(|&i| carries_over(&prev, i) && is_deferred(&prev, i, entry_date, today))
+        .map(|i| prev.tasks()[i].resolve_dates(entry_date))
+        .collect();
     Ok(tasks)
 }
 
-fn create_new_logfile(next_path: &Path, tasks: &[Task]) -> Result<(), Error> {
-    let mut f = OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(next_path)?;
+fn carries_over(f: &LogFile, i: usize) -> bool {
+    f.effective_status(i).carries_over() || f.children(i).iter().any(|&c| carries_over(f, c))
+}
 
-    for t in tasks {
-        write!(f, "{}\n", t)?;
+/// Whether the top-level task containing the task at index `i` is deferred past `today`.
+fn is_deferred(f: &LogFile, i: usize, entry_date: NaiveDate, today: NaiveDate) -> bool {
+    let mut top = i;
+    while let Some(parent) = f.parent(top) {
+        top = parent;
     }
+    f.tasks()[top]
+        .defer_date(entry_date)
+        .is_some_and(|d| d > today)
+}
 
+fn create_new_logfile(repo_dir: &Path, next: &LogPath, tasks: &[Task]) -> Result<(), Error> {
+    let today = date::today();
+    let contents = match load_template(repo_dir, today)? {
+        Some(t) => render(&t, today, next.seq_num(), tasks),
+        None => {
+            let mut s = String::new();
+            for t in tasks {
+                s.push_str(&t.format_with_body());
+                s.push('\n');
+            }
+            s
+        }
+    };
+    atomic::create_new(next.path(), &contents)?;
     Ok(())
 }
 
@@ -64,6 +134,7 @@ fn create_new_logfile(next_path: &Path, tasks: &[Task]) -> Result<(), Error> {
 mod tests {
     use super::*;
     use crate::repository::LogRepository;
+    use crate::task::TaskStatus;
     use tempfile::tempdir;
 
     #[test]
@@ -96,4 +167,196 @@ mod tests {
         paths.sort();
         assert_eq!(paths, vec![first_logpath, new_logpath]);
     }
+
+    struct VetoRollover;
+
+    impl Hook for VetoRollover {
+        fn before_rollover(&self, _w: &mut dyn Write, _path: &Path) -> Result<(), Error> {
+            Err(Error::HookVetoed("not today".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_rollover_vetoed_by_hook() {
+        let mut out = Vec::new();
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let mut config = Config::new(dir.path(), "");
+        config.register_hook(Box::new(VetoRollover));
+
+        repo.init().unwrap();
+        let first_logpath = repo.latest().unwrap().unwrap();
+        match rollover(&mut out, &config, &first_logpath) {
+            Err(Error::HookVetoed(_)) => {}
+            _ => panic!("expected HookVetoed"),
+        }
+
+        // No new logfile should have been created
+        assert_eq!(repo.list().unwrap(), vec![first_logpath]);
+    }
+
+    #[test]
+    fn test_rollover_with_template() {
+        let mut out = Vec::new();
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let config = Config::new(dir.path(), "");
+        repo.init().unwrap();
+
+        let template_path = crate::template::template_path(dir.path(), "daily");
+        std::fs::create_dir_all(template_path.parent().unwrap()).unwrap();
+        std::fs::write(
+            &template_path,
+            "#{{seq}}\nGoals:\n{{carried_tasks}}\nNotes:\n",
+        )
+        .unwrap();
+
+        let first_logpath = repo.latest().unwrap().unwrap();
+        let (new_logpath, _) = rollover(&mut out, &config, &first_logpath).unwrap();
+        let contents = std::fs::read_to_string(new_logpath.path()).unwrap();
+        assert!(contents.starts_with("#2\nGoals:\n* "));
+        assert!(contents.ends_with("\nNotes:\n"));
+
+        let logfile = LogFile::load(new_logpath.path()).unwrap();
+        assert_eq!(logfile.tasks().len(), 3);
+    }
+
+    #[test]
+    fn test_rollover_with_subtasks() {
+        let mut out = Vec::new();
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let mut config = Config::new(dir.path(), "");
+        let options = ParseOptions::default().with_subtasks(true);
+        config.set_parse_options(options.clone());
+
+        let first_logpath = repo.init().unwrap();
+        std::fs::write(
+            first_logpath.path(),
+            "* Foo\n  + Foo 1\n  * Foo 2\n+ Bar\n  + Bar 1\n+ Baz\n  - Baz 1\n",
+        )
+        .unwrap();
+
+        let (new_logpath, num_imported) = rollover(&mut out, &config, &first_logpath).unwrap();
+        assert_eq!(num_imported, 4);
+        let contents = std::fs::read_to_string(new_logpath.path()).unwrap();
+        assert_eq!(contents, "* Foo\n    * Foo 2\n+ Baz\n    - Baz 1\n");
+
+        let logfile = LogFile::load_with(new_logpath.path(), &options).unwrap();
+        assert_eq!(logfile.children(0), vec![1]);
+    }
+
+    #[test]
+    fn test_rollover_preserves_body() {
+        let mut out = Vec::new();
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let config = Config::new(dir.path(), "");
+
+        let first_logpath = repo.init().unwrap();
+        std::fs::write(
+            first_logpath.path(),
+            "^ Foo\n  Traceback:\n    File \"main.py\"\n+ Bar\n  about bar\n",
+        )
+        .unwrap();
+
+        let (new_logpath, _) = rollover(&mut out, &config, &first_logpath).unwrap();
+        let contents = std::fs::read_to_string(new_logpath.path()).unwrap();
+        assert_eq!(contents, "^ Foo\n    Traceback:\n      File \"main.py\"\n");
+    }
+
+    #[test]
+    fn test_rollover_resolves_relative_dates() {
+        let mut out = Vec::new();
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let config = Config::new(dir.path(), "");
+        let first_logpath = repo.init().unwrap();
+        std::fs::write(
+            first_logpath.path(),
+            "* Foo due:tomorrow\n    @today stays in the body\n* Bar @2022-05-06\n",
+        )
+        .unwrap();
+        let tomorrow = date::entry_date(first_logpath.path()).unwrap() + chrono::Duration::days(1);
+
+        let (new_logpath, _) = rollover(&mut out, &config, &first_logpath).unwrap();
+        let contents = std::fs::read_to_string(new_logpath.path()).unwrap();
+        assert_eq!(
+            contents,
+            format!(
+                "* Foo due:{}\n    @today stays in the body\n* Bar @2022-05-06\n",
+                tomorrow.format("%Y-%m-%d")
+            )
+        );
+    }
+
+    #[test]
+    fn test_rollover_deferred_tasks() {
+        let mut out = Vec::new();
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let config = Config::new(dir.path(), "");
+        let first_logpath = repo.init().unwrap();
+        std::fs::write(
+            first_logpath.path(),
+            "* Foo defer:9999-01-01\n    notes\n* Bar defer:2000-01-01\n+ Done defer:9999-01-01\n* Baz\n",
+        )
+        .unwrap();
+        deferred::save(
+            dir.path(),
+            &[
+                Task::new(TaskStatus::ToDo, "Ready defer:2000-01-01"),
+                Task::new(TaskStatus::ToDo, "Later defer:9999-12-31"),
+            ],
+        )
+        .unwrap();
+
+        let (new_logpath, num_imported) = rollover(&mut out, &config, &first_logpath).unwrap();
+        assert_eq!(num_imported, 3);
+        let contents = std::fs::read_to_string(new_logpath.path()).unwrap();
+        assert_eq!(
+            contents,
+            "* Bar defer:2000-01-01\n* Baz\n* Ready defer:2000-01-01\n"
+        );
+
+        let parked = deferred::load(dir.path(), config.parse_options()).unwrap();
+        let parked: Vec<String> = parked.iter().map(|t| t.format_with_body()).collect();
+        assert_eq!(
+            parked,
+            vec![
+                "* Later defer:9999-12-31",
+                "* Foo defer:9999-01-01\n    notes"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_rollover_keeps_deferred_tasks() {
+        let mut out = Vec::new();
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let config = Config::new(dir.path(), "");
+        let first_logpath = repo.init().unwrap();
+        std::fs::write(first_logpath.path(), "* Foo defer:9999-01-01\n* Bar\n").unwrap();
+        let parked = vec![Task::new(TaskStatus::ToDo, "Ready defer:2000-01-01")];
+        deferred::save(dir.path(), &parked).unwrap();
+
+        // Creating the new entry fails, so the ready task is parked again.
+        let next = first_logpath.next().unwrap();
+        std::fs::create_dir(next.path()).unwrap();
+        assert!(rollover(&mut out, &config, &first_logpath).is_err());
+        assert_eq!(
+            deferred::load(dir.path(), config.parse_options()).unwrap(),
+            parked
+        );
+        std::fs::remove_dir(next.path()).unwrap();
+
+        // Parking the deferred task fails, so no new entry is created.
+        let deferred_path = deferred::deferred_path(dir.path());
+        std::fs::remove_file(&deferred_path).unwrap();
+        std::fs::create_dir(&deferred_path).unwrap();
+        std::fs::write(deferred_path.join("file"), "").unwrap();
+        assert!(rollover(&mut out, &config, &first_logpath).is_err());
+        assert_eq!(repo.latest().unwrap().unwrap(), first_logpath);
+    }
 }
@@ -0,0 +1,754 @@
+//! Serve a local HTTP API for reading and updating devlog entries,
+//! so that editor extensions and dashboards don't need to parse the file format.
+//!
+//! All requests and responses use JSON.  The API provides these endpoints:
+//!
+//! * `GET /entries`: list all entries.
+//! * `GET /entries/{seq}`: get an entry's contents and tasks.  `{seq}` may be "latest".
+//! * `GET /entries/{seq}/tasks`: get an entry's tasks.
+//! * `POST /entries/{seq}/tasks`: add a task, e.g. `{"content": "Foo", "status": "todo"}`.
+//! * `PUT /entries/{seq}/tasks/{index}`: update a task's `status` and/or `content`.
+//! * `GET /status?back=N&show=STATUS`: tasks grouped by status, like `devlog status`.
+//! * `POST /rollover`: rollover the latest entry.
+//!
+//! Archived entries (see `archive`) can be read, but not updated, and have `"archived": true`.
+//! Task statuses are identified by the names "todo", "started", "blocked", and "done",
+//! or the names of custom statuses.
+//! Each task also has a `depth`, which is greater than zero for subtasks,
+//! and a `body` with the lines that continue the task's content.
+//! Tasks with a priority like "(A)" or "!!" in their content have a `priority` letter; otherwise it is null.
+//! Errors are reported as `{"error": "message"}` with an appropriate HTTP status code.
+//! Requests that modify the repository fail with status 409 while another devlog command holds its lock.
+//!
+//! So that web pages open in the user's browser can't use the API, the server rejects
+//! requests with an `Origin` header or a `Host` header other than a loopback address or
+//! "localhost", and requests that modify the repository must have the content type
+//! `application/json`.
+
+use crate::archive::{self, ArchivedEntry};
+use crate::config::Config;
+use crate::error::Error;
+use crate::file::{LogFile, ParseOptions};
+use crate::lock::RepoLock;
+use crate::path::LogPath;
+use crate::repository::LogRepository;
+use crate::rollover;
+use crate::task::{Task, TaskStatus};
+use serde_json::{json, Value};
+use std::fs::read_to_string;
+use std::io::Error as IOError;
+use std::net::{IpAddr, SocketAddr};
+
+/// A JSON response to an API request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    status: u16,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Response {
+        Response { status: 200, body }
+    }
+
+    fn error(status: u16, msg: &str) -> Response {
+        Response {
+            status,
+            body: json!({ "error": msg }),
+        }
+    }
+
+    /// The HTTP status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The JSON response body.
+    pub fn body(&self) -> &Value {
+        &self.body
+    }
+}
+
+/// An HTTP server for the devlog API.
+pub struct Server {
+    inner: tiny_http::Server,
+}
+
+impl Server {
+    /// Listens on the specified address, for example "127.0.0.1:8080".
+    /// Use port zero to choose any available port.
+    pub fn bind(addr: &str) -> Result<Server, Error> {
+        let inner = tiny_http::Server::http(addr).map_err(|e| IOError::other(e.to_string()))?;
+        Ok(Server { inner })
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.server_addr().to_ip()
+    }
+
+    /// Handles requests one at a time, until the server fails to receive a request.
+    pub fn run(&self, config: &Config) -> Result<(), Error> {
+        loop {
+            let mut request = self.inner.recv()?;
+            let method = request.method().to_string();
+            let header = |name: &'static str| {
+                request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv(name))
+                    .map(|h| h.value.as_str().to_string())
+            };
+            let rejected = check_headers(
+                &method,
+                header("Host").as_deref(),
+                header("Origin").as_deref(),
+                header("Content-Type").as_deref(),
+            );
+            let mut body = String::new();
+            let response = match rejected {
+                Some(response) => response,
+                None => match request.as_reader().read_to_string(&mut body) {
+                    Ok(_) => handle_request(config, &method, request.url(), &body),
+                    Err(_) => Response::error(400, "request body must be UTF-8"),
+                },
+            };
+            let content_type =
+                tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                    .expect("valid header");
+            let http_response = tiny_http::Response::from_string(response.body.to_string())
+                .with_status_code(response.status)
+                .with_header(content_type);
+            // A client that disconnects early shouldn't stop the server.
+            let _ = request.respond(http_response);
+        }
+    }
+}
+
+/// Rejects requests that a web page could have sent from the user's browser,
+/// including through DNS rebinding.  Returns `None` if the request is allowed.
+fn check_headers(
+    method: &str,
+    host: Option<&str>,
+    origin: Option<&str>,
+    content_type: Option<&str>,
+) -> Option<Response> {
+    if origin.is_some() {
+        return Some(Response::error(
+            403,
+            "cross-origin requests are not allowed",
+        ));
+    }
+    if !host.is_some_and(is_loopback_host) {
+        return Some(Response::error(403, "Host must be a loopback address"));
+    }
+    let modifies = matches!(method, "POST" | "PUT" | "PATCH");
+    let is_json = content_type
+        .and_then(|t| t.split(';').next())
+        .is_some_and(|t| t.trim().eq_ignore_ascii_case("application/json"));
+    if modifies && !is_json {
+        return Some(Response::error(
+            415,
+            "Content-Type must be application/json",
+        ));
+    }
+    None
+}
+
+/// Whether the value of a `Host` header, like "127.0.0.1:8080" or "[::1]:8080",
+/// names the local machine.
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(""),
+        None => host.split(':').next().unwrap_or(""),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Handles a single API request and returns the response.
+/// `url` is the request path with an optional query string, like "/status?back=1".
+pub fn handle_request(config: &Config, method: &str, url: &str, body: &str) -> Response {
+    let repo = LogRepository::new(config.repo_dir());
+    let options = config.parse_options();
+    let (path, query) = match url.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (url, ""),
+    };
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    let result = match (method, segments.as_slice()) {
+        ("GET", ["entries"]) => list_entries(&repo),
+        ("GET", ["entries", seq]) => get_entry(&repo, options, seq),
+        ("GET", ["entries", seq, "tasks"]) => get_tasks(&repo, options, seq),
+        ("POST", ["entries", seq, "tasks"]) => add_task(&repo, options, seq, body),
+        ("PUT", ["entries", seq, "tasks", index]) | ("PATCH", ["entries", seq, "tasks", index]) => {
+            update_task(&repo, options, seq, index, body)
+        }
+        ("GET", ["status"]) => get_status(&repo, options, query),
+        ("POST", ["rollover"]) => post_rollover(config, &repo),
+        (_, ["entries"])
+        | (_, ["entries", _])
+        | (_, ["entries", _, "tasks"])
+        | (_, ["entries", _, "tasks", _])
+        | (_, ["status"])
+        | (_, ["rollover"]) => Ok(Response::error(405, "method not allowed")),
+        _ => Ok(Response::error(404, "not found")),
+    };
+
+    result.unwrap_or_else(|err| match err {
+        Error::RepositoryLocked(_) => {
+            Response::error(409, "another devlog command is modifying the repository")
+        }
+        _ => Response::error(500, "internal server error"),
+    })
+}
+
+fn task_json(index: usize, t: &Task) -> Value {
+    json!({
+        "index": index,
+        "status": t.status().name(),
+        "content": t.content(),
+        "depth": t.depth(),
+        "body": t.body(),
+        "priority": t.priority().map(|p| p.to_string()),
+    })
+}
+
+fn tasks_json(f: &LogFile) -> Value {
+    Value::Array(
+        f.tasks()
+            .iter()
+            .enumerate()
+            .map(|(i, t)| task_json(i, t))
+            .collect(),
+    )
+}
+
+fn entry_json(p: &LogPath, latest: &Option<LogPath>) -> Value {
+    json!({
+        "seq": p.seq_num(),
+        "path": p.path().to_string_lossy(),
+        "latest": Some(p) == latest.as_ref(),
+        "archived": false,
+    })
+}
+
+fn archived_entry_json(e: &ArchivedEntry) -> Value {
+    json!({
+        "seq": e.seq_num(),
+        "path": e.archive_path().to_string_lossy(),
+        "latest": false,
+        "archived": true,
+    })
+}
+
+/// Finds an archived entry for requests that only read entries.
+fn find_archived_entry(repo: &LogRepository, seq: &str) -> Result<Option<ArchivedEntry>, Error> {
+    match seq.parse::<usize>() {
+        Ok(seq_num) => archive::find(repo.path(), seq_num),
+        Err(_) => Ok(None),
+    }
+}
+
+fn find_entry(repo: &LogRepository, seq: &str) -> Result<Option<LogPath>, Error> {
+    if seq == "latest" {
+        repo.latest()
+    } else {
+        match seq.parse::<usize>() {
+            Ok(seq_num) => repo.get(seq_num),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+fn parse_body(body: &str) -> Result<Value, Response> {
+    match serde_json::from_str::<Value>(body) {
+        Ok(v) if v.is_object() => Ok(v),
+        _ => Err(Response::error(400, "request body must be a JSON object")),
+    }
+}
+
+fn parse_status(v: &Value, options: &ParseOptions) -> Result<Option<TaskStatus>, Response> {
+    match v.get("status") {
+        None => Ok(None),
+        Some(s) => s
+            .as_str()
+            .and_then(|name| options.status_named(name))
+            .map(Some)
+            .ok_or_else(|| Response::error(400, "unknown task status")),
+    }
+}
+
+fn parse_content(v: &Value) -> Result<Option<String>, Response> {
+    match v.get("content") {
+        None => Ok(None),
+        Some(c) => match c.as_str().map(|s| s.trim()) {
+            Some(s) if !s.is_empty() && !s.contains('\n') => Ok(Some(s.to_string())),
+            _ => Err(Response::error(
+                400,
+                "content must be a non-empty, single-line string",
+            )),
+        },
+    }
+}
+
+fn list_entries(repo: &LogRepository) -> Result<Response, Error> {
+    let mut paths = repo.list()?;
+    paths.sort();
+    let latest = repo.latest()?;
+    let entries: Vec<Value> = archive::load(repo.path())?
+        .iter()
+        .map(archived_entry_json)
+        .chain(paths.iter().map(|p| entry_json(p, &latest)))
+        .collect();
+    Ok(Response::ok(Value::Array(entries)))
+}
+
+fn get_entry(repo: &LogRepository, options: &ParseOptions, seq: &str) -> Result<Response, Error> {
+    match find_entry(repo, seq)? {
+        Some(p) => {
+            let contents = read_to_string(p.path())?;
+            let f = LogFile::parse_with(&contents, options);
+            let mut entry = entry_json(&p, &repo.latest()?);
+            entry["contents"] = Value::String(contents);
+            entry["tasks"] = tasks_json(&f);
+            Ok(Response::ok(entry))
+        }
+        None => match find_archived_entry(repo, seq)? {
+            Some(e) => {
+                let f = LogFile::parse_with(e.contents(), options);
+                let mut entry = archived_entry_json(&e);
+                entry["contents"] = Value::String(e.contents().to_string());
+                entry["tasks"] = tasks_json(&f);
+                Ok(Response::ok(entry))
+            }
+            None => Ok(Response::error(404, "entry not found")),
+        },
+    }
+}
+
+fn get_tasks(repo: &LogRepository, options: &ParseOptions, seq: &str) -> Result<Response, Error> {
+    match find_entry(repo, seq)? {
+        Some(p) => Ok(Response::ok(tasks_json(&LogFile::load_with(
+            p.path(),
+            options,
+        )?))),
+        None => match find_archived_entry(repo, seq)? {
+            Some(e) => Ok(Response::ok(tasks_json(&LogFile::parse_with(
+                e.contents(),
+                options,
+            )))),
+            None => Ok(Response::error(404, "entry not found")),
+        },
+    }
+}
+
+fn add_task(
+    repo: &LogRepository,
+    options: &ParseOptions,
+    seq: &str,
+    body: &str,
+) -> Result<Response, Error> {
+    let p = match find_entry(repo, seq)? {
+        Some(p) => p,
+        None => return Ok(Response::error(404, "entry not found")),
+    };
+    let parsed =
+        parse_body(body).and_then(|v| Ok((parse_status(&v, options)?, parse_content(&v)?)));
+    let (status, content) = match parsed {
+        Ok((status, Some(content))) => (status.unwrap_or(TaskStatus::ToDo), content),
+        Ok((_, None)) => return Ok(Response::error(400, "content is required")),
+        Err(resp) => return Ok(resp),
+    };
+
+    let _lock = RepoLock::acquire(repo.path())?;
+    let mut f = LogFile::load_with(p.path(), options)?;
+    let i = f.add_task(Task::new(status, &content));
+    f.save(p.path())?;
+    Ok(Response {
+        status: 201,
+        body: task_json(i, &f.tasks()[i]),
+    })
+}
+
+fn update_task(
+    repo: &LogRepository,
+    options: &ParseOptions,
+    seq: &str,
+    index: &str,
+    body: &str,
+) -> Result<Response, Error> {
+    let p = match find_entry(repo, seq)? {
+        Some(p) => p,
+        None => return Ok(Response::error(404, "entry not found")),
+    };
+    let _lock = RepoLock::acquire(repo.path())?;
+    let mut f = LogFile::load_with(p.path(), options)?;
+    let i = match index.parse::<usize>() {
+        Ok(i) if i < f.tasks().len() => i,
+        _ => return Ok(Response::error(404, "task not found")),
+    };
+    let (status, content) =
+        match parse_body(body).and_then(|v| Ok((parse_status(&v, options)?, parse_content(&v)?))) {
+            Ok(parsed) => parsed,
+            Err(resp) => return Ok(resp),
+        };
+
+    let old = &f.tasks()[i];
+    let t = Task::new(
+        status.unwrap_or_else(|| old.status()),
+        content.as_deref().unwrap_or_else(|| old.content()),
+    );
+    f.replace_task(i, t);
+    f.save(p.path())?;
+    Ok(Response::ok(task_json(i, &f.tasks()[i])))
+}
+
+fn get_status(
+    repo: &LogRepository,
+    options: &ParseOptions,
+    query: &str,
+) -> Result<Response, Error> {
+    let mut num_back = 0;
+    let mut show: Option<TaskStatus> = None;
+    for (key, value) in query.split('&').filter_map(|kv| kv.split_once('=')) {
+        match key {
+            "back" => match value.parse::<usize>() {
+                Ok(n) => num_back = n,
+                Err(_) => return Ok(Response::error(400, "back must be an integer")),
+            },
+            "show" if value == "all" => show = None,
+            "show" => match options.status_named(value) {
+                Some(s) => show = Some(s),
+                None => return Ok(Response::error(400, "unknown task status")),
+            },
+            _ => {}
+        }
+    }
+
+    let p = match repo.nth_from_latest(num_back)? {
+        Some(p) => p,
+        None => return Ok(Response::error(404, "entry not found")),
+    };
+    let f = LogFile::load_with(p.path(), options)?;
+    let sections: Vec<Value> = options
+        .statuses()
+        .iter()
+        .filter(|status| show.as_ref().is_none_or(|s| s == *status))
+        .map(|status| {
+            let tasks: Vec<Value> = f
+                .tasks()
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.status() == *status)
+                .map(|(i, t)| task_json(i, t))
+                .collect();
+            json!({
+                "status": status.name(),
+                "name": status.display_name(),
+                "tasks": tasks,
+            })
+        })
+        .collect();
+    Ok(Response::ok(json!({
+        "seq": p.seq_num(),
+        "sections": sections,
+    })))
+}
+
+fn post_rollover(config: &Config, repo: &LogRepository) -> Result<Response, Error> {
+    let _lock = RepoLock::acquire(repo.path())?;
+    let p = match repo.latest()? {
+        Some(p) => p,
+        None => return Ok(Response::error(404, "entry not found")),
+    };
+    let mut out = Vec::new();
+    let (next, count) = rollover::rollover(&mut out, config, &p)?;
+    Ok(Response {
+        status: 201,
+        body: json!({
+            "seq": next.seq_num(),
+            "path": next.path().to_string_lossy(),
+            "imported": count,
+            "hook_output": String::from_utf8_lossy(&out),
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::path::Path;
+    use std::thread;
+    use tempfile::tempdir;
+
+    fn init_repo(dir: &Path) -> Config {
+        let repo = LogRepository::new(dir);
+        let p = repo.init().unwrap();
+        std::fs::write(p.path(), "Notes\n* Foo\n^ Bar\n+ Baz\n").unwrap();
+        Config::new(dir, "")
+    }
+
+    #[test]
+    fn test_list_entries() {
+        let dir = tempdir().unwrap();
+        let config = init_repo(dir.path());
+        let resp = handle_request(&config, "GET", "/entries", "");
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.body()[0]["seq"], 1);
+        assert_eq!(resp.body()[0]["latest"], true);
+    }
+
+    #[test]
+    fn test_get_archived_entry() {
+        let dir = tempdir().unwrap();
+        let config = init_repo(dir.path());
+        let repo = LogRepository::new(dir.path());
+        std::fs::write(dir.path().join("000000002.devlog"), "* Next\n").unwrap();
+        let paths = archive::select(&repo, archive::Cutoff::SeqNum(2)).unwrap();
+        archive::archive(&repo, &paths).unwrap();
+
+        let resp = handle_request(&config, "GET", "/entries", "");
+        assert_eq!(resp.body()[0]["seq"], 1);
+        assert_eq!(resp.body()[0]["archived"], true);
+        assert_eq!(resp.body()[1]["seq"], 2);
+        assert_eq!(resp.body()[1]["archived"], false);
+
+        let resp = handle_request(&config, "GET", "/entries/1", "");
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.body()["contents"], "Notes\n* Foo\n^ Bar\n+ Baz\n");
+        let resp = handle_request(&config, "GET", "/entries/1/tasks", "");
+        assert_eq!(resp.body()[2]["content"], "Baz");
+
+        let resp = handle_request(&config, "POST", "/entries/1/tasks", r#"{"content": "Foo"}"#);
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[test]
+    fn test_get_entry() {
+        let dir = tempdir().unwrap();
+        let config = init_repo(dir.path());
+        let resp = handle_request(&config, "GET", "/entries/latest", "");
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.body()["contents"], "Notes\n* Foo\n^ Bar\n+ Baz\n");
+        assert_eq!(
+            resp.body()["tasks"][1],
+            json!({"index": 1, "status": "started", "content": "Bar", "depth": 0, "body": [], "priority": null})
+        );
+
+        let resp = handle_request(&config, "GET", "/entries/2", "");
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[test]
+    fn test_add_and_update_task() {
+        let dir = tempdir().unwrap();
+        let config = init_repo(dir.path());
+        let resp = handle_request(
+            &config,
+            "POST",
+            "/entries/1/tasks",
+            r#"{"content": "Boo !!", "status": "blocked"}"#,
+        );
+        assert_eq!(resp.status(), 201);
+        assert_eq!(resp.body()["index"], 3);
+        assert_eq!(resp.body()["priority"], "B");
+
+        let resp = handle_request(
+            &config,
+            "PUT",
+            "/entries/1/tasks/0",
+            r#"{"status": "done"}"#,
+        );
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            *resp.body(),
+            json!({"index": 0, "status": "done", "content": "Foo", "depth": 0, "body": [], "priority": null})
+        );
+
+        let contents = std::fs::read_to_string(dir.path().join("000000001.devlog")).unwrap();
+        assert_eq!(contents, "Notes\n+ Foo\n^ Bar\n+ Baz\n- Boo !!\n");
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let dir = tempdir().unwrap();
+        let config = init_repo(dir.path());
+        let check = |method, url, body, status| {
+            let resp = handle_request(&config, method, url, body);
+            assert_eq!(resp.status(), status, "{} {}", method, url);
+            assert!(resp.body()["error"].is_string());
+        };
+        check("POST", "/entries/1/tasks", "not json", 400);
+        check("POST", "/entries/1/tasks", r#"{"status": "todo"}"#, 400);
+        check("POST", "/entries/1/tasks", r#"{"content": "a\nb"}"#, 400);
+        check(
+            "PUT",
+            "/entries/1/tasks/0",
+            r#"{"status": "cancelled"}"#,
+            400,
+        );
+        check("PUT", "/entries/1/tasks/10", r#"{"status": "done"}"#, 404);
+        check("DELETE", "/entries/1", "", 405);
+        check("GET", "/nothing", "", 404);
+    }
+
+    #[test]
+    fn test_status() {
+        let dir = tempdir().unwrap();
+        let config = init_repo(dir.path());
+        let resp = handle_request(&config, "GET", "/status", "");
+        assert_eq!(resp.status(), 200);
+        let sections = resp.body()["sections"].as_array().unwrap();
+        assert_eq!(sections.len(), 4);
+        assert_eq!(sections[0]["name"], "In Progress");
+        assert_eq!(sections[0]["tasks"][0]["content"], "Bar");
+
+        let resp = handle_request(&config, "GET", "/status?show=todo&back=0", "");
+        let sections = resp.body()["sections"].as_array().unwrap();
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0]["tasks"][0]["content"], "Foo");
+
+        let resp = handle_request(&config, "GET", "/status?back=1", "");
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[test]
+    fn test_rollover() {
+        let dir = tempdir().unwrap();
+        let config = init_repo(dir.path());
+        let resp = handle_request(&config, "POST", "/rollover", "");
+        assert_eq!(resp.status(), 201);
+        assert_eq!(resp.body()["seq"], 2);
+        assert_eq!(resp.body()["imported"], 2);
+    }
+
+    #[test]
+    fn test_rollover_while_locked() {
+        let dir = tempdir().unwrap();
+        let config = init_repo(dir.path());
+        let lock = RepoLock::acquire(dir.path()).unwrap();
+        let resp = handle_request(&config, "POST", "/rollover", "");
+        assert_eq!(resp.status(), 409);
+        let resp = handle_request(
+            &config,
+            "POST",
+            "/entries/latest/tasks",
+            r#"{"content": "Foo"}"#,
+        );
+        assert_eq!(resp.status(), 409);
+
+        drop(lock);
+        let resp = handle_request(&config, "POST", "/rollover", "");
+        assert_eq!(resp.status(), 201);
+    }
+
+    fn http_request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let headers = "Host: localhost\r\nContent-Type: application/json\r\n";
+        http_request_with_headers(addr, method, path, headers, body)
+    }
+
+    fn http_request_with_headers(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        headers: &str,
+        body: &str,
+    ) -> (u16, Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\n{}Connection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            headers,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_server_on_localhost() {
+        let dir = tempdir().unwrap();
+        init_repo(dir.path());
+
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let repo_dir = dir.path().to_path_buf();
+        thread::spawn(move || {
+            let config = Config::new(&repo_dir, "");
+            let _ = server.run(&config);
+        });
+
+        let (status, body) = http_request(addr, "GET", "/entries/1/tasks", "");
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 3);
+
+        let (status, body) =
+            http_request(addr, "POST", "/entries/1/tasks", r#"{"content": "Boo"}"#);
+        assert_eq!(status, 201);
+        assert_eq!(body["status"], "todo");
+
+        let (status, _) = http_request(addr, "GET", "/missing", "");
+        assert_eq!(status, 404);
+
+        let body = r#"{"content": "Boo"}"#;
+        let headers = "Host: localhost\r\nContent-Type: text/plain\r\n";
+        let (status, _) = http_request_with_headers(addr, "POST", "/rollover", headers, body);
+        assert_eq!(status, 415);
+        let headers = "Host: evil.example:8080\r\nContent-Type: application/json\r\n";
+        let (status, _) = http_request_with_headers(addr, "POST", "/rollover", headers, body);
+        assert_eq!(status, 403);
+        let (status, body) = http_request(addr, "GET", "/entries", "");
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_check_headers() {
+        let json = Some("application/json");
+        let check = |method, host, origin, content_type| {
+            check_headers(method, host, origin, content_type).map(|r| r.status())
+        };
+        assert_eq!(check("GET", Some("localhost:8080"), None, None), None);
+        assert_eq!(check("GET", Some("127.0.0.1:8080"), None, None), None);
+        assert_eq!(check("GET", Some("[::1]:8080"), None, None), None);
+        assert_eq!(check("PUT", Some("LOCALHOST"), None, json), None);
+        assert_eq!(
+            check(
+                "POST",
+                Some("localhost"),
+                None,
+                Some("application/json; charset=utf-8")
+            ),
+            None
+        );
+
+        let origin = Some("https://example.com");
+        assert_eq!(check("GET", Some("localhost"), origin, None), Some(403));
+        assert_eq!(check("GET", Some("example.com"), None, None), Some(403));
+        assert_eq!(
+            check("GET", Some("192.168.1.2:8080"), None, None),
+            Some(403)
+        );
+        assert_eq!(
+            check("GET", Some("localhost.example.com"), None, None),
+            Some(403)
+        );
+        assert_eq!(check("GET", None, None, None), Some(403));
+        assert_eq!(check("POST", Some("localhost"), None, None), Some(415));
+        assert_eq!(
+            check("POST", Some("localhost"), None, Some("text/plain")),
+            Some(415)
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Load repository settings from the `settings.conf` file in the devlog repository.
+//!
+//! Settings are stored in the repository, next to `statuses.conf`, so that every
+//! devlog command and every clone of the repository parses entries the same way.
+//! Each line sets a key to a value.  For example:
+//!
+//! ```text
+//! # Parse indented tasks as subtasks
+//! subtasks = yes
+//! ```
+//!
+//! These keys are supported:
+//! * `subtasks`: whether indented tasks are subtasks of the task above.  Defaults to "no".
+//!
+//! Lines starting with "#" are comments.
+
+use crate::error::Error;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+const SETTINGS_FILE_NAME: &str = "settings.conf";
+
+/// Returns the path to the repository settings file, which may or may not exist.
+pub fn settings_path(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(SETTINGS_FILE_NAME)
+}
+
+/// Settings stored in a devlog repository.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    subtasks: bool,
+}
+
+impl Settings {
+    /// Whether indented tasks are parsed as subtasks.
+    pub fn subtasks(&self) -> bool {
+        self.subtasks
+    }
+}
+
+/// Loads the settings for the repository at `repo_dir`,
+/// or the default settings if the repository has no settings file.
+pub fn load_settings(repo_dir: &Path) -> Result<Settings, Error> {
+    let p = settings_path(repo_dir);
+    if p.is_file() {
+        parse_settings(&read_to_string(&p)?)
+    } else {
+        Ok(Settings::default())
+    }
+}
+
+/// Parses the contents of a `settings.conf` file.
+pub fn parse_settings(s: &str) -> Result<Settings, Error> {
+    let mut settings = Settings::default();
+    for (i, line) in s.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or_else(|| invalid(line_number, "expected \"key = value\""))?;
+        match key {
+            "subtasks" => settings.subtasks = parse_bool(line_number, value)?,
+            _ => return Err(invalid(line_number, &format!("unknown key \"{}\"", key))),
+        }
+    }
+    Ok(settings)
+}
+
+fn parse_bool(line_number: usize, value: &str) -> Result<bool, Error> {
+    match value.to_lowercase().as_str() {
+        "yes" | "true" => Ok(true),
+        "no" | "false" => Ok(false),
+        _ => Err(invalid(line_number, "expected \"yes\" or \"no\"")),
+    }
+}
+
+fn invalid(line_number: usize, msg: &str) -> Error {
+    Error::InvalidConfig(format!(
+        "{} line {}: {}",
+        SETTINGS_FILE_NAME, line_number, msg
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_settings() {
+        let settings = parse_settings("# Our settings\n\nsubtasks = yes\n").unwrap();
+        assert!(settings.subtasks());
+        assert_eq!(parse_settings("").unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn test_parse_invalid_settings() {
+        for (s, msg) in [
+            ("subtasks", "settings.conf line 1: expected \"key = value\""),
+            (
+                "subtasks = maybe",
+                "settings.conf line 1: expected \"yes\" or \"no\"",
+            ),
+            (
+                "\ncolor = red",
+                "settings.conf line 2: unknown key \"color\"",
+            ),
+        ] {
+            match parse_settings(s) {
+                Err(Error::InvalidConfig(m)) => assert_eq!(m, msg),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_settings() {
+        let dir = tempdir().unwrap();
+        assert_eq!(load_settings(dir.path()).unwrap(), Settings::default());
+        std::fs::write(settings_path(dir.path()), "subtasks = true\n").unwrap();
+        assert!(load_settings(dir.path()).unwrap().subtasks());
+    }
+}
@@ -1,14 +1,22 @@
 //! Report tasks from the most recent devlog entry file,
 //! grouped by task status type.
+//!
+//! Entries further back than the repository directory are read from the archive
+//! (see the `archive` module).
 
+use crate::archive::{self, ArchivedEntry};
+use crate::date;
 use crate::error::Error;
-use crate::file::LogFile;
+use crate::file::{LogFile, ParseOptions};
 use crate::repository::LogRepository;
 use crate::task::{Task, TaskStatus};
+use chrono::NaiveDate;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
+use std::path::Path;
 
 /// Controls how tasks are displayed in the status report.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub enum DisplayMode {
     /// Show all tasks, grouped by task status.
     ShowAll,
@@ -35,48 +43,196 @@ impl DisplayMode {
     }
 }
 
+/// Controls the order of tasks within each section of the status report.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Show tasks in the order they appear in the devlog entry.
+    File,
+
+    /// Show tasks with the highest priority first, then tasks without a priority.
+    /// Tasks with the same priority are shown in file order.
+    Priority,
+
+    /// Show the tasks that have been carried over from the most previous entries first.
+    /// Tasks with the same age are shown in file order.
+    Age,
+}
+
+impl SortOrder {
+    /// Parse a sort order from its name: "file", "priority", or "age".
+    pub fn from_name(name: &str) -> Option<SortOrder> {
+        match name {
+            "file" => Some(SortOrder::File),
+            "priority" => Some(SortOrder::Priority),
+            "age" => Some(SortOrder::Age),
+            _ => None,
+        }
+    }
+}
+
 /// Prints the status report using the provided writer.
+/// Subtasks are shown beneath their parent task,
+/// which is grouped by its status derived from the subtasks.
+/// Incomplete tasks that are overdue or due today are labeled.
+/// If `num_back` goes past the entries in the repository directory, the archived entry is reported.
 pub fn print<W: Write>(
     w: &mut W,
     repo: &LogRepository,
+    options: &ParseOptions,
     num_back: usize,
     d: DisplayMode,
+    sort: SortOrder,
+) -> Result<(), Error> {
+    let entry = match repo.nth_from_latest(num_back)? {
+        Some(logpath) => Some(load_entry(logpath.path(), options)?),
+        None => {
+            archive::nth_from_latest(repo, num_back)?.map(|e| parse_archived_entry(&e, options))
+        }
+    };
+    let mut g = group_by_status(entry.as_ref(), options);
+    match sort {
+        SortOrder::File => {}
+        SortOrder::Priority => sort_by_priority(&mut g),
+        SortOrder::Age => {
+            let ages = load_task_ages(repo, options, num_back, &g)?;
+            g.sort_by_key(|block| std::cmp::Reverse(ages.get(block[0].content()).copied()));
+        }
+    }
+    print_status_report(w, &g, d, date::today())
+}
+
+/// Prints the tasks in the repository's backlog, grouped by status like `print`.
+/// Sorting by age has no effect, because backlog tasks are not carried over between entries.
+pub fn print_backlog<W: Write>(
+    w: &mut W,
+    repo: &LogRepository,
+    options: &ParseOptions,
+    d: DisplayMode,
+    sort: SortOrder,
 ) -> Result<(), Error> {
-    let g = load_tasks_group_by_status(repo, num_back)?;
-    print_status_report(w, &g, d)
+    let path = repo.backlog_path();
+    let entry = if path.is_file() {
+        Some(load_entry(&path, options)?)
+    } else {
+        None
+    };
+    let mut g = group_by_status(entry.as_ref(), options);
+    if sort == SortOrder::Priority {
+        sort_by_priority(&mut g);
+    }
+    print_status_report(w, &g, d, date::today())
+}
+
+fn sort_by_priority(g: &mut GroupedTasks) {
+    g.sort_by_key(|block| {
+        // Tasks without a priority sort after all tasks with a priority.
+        let p = block[0].priority();
+        (p.is_none(), p)
+    })
+}
+
+/// Loads an entry file and the date it was created.
+fn load_entry(path: &Path, options: &ParseOptions) -> Result<(LogFile, NaiveDate), Error> {
+    Ok((LogFile::load_with(path, options)?, date::entry_date(path)?))
 }
 
-fn load_tasks_group_by_status(
+fn parse_archived_entry(e: &ArchivedEntry, options: &ParseOptions) -> (LogFile, NaiveDate) {
+    (LogFile::parse_with(e.contents(), options), e.date())
+}
+
+fn group_by_status(entry: Option<&(LogFile, NaiveDate)>, options: &ParseOptions) -> GroupedTasks {
+    let mut grouped = GroupedTasks::new(options.statuses());
+    if let Some((f, entry_date)) = entry {
+        grouped.entry_date = Some(*entry_date);
+        let tasks = f.tasks();
+        for (i, t) in tasks.iter().enumerate() {
+            if t.depth() == 0 {
+                let status = f.effective_status(i);
+                let num_subtasks = tasks[i + 1..]
+                    .iter()
+                    .take_while(|sub| sub.depth() > 0)
+                    .count();
+                let parent = Task::new(status.clone(), t.content()).with_body(t.body().to_vec());
+                grouped.insert(status, &parent, &tasks[i + 1..i + 1 + num_subtasks]);
+            }
+        }
+    }
+    grouped
+}
+
+/// Returns the number of consecutive entries before the reported entry that contain
+/// each top-level task in the report, keyed by task content.
+/// Counting continues into archived entries.
+fn load_task_ages(
     repo: &LogRepository,
+    options: &ParseOptions,
     num_back: usize,
-) -> Result<GroupedTasks, Error> {
-    let mut grouped = GroupedTasks::new();
-    if let Some(logpath) = repo.nth_from_latest(num_back)? {
-        let f = LogFile::load(logpath.path())?;
-        f.tasks().iter().for_each(|t| grouped.insert(t));
+    g: &GroupedTasks,
+) -> Result<HashMap<String, usize>, Error> {
+    let mut ages = HashMap::new();
+    let mut remaining: HashSet<&str> = g.blocks().map(|block| block[0].content()).collect();
+    let mut archived: Option<(usize, Vec<ArchivedEntry>)> = None;
+    let mut age = 0;
+    while !remaining.is_empty() {
+        age += 1;
+        let n = num_back + age;
+        let f = match repo.nth_from_latest(n)? {
+            Some(logpath) => LogFile::load_with(logpath.path(), options)?,
+            None => {
+                // Load the archives once, most recent first.
+                if archived.is_none() {
+                    let num_live = repo.list()?.len();
+                    let entries = archive::load(repo.path())?.into_iter().rev().collect();
+                    archived = Some((num_live, entries));
+                }
+                let (num_live, entries) = archived.as_ref().unwrap();
+                match n.checked_sub(*num_live).and_then(|i| entries.get(i)) {
+                    Some(e) => LogFile::parse_with(e.contents(), options),
+                    None => break,
+                }
+            }
+        };
+        let found: HashSet<&str> = f
+            .tasks()
+            .iter()
+            .filter(|t| t.depth() == 0)
+            .map(|t| t.content())
+            .collect();
+        remaining.retain(|content| {
+            if found.contains(content) {
+                ages.insert(content.to_string(), age);
+                true
+            } else {
+                false
+            }
+        });
     }
-    Ok(grouped)
+    Ok(ages)
 }
 
-const ALL_STATUSES: &[TaskStatus] = &[
+/// The built-in task statuses, in the order they appear in the status report.
+/// Custom statuses can be added with `ParseOptions::with_statuses`.
+pub const ALL_STATUSES: &[TaskStatus] = &[
     TaskStatus::Started,
     TaskStatus::ToDo,
     TaskStatus::Blocked,
     TaskStatus::Done,
 ];
 
-fn print_status_report<W: Write>(w: &mut W, g: &GroupedTasks, d: DisplayMode) -> Result<(), Error> {
+fn print_status_report<W: Write>(
+    w: &mut W,
+    g: &GroupedTasks,
+    d: DisplayMode,
+    today: NaiveDate,
+) -> Result<(), Error> {
     let mut has_prev = false;
-    for status in ALL_STATUSES {
-        if d.show_status(status) {
-            let tasks = g.retrieve(status);
-            if tasks.len() > 0 {
-                if has_prev {
-                    write!(w, "\n")?;
-                }
-                print_section(w, status, tasks, d)?;
-                has_prev = true;
+    for (status, tasks) in &g.groups {
+        if d.show_status(status) && !tasks.is_empty() {
+            if has_prev {
+                writeln!(w)?;
             }
+            print_section(w, status, tasks, &d, g.entry_date, today)?;
+            has_prev = true;
         }
     }
 
@@ -86,51 +242,77 @@ fn print_status_report<W: Write>(w: &mut W, g: &GroupedTasks, d: DisplayMode) ->
 fn print_section<W: Write>(
     w: &mut W,
     status: &TaskStatus,
-    tasks: &[Task],
-    d: DisplayMode,
+    tasks: &[Vec<Task>],
+    d: &DisplayMode,
+    entry_date: Option<NaiveDate>,
+    today: NaiveDate,
 ) -> Result<(), Error> {
     if d.show_section_names() {
-        write!(w, "{}:\n", status.display_name())?;
+        writeln!(w, "{}:", status.display_name())?;
     }
-    for t in tasks {
-        write!(w, "{}\n", t)?;
+    for t in tasks.iter().flatten() {
+        let label = entry_date.and_then(|entry_date| due_label(t, entry_date, today));
+        match label {
+            Some(label) => {
+                write!(w, "{}  ({})", t, label)?;
+                for line in t.format_with_body().lines().skip(1) {
+                    write!(w, "\n{}", line)?;
+                }
+                writeln!(w)?;
+            }
+            None => writeln!(w, "{}", t.format_with_body())?,
+        }
     }
     Ok(())
 }
 
+/// Describes when an incomplete task is due, if it is overdue or due today.
+fn due_label(t: &Task, entry_date: NaiveDate, today: NaiveDate) -> Option<&'static str> {
+    if t.status().is_complete() {
+        return None;
+    }
+    match t.due_date(entry_date)? {
+        due if due < today => Some("overdue"),
+        due if due == today => Some("due today"),
+        _ => None,
+    }
+}
+
+/// Top-level tasks grouped by status.  Each top-level task is stored in a block
+/// followed by its subtasks, so that sorting keeps subtasks beneath their parent.
 struct GroupedTasks {
-    todo: Vec<Task>,
-    started: Vec<Task>,
-    blocked: Vec<Task>,
-    done: Vec<Task>,
+    groups: Vec<(TaskStatus, Vec<Vec<Task>>)>,
+    entry_date: Option<NaiveDate>,
 }
 
 impl GroupedTasks {
-    fn new() -> GroupedTasks {
+    fn new(statuses: &[TaskStatus]) -> GroupedTasks {
         GroupedTasks {
-            todo: Vec::new(),
-            started: Vec::new(),
-            blocked: Vec::new(),
-            done: Vec::new(),
+            groups: statuses.iter().map(|s| (s.clone(), Vec::new())).collect(),
+            entry_date: None,
         }
     }
 
-    fn insert(&mut self, task: &Task) {
-        let t = task.clone();
-        match t.status() {
-            TaskStatus::ToDo => self.todo.push(t),
-            TaskStatus::Started => self.started.push(t),
-            TaskStatus::Blocked => self.blocked.push(t),
-            TaskStatus::Done => self.done.push(t),
-        }
+    fn insert(&mut self, status: TaskStatus, task: &Task, subtasks: &[Task]) {
+        let pos = match self.groups.iter().position(|(s, _)| *s == status) {
+            Some(pos) => pos,
+            None => {
+                self.groups.push((status, Vec::new()));
+                self.groups.len() - 1
+            }
+        };
+        let mut block = vec![task.clone()];
+        block.extend_from_slice(subtasks);
+        self.groups[pos].1.push(block);
+    }
+
+    fn blocks(&self) -> impl Iterator<Item = &Vec<Task>> {
+        self.groups.iter().flat_map(|(_, blocks)| blocks)
     }
 
-    fn retrieve(&self, status: &TaskStatus) -> &[Task] {
-        match status {
-            TaskStatus::ToDo => &self.todo,
-            TaskStatus::Started => &self.started,
-            TaskStatus::Blocked => &self.blocked,
-            TaskStatus::Done => &self.done,
+    fn sort_by_key<K: Ord, F: FnMut(&Vec<Task>) -> K>(&mut self, mut f: F) {
+        for (_, blocks) in self.groups.iter_mut() {
+            blocks.sort_by_key(&mut f);
         }
     }
 }
@@ -138,7 +320,7 @@ impl GroupedTasks {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::task::Task;
+    use crate::task::{CustomStatus, Task};
     use std::fs::OpenOptions;
     use std::path::Path;
     use std::str;
@@ -153,7 +335,7 @@ mod tests {
             .unwrap();
 
         for t in tasks {
-            write!(&mut f, "{}\n", t).unwrap();
+            writeln!(&mut f, "{}", t).unwrap();
         }
     }
 
@@ -171,7 +353,16 @@ mod tests {
         expected_status: &str,
     ) {
         let mut buf = Vec::new();
-        print(&mut buf, &repo, num_back, display_mode).unwrap();
+        let options = ParseOptions::default();
+        print(
+            &mut buf,
+            repo,
+            &options,
+            num_back,
+            display_mode,
+            SortOrder::File,
+        )
+        .unwrap();
         let actual_status = str::from_utf8(&buf).unwrap();
         assert_eq!(actual_status, expected_status);
     }
@@ -428,7 +619,7 @@ mod tests {
         // Create a new devlog file with only the "todo" task
         let p = repo.latest().unwrap().unwrap();
         let next = p.next().unwrap();
-        write_tasks_to_file(&next.path(), &[Task::new(TaskStatus::ToDo, "Bar")]);
+        write_tasks_to_file(next.path(), &[Task::new(TaskStatus::ToDo, "Bar")]);
 
         // check before the first logfile
         check_status(&repo, 2, DisplayMode::ShowAll, "");
@@ -444,4 +635,202 @@ mod tests {
         // check the latest logfile
         check_status(&repo, 0, DisplayMode::ShowAll, "To Do:\n* Bar\n");
     }
+
+    #[test]
+    fn test_status_with_subtasks() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        let contents = "* Foo\n  + Foo 1\n  * Foo 2\n^ Bar\n  + Bar 1\n- Baz\n";
+        std::fs::write(logpath.path(), contents).unwrap();
+
+        let mut buf = Vec::new();
+        let options = ParseOptions::default().with_subtasks(true);
+        print(
+            &mut buf,
+            &repo,
+            &options,
+            0,
+            DisplayMode::ShowAll,
+            SortOrder::File,
+        )
+        .unwrap();
+        assert_eq!(
+            str::from_utf8(&buf).unwrap(),
+            "To Do:\n* Foo\n    + Foo 1\n    * Foo 2\n\nBlocked:\n- Baz\n\nDone:\n+ Bar\n    + Bar 1\n"
+        );
+    }
+
+    #[test]
+    fn test_status_with_body() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        let contents = "^ Foo\n  $ cargo test\n    --release\n* Bar\nNotes\n";
+        std::fs::write(logpath.path(), contents).unwrap();
+        check_current_status(
+            &repo,
+            DisplayMode::ShowAll,
+            "In Progress:\n^ Foo\n    $ cargo test\n      --release\n\nTo Do:\n* Bar\n",
+        );
+    }
+
+    #[test]
+    fn test_status_with_custom_statuses() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        std::fs::write(logpath.path(), "* Foo\n% Bar\n^ Baz\n").unwrap();
+
+        let review = CustomStatus::new("review", '%', "In Review", false, true).into_status();
+        let statuses = vec![
+            TaskStatus::Started,
+            review.clone(),
+            TaskStatus::ToDo,
+            TaskStatus::Blocked,
+            TaskStatus::Done,
+        ];
+        let options = ParseOptions::default().with_statuses(statuses);
+        let mut buf = Vec::new();
+        print(
+            &mut buf,
+            &repo,
+            &options,
+            0,
+            DisplayMode::ShowAll,
+            SortOrder::File,
+        )
+        .unwrap();
+        assert_eq!(
+            str::from_utf8(&buf).unwrap(),
+            "In Progress:\n^ Baz\n\nIn Review:\n% Bar\n\nTo Do:\n* Foo\n"
+        );
+
+        let mut buf = Vec::new();
+        print(
+            &mut buf,
+            &repo,
+            &options,
+            0,
+            DisplayMode::ShowOnly(review),
+            SortOrder::File,
+        )
+        .unwrap();
+        assert_eq!(str::from_utf8(&buf).unwrap(), "% Bar\n");
+    }
+
+    fn sorted_status(repo: &LogRepository, sort: SortOrder) -> String {
+        let mut buf = Vec::new();
+        let options = ParseOptions::default();
+        print(&mut buf, repo, &options, 0, DisplayMode::ShowAll, sort).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_sort_by_priority() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        let contents = "* Foo\n* Bar !\n* (A) Baz\n* Boo !!\n^ Started\n";
+        std::fs::write(logpath.path(), contents).unwrap();
+        assert_eq!(
+            sorted_status(&repo, SortOrder::Priority),
+            "In Progress:\n^ Started\n\nTo Do:\n* (A) Baz\n* Boo !!\n* Bar !\n* Foo\n"
+        );
+        assert_eq!(
+            sorted_status(&repo, SortOrder::File),
+            "In Progress:\n^ Started\n\nTo Do:\n* Foo\n* Bar !\n* (A) Baz\n* Boo !!\n"
+        );
+    }
+
+    #[test]
+    fn test_sort_by_age() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let first = repo.init().unwrap();
+        std::fs::write(first.path(), "* Old\n* Gone\n").unwrap();
+        let second = first.next().unwrap();
+        std::fs::write(second.path(), "* Middle\n* Old\n").unwrap();
+        let third = second.next().unwrap();
+        std::fs::write(third.path(), "* New\n* Middle\n* Old\n* Gone\n").unwrap();
+        assert_eq!(
+            sorted_status(&repo, SortOrder::Age),
+            "To Do:\n* Old\n* Middle\n* New\n* Gone\n"
+        );
+    }
+
+    #[test]
+    fn test_status_reads_archived_entries() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let first = repo.init().unwrap();
+        std::fs::write(first.path(), "* Old\n* Gone\n").unwrap();
+        let second = first.next().unwrap();
+        std::fs::write(second.path(), "* Middle\n* Old\n").unwrap();
+        let third = second.next().unwrap();
+        std::fs::write(third.path(), "* New\n* Middle\n* Old\n").unwrap();
+        let paths = archive::select(&repo, archive::Cutoff::SeqNum(3)).unwrap();
+        archive::archive(&repo, &paths).unwrap();
+
+        check_status(&repo, 1, DisplayMode::ShowAll, "To Do:\n* Middle\n* Old\n");
+        check_status(&repo, 2, DisplayMode::ShowAll, "To Do:\n* Old\n* Gone\n");
+        check_status(&repo, 3, DisplayMode::ShowAll, "");
+        assert_eq!(
+            sorted_status(&repo, SortOrder::Age),
+            "To Do:\n* Old\n* Middle\n* New\n"
+        );
+    }
+
+    #[test]
+    fn test_due_labels() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        let today = date::today().format("%Y-%m-%d");
+        let contents = format!(
+            "* Foo due:2000-01-01\n    notes\n* Bar @{}\n* Baz due:9999-01-01\n+ Boo due:2000-01-01\n",
+            today
+        );
+        std::fs::write(logpath.path(), contents).unwrap();
+        assert_eq!(
+            sorted_status(&repo, SortOrder::File),
+            format!(
+                "To Do:\n* Foo due:2000-01-01  (overdue)\n    notes\n* Bar @{}  (due today)\n* Baz due:9999-01-01\n\nDone:\n+ Boo due:2000-01-01\n",
+                today
+            )
+        );
+    }
+
+    #[test]
+    fn test_backlog_status() {
+        let dir = tempdir().unwrap();
+        let repo = init_repo_with_tasks(dir.path(), &[Task::new(TaskStatus::ToDo, "Foo")]);
+        let options = ParseOptions::default();
+
+        let mut buf = Vec::new();
+        print_backlog(
+            &mut buf,
+            &repo,
+            &options,
+            DisplayMode::ShowAll,
+            SortOrder::File,
+        )
+        .unwrap();
+        assert!(buf.is_empty());
+
+        std::fs::write(repo.backlog_path(), "* Someday\n* Maybe (A)\n- Never\n").unwrap();
+        let mut buf = Vec::new();
+        print_backlog(
+            &mut buf,
+            &repo,
+            &options,
+            DisplayMode::ShowAll,
+            SortOrder::File,
+        )
+        .unwrap();
+        assert_eq!(
+            str::from_utf8(&buf).unwrap(),
+            "To Do:\n* Someday\n* Maybe (A)\n\nBlocked:\n- Never\n"
+        );
+    }
 }
@@ -0,0 +1,242 @@
+//! Load custom task statuses from the `statuses.conf` file in the devlog repository.
+//!
+//! Each section of the file defines a status, named by the section header.
+//! The name identifies the status in command-line arguments and the HTTP API.  For example:
+//!
+//! ```text
+//! [review]
+//! marker = %
+//! display = In Review
+//! after = started
+//!
+//! [cancelled]
+//! marker = x
+//! complete = yes
+//! ```
+//!
+//! Sections may contain these keys:
+//! * `marker` (required): the character that starts tasks with the status.
+//! * `display`: the name shown in the status report.  Defaults to the section name.
+//! * `complete`: whether tasks with the status are finished.  Defaults to "no".
+//! * `rollover`: whether rollover copies tasks with the status to the new entry.
+//!   Defaults to "yes" for incomplete statuses and "no" for complete statuses.
+//! * `after`: the status that this status follows in the status report.
+//!   Defaults to the end of the report.
+//!
+//! Lines starting with "#" are comments.
+
+use crate::error::Error;
+use crate::status::ALL_STATUSES;
+use crate::task::{CustomStatus, TaskStatus};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+const STATUSES_FILE_NAME: &str = "statuses.conf";
+
+/// Returns the path to the file defining custom statuses, which may or may not exist.
+pub fn statuses_path(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(STATUSES_FILE_NAME)
+}
+
+/// Loads the built-in and custom statuses for the repository, in the order of the status report.
+/// If the repository does not define custom statuses, this returns `status::ALL_STATUSES`.
+pub fn load_statuses(repo_dir: &Path) -> Result<Vec<TaskStatus>, Error> {
+    let p = statuses_path(repo_dir);
+    if p.is_file() {
+        parse_statuses(&read_to_string(&p)?)
+    } else {
+        Ok(ALL_STATUSES.to_vec())
+    }
+}
+
+struct Section {
+    line_number: usize,
+    name: String,
+    marker: Option<char>,
+    display: Option<String>,
+    complete: bool,
+    rollover: Option<bool>,
+    after: Option<String>,
+}
+
+/// Parses the contents of a `statuses.conf` file, returning the built-in and custom statuses
+/// in the order of the status report.
+pub fn parse_statuses(s: &str) -> Result<Vec<TaskStatus>, Error> {
+    let mut sections: Vec<Section> = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push(Section {
+                line_number,
+                name: name.trim().to_string(),
+                marker: None,
+                display: None,
+                complete: false,
+                rollover: None,
+                after: None,
+            });
+            continue;
+        }
+
+        let section = sections
+            .last_mut()
+            .ok_or_else(|| invalid(line_number, "expected a [section] header"))?;
+        let (key, value) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or_else(|| invalid(line_number, "expected \"key = value\""))?;
+        match key {
+            "marker" => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => section.marker = Some(c),
+                    _ => return Err(invalid(line_number, "marker must be a single character")),
+                }
+            }
+            "display" => section.display = Some(value.to_string()),
+            "complete" => section.complete = parse_bool(line_number, value)?,
+            "rollover" => section.rollover = Some(parse_bool(line_number, value)?),
+            "after" => section.after = Some(value.to_string()),
+            _ => return Err(invalid(line_number, &format!("unknown key \"{}\"", key))),
+        }
+    }
+
+    let mut statuses = ALL_STATUSES.to_vec();
+    for section in sections {
+        let line_number = section.line_number;
+        let name = section.name.as_str();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(invalid(
+                line_number,
+                "status names may contain only lowercase letters, digits, \"-\", and \"_\"",
+            ));
+        }
+        if statuses.iter().any(|s| s.name() == name) {
+            return Err(invalid(
+                line_number,
+                &format!("status \"{}\" is already defined", name),
+            ));
+        }
+        let marker = section
+            .marker
+            .ok_or_else(|| invalid(line_number, "status has no marker"))?;
+        if marker.is_whitespace() || statuses.iter().any(|s| s.marker() == marker) {
+            return Err(invalid(
+                line_number,
+                &format!("marker \"{}\" cannot be used", marker),
+            ));
+        }
+
+        let display = section.display.as_deref().unwrap_or(name);
+        let rollover = section.rollover.unwrap_or(!section.complete);
+        let status = CustomStatus::new(name, marker, display, section.complete, rollover);
+        let pos = match &section.after {
+            Some(after) => {
+                statuses
+                    .iter()
+                    .position(|s| s.name() == after)
+                    .ok_or_else(|| invalid(line_number, &format!("unknown status \"{}\"", after)))?
+                    + 1
+            }
+            None => statuses.len(),
+        };
+        statuses.insert(pos, status.into_status());
+    }
+    Ok(statuses)
+}
+
+fn parse_bool(line_number: usize, value: &str) -> Result<bool, Error> {
+    match value.to_lowercase().as_str() {
+        "yes" | "true" => Ok(true),
+        "no" | "false" => Ok(false),
+        _ => Err(invalid(line_number, "expected \"yes\" or \"no\"")),
+    }
+}
+
+fn invalid(line_number: usize, msg: &str) -> Error {
+    Error::InvalidConfig(format!(
+        "{} line {}: {}",
+        STATUSES_FILE_NAME, line_number, msg
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const STATUSES: &str = "# Our workflow
+[review]
+marker = %
+display = In Review
+after = started
+
+[cancelled]
+marker = x
+complete = yes
+";
+
+    fn names(statuses: &[TaskStatus]) -> Vec<&str> {
+        statuses.iter().map(|s| s.name()).collect()
+    }
+
+    #[test]
+    fn test_parse_statuses() {
+        let statuses = parse_statuses(STATUSES).unwrap();
+        assert_eq!(
+            names(&statuses),
+            vec!["started", "review", "todo", "blocked", "done", "cancelled"]
+        );
+
+        let review = statuses[1].clone();
+        assert_eq!(review.marker(), '%');
+        assert_eq!(review.display_name(), "In Review");
+        assert!(!review.is_complete());
+        assert!(review.carries_over());
+
+        let cancelled = statuses[5].clone();
+        assert_eq!(cancelled.display_name(), "cancelled");
+        assert!(cancelled.is_complete());
+        assert!(!cancelled.carries_over());
+    }
+
+    #[test]
+    fn test_parse_invalid_statuses() {
+        let invalid = [
+            "marker = %\n",
+            "[review]\nmarker = +\n",
+            "[review]\nmarker = ab\n",
+            "[review]\n",
+            "[done]\nmarker = %\n",
+            "[Review]\nmarker = %\n",
+            "[review]\nmarker = %\nafter = nothing\n",
+            "[review]\nmarker = %\ncomplete = maybe\n",
+            "[review]\nmarker = %\ncolor = red\n",
+            "[review]\nmarker = %\n[review2]\nmarker = %\n",
+        ];
+        for s in &invalid {
+            match parse_statuses(s) {
+                Err(Error::InvalidConfig(_)) => {}
+                other => panic!("expected InvalidConfig for {:?}, got {:?}", s, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_statuses() {
+        let dir = tempdir().unwrap();
+        assert_eq!(load_statuses(dir.path()).unwrap(), ALL_STATUSES.to_vec());
+
+        std::fs::write(statuses_path(dir.path()), STATUSES).unwrap();
+        assert_eq!(load_statuses(dir.path()).unwrap().len(), 6);
+    }
+}
@@ -0,0 +1,465 @@
+//! Sync a devlog repository with a git remote.
+//!
+//! Syncing commits every change in the repository, pulls changes from the remote named
+//! "origin", and pushes the result.  When two machines both create the next devlog entry
+//! before syncing, both entries have the same sequence number.  Instead of failing with a
+//! merge conflict, sync renumbers the local entries so they follow the entries from the remote.
+//! Append-only logs, like the file of entry dates (see `date::entry_dates_path`),
+//! the time log, and the focus session log, are merged by keeping the lines from both sides,
+//! which git configures through the repository's `.gitattributes` file.
+
+use crate::date;
+use crate::error::Error;
+use crate::focus;
+use crate::lock::lock_path;
+use crate::path::LogPath;
+use crate::timelog;
+use std::collections::HashSet;
+use std::fs::{read_to_string, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const REMOTE_NAME: &str = "origin";
+const GITATTRIBUTES_FILE_NAME: &str = ".gitattributes";
+
+/// What happened during a sync.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncSummary {
+    commit_message: Option<String>,
+    renumbered: Vec<(usize, usize)>,
+    remote: bool,
+}
+
+impl SyncSummary {
+    /// The message of the commit that recorded local changes, or `None` if nothing changed.
+    pub fn commit_message(&self) -> Option<&str> {
+        self.commit_message.as_deref()
+    }
+
+    /// Local entries that were renumbered to avoid conflicts with the remote,
+    /// as pairs of the old and new sequence numbers.
+    pub fn renumbered(&self) -> &[(usize, usize)] {
+        &self.renumbered
+    }
+
+    /// Whether the repository was pulled from and pushed to a remote.
+    pub fn remote(&self) -> bool {
+        self.remote
+    }
+}
+
+/// Sets up the devlog repository at `repo_dir` for syncing.
+/// This creates a git repository if there isn't one, sets the "origin" remote to `remote_url`
+/// if provided, and commits the existing files.
+pub fn init_git(repo_dir: &Path, remote_url: Option<&str>) -> Result<(), Error> {
+    if !is_git_repo(repo_dir) {
+        git(repo_dir, &["init"])?;
+    }
+    if let Some(url) = remote_url {
+        if has_remote(repo_dir)? {
+            git(repo_dir, &["remote", "set-url", REMOTE_NAME, url])?;
+        } else {
+            git(repo_dir, &["remote", "add", REMOTE_NAME, url])?;
+        }
+    }
+    configure_merge(repo_dir)?;
+    commit_changes(repo_dir)?;
+    Ok(())
+}
+
+/// Adds lines to `.gitattributes` that merge the append-only logs with git's union driver,
+/// since machines that create entries or track time before syncing both add lines to their ends.
+fn configure_merge(repo_dir: &Path) -> Result<(), Error> {
+    let p = repo_dir.join(GITATTRIBUTES_FILE_NAME);
+    let existing = if p.is_file() {
+        read_to_string(&p)?
+    } else {
+        String::new()
+    };
+    let missing: Vec<String> = [
+        date::entry_dates_path(repo_dir),
+        timelog::timelog_path(repo_dir),
+        focus::focus_log_path(repo_dir),
+    ]
+    .iter()
+    .map(|path| {
+        format!(
+            "{} merge=union",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        )
+    })
+    .filter(|line| !existing.lines().any(|l| l.trim() == line))
+    .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let mut f = OpenOptions::new().create(true).append(true).open(&p)?;
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        writeln!(f)?;
+    }
+    for line in missing {
+        writeln!(f, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Whether the devlog repository at `repo_dir` is a git repository.
+pub fn is_git_repo(repo_dir: &Path) -> bool {
+    repo_dir.join(".git").exists()
+}
+
+/// Commits all changes in the repository, then pulls from and pushes to the "origin" remote
+/// if it is configured.  Conflicting entries created on both sides are renumbered locally.
+pub fn sync(repo_dir: &Path) -> Result<SyncSummary, Error> {
+    if !is_git_repo(repo_dir) {
+        return Err(Error::GitFailed(
+            "the devlog repository is not a git repository; run `devlog init --git` first"
+                .to_string(),
+        ));
+    }
+
+    // Repositories set up by older versions of devlog don't configure the merge yet.
+    configure_merge(repo_dir)?;
+    let mut summary = SyncSummary {
+        commit_message: commit_changes(repo_dir)?,
+        ..SyncSummary::default()
+    };
+    if !has_remote(repo_dir)? {
+        return Ok(summary);
+    }
+    summary.remote = true;
+
+    let branch = git(repo_dir, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let remote_ref = format!("{}/{}", REMOTE_NAME, branch);
+    git(repo_dir, &["fetch", REMOTE_NAME])?;
+    if git(repo_dir, &["rev-parse", "--verify", "--quiet", &remote_ref]).is_ok() {
+        let base = git(repo_dir, &["merge-base", "HEAD", &remote_ref])?;
+        summary.renumbered = renumber_conflicts(repo_dir, &base, &remote_ref)?;
+        if let Err(err) = git(repo_dir, &["merge", "--no-edit", &remote_ref]) {
+            // Leave the repository as it was before the merge, so the user can resolve it by hand.
+            let _ = git(repo_dir, &["merge", "--abort"]);
+            return Err(err);
+        }
+    }
+    git(repo_dir, &["push", "--set-upstream", REMOTE_NAME, &branch])?;
+    Ok(summary)
+}
+
+/// Stages and commits all changes except the repository lock, returning the commit message,
+/// or `None` if there was nothing to commit.
+fn commit_changes(repo_dir: &Path) -> Result<Option<String>, Error> {
+    let lock_file = lock_path(repo_dir);
+    let exclude_lock = format!(
+        ":(exclude){}",
+        lock_file.file_name().unwrap_or_default().to_string_lossy()
+    );
+    git(repo_dir, &["add", "--all", "--", ".", &exclude_lock])?;
+    let status = git(repo_dir, &["diff", "--cached", "--name-status"])?;
+    match commit_message(&status) {
+        Some(msg) => {
+            git(repo_dir, &["commit", "--quiet", "-m", &msg])?;
+            Ok(Some(msg))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Describes staged changes, given the output of `git diff --cached --name-status`.
+/// Changes to the file of entry dates go along with the entries, so they are only
+/// mentioned if nothing else changed.
+fn commit_message(name_status: &str) -> Option<String> {
+    let mut added = Vec::new();
+    let mut edited = Vec::new();
+    let mut removed = Vec::new();
+    let mut other = Vec::new();
+    let mut dates_changed = false;
+    let dates_file_name = date::entry_dates_path(Path::new(""))
+        .to_string_lossy()
+        .into_owned();
+    for line in name_status.lines() {
+        let mut fields = line.split('\t');
+        let (status, name) = match (fields.next(), fields.next_back()) {
+            (Some(status), Some(name)) => (status, name),
+            _ => continue,
+        };
+        if name == dates_file_name {
+            dates_changed = true;
+            continue;
+        }
+        match (status.chars().next(), entry_seq_num(name)) {
+            (Some('A'), Some(seq)) => added.push(seq),
+            (Some('M'), Some(seq)) => edited.push(seq),
+            (Some('D'), Some(seq)) => removed.push(seq),
+            _ => other.push(name.to_string()),
+        }
+    }
+
+    let mut parts = Vec::new();
+    for (verb, seqs) in &[("add", &added), ("edit", &edited), ("remove", &removed)] {
+        if !seqs.is_empty() {
+            let nums: Vec<String> = seqs.iter().map(|s| s.to_string()).collect();
+            let noun = if seqs.len() == 1 { "entry" } else { "entries" };
+            parts.push(format!("{} {} {}", verb, noun, nums.join(", ")));
+        }
+    }
+    if !other.is_empty() {
+        parts.push(format!("update {}", other.join(", ")));
+    }
+
+    if parts.is_empty() && dates_changed {
+        parts.push("update entry dates".to_string());
+    }
+
+    let msg = parts.join("; ");
+    let mut chars = msg.chars();
+    chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+}
+
+/// Renames local entries added since `base` that have the same sequence number as entries
+/// added on `remote_ref`, along with any later local entries, so they follow the remote's entries.
+/// Returns the old and new sequence numbers of the renamed entries.
+fn renumber_conflicts(
+    repo_dir: &Path,
+    base: &str,
+    remote_ref: &str,
+) -> Result<Vec<(usize, usize)>, Error> {
+    let local_added = added_entries(repo_dir, base, "HEAD")?;
+    let remote_added: HashSet<usize> = added_entries(repo_dir, base, remote_ref)?
+        .into_iter()
+        .collect();
+    let first_conflict = match local_added.iter().find(|s| remote_added.contains(s)) {
+        Some(&seq) => seq,
+        None => return Ok(Vec::new()),
+    };
+
+    let remote_max = git(repo_dir, &["ls-tree", "--name-only", remote_ref])?
+        .lines()
+        .filter_map(entry_seq_num)
+        .max()
+        .unwrap_or(0);
+    let to_move: Vec<usize> = local_added
+        .into_iter()
+        .filter(|&s| s >= first_conflict)
+        .collect();
+    let renumbered: Vec<(usize, usize)> = to_move
+        .iter()
+        .enumerate()
+        .map(|(i, &old)| (old, remote_max + 1 + i))
+        .collect();
+
+    // New numbers are always larger, so renaming the last entry first never overwrites another.
+    for &(old, new) in renumbered.iter().rev() {
+        git(
+            repo_dir,
+            &["mv", &entry_file_name(old), &entry_file_name(new)],
+        )?;
+    }
+    date::renumber_entry_dates(repo_dir, &renumbered)?;
+    let dates_path = date::entry_dates_path(repo_dir);
+    if dates_path.is_file() {
+        git(repo_dir, &["add", "--", &dates_path.to_string_lossy()])?;
+    }
+    let moves: Vec<String> = renumbered
+        .iter()
+        .map(|(old, new)| format!("{} to {}", old, new))
+        .collect();
+    let msg = format!(
+        "Renumber entries {} to follow entries from {}",
+        moves.join(", "),
+        REMOTE_NAME
+    );
+    git(repo_dir, &["commit", "--quiet", "-m", &msg])?;
+    Ok(renumbered)
+}
+
+/// Returns the sequence numbers of entries added between two commits, in ascending order.
+fn added_entries(repo_dir: &Path, from: &str, to: &str) -> Result<Vec<usize>, Error> {
+    let out = git(
+        repo_dir,
+        &["diff", "--name-only", "--diff-filter=A", from, to],
+    )?;
+    let mut seqs: Vec<usize> = out.lines().filter_map(entry_seq_num).collect();
+    seqs.sort_unstable();
+    Ok(seqs)
+}
+
+fn entry_seq_num(name: &str) -> Option<usize> {
+    if name.contains('/') {
+        return None;
+    }
+    LogPath::from_path(PathBuf::from(name)).map(|p| p.seq_num())
+}
+
+fn entry_file_name(seq_num: usize) -> String {
+    LogPath::new(Path::new(""), seq_num)
+        .path()
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn has_remote(repo_dir: &Path) -> Result<bool, Error> {
+    Ok(git(repo_dir, &["remote"])?
+        .lines()
+        .any(|r| r == REMOTE_NAME))
+}
+
+/// Runs git in the repository directory and returns its trimmed output.
+fn git(repo_dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo_dir)
+        .args(args)
+        .output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(Error::GitFailed(format!(
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::RepoLock;
+    use crate::repository::LogRepository;
+    use chrono::NaiveDate;
+    use std::fs::{read_to_string, write};
+    use tempfile::tempdir;
+
+    const GIT_IDENTITY: [&str; 4] = [
+        "-c",
+        "user.name=devlog",
+        "-c",
+        "user.email=devlog@example.com",
+    ];
+
+    /// Creates a git repository whose commits don't depend on the user's git configuration.
+    fn git_init_with_identity(repo_dir: &Path) {
+        git(repo_dir, &["init", "--quiet"]).unwrap();
+        git(repo_dir, &["config", "user.name", "devlog"]).unwrap();
+        git(repo_dir, &["config", "user.email", "devlog@example.com"]).unwrap();
+    }
+
+    #[test]
+    fn test_commit_message() {
+        assert_eq!(commit_message(""), None);
+        assert_eq!(
+            commit_message("A\t000000003.devlog\nA\t000000004.devlog\nM\t000000002.devlog\n"),
+            Some("Add entries 3, 4; edit entry 2".to_string())
+        );
+        assert_eq!(
+            commit_message("D\t000000001.devlog\nM\tbacklog.devlog\nA\thooks/before-edit\n"),
+            Some("Remove entry 1; update backlog.devlog, hooks/before-edit".to_string())
+        );
+        assert_eq!(
+            commit_message("A\t000000002.devlog\nM\tdates.log\n"),
+            Some("Add entry 2".to_string())
+        );
+        assert_eq!(
+            commit_message("M\tdates.log\n"),
+            Some("Update entry dates".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sync_without_remote() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        repo.init().unwrap();
+        assert!(matches!(sync(dir.path()), Err(Error::GitFailed(_))));
+
+        git_init_with_identity(dir.path());
+        init_git(dir.path(), None).unwrap();
+        let summary = sync(dir.path()).unwrap();
+        assert_eq!(summary, SyncSummary::default());
+
+        write(dir.path().join("000000002.devlog"), "* Foo\n").unwrap();
+        let _lock = RepoLock::acquire(dir.path()).unwrap();
+        let summary = sync(dir.path()).unwrap();
+        assert_eq!(summary.commit_message(), Some("Add entry 2"));
+        assert!(!summary.remote());
+        let tracked = git(dir.path(), &["ls-files"]).unwrap();
+        assert!(!tracked.contains("devlog.lock"));
+    }
+
+    #[test]
+    fn test_sync_renumbers_conflicting_entries() {
+        let remote = tempdir().unwrap();
+        git(remote.path(), &["init", "--bare", "--quiet"]).unwrap();
+        let remote_url = remote.path().to_str().unwrap();
+
+        // The first machine creates the repository and pushes it.
+        let a = tempdir().unwrap();
+        LogRepository::new(a.path()).init().unwrap();
+        git_init_with_identity(a.path());
+        init_git(a.path(), Some(remote_url)).unwrap();
+        assert!(sync(a.path()).unwrap().remote());
+
+        // The second machine clones it.
+        let b = tempdir().unwrap();
+        let mut clone_args = vec!["clone", "--quiet"];
+        clone_args.extend(GIT_IDENTITY);
+        clone_args.extend([remote_url, "."]);
+        git(b.path(), &clone_args).unwrap();
+
+        // Both machines create entry 2 before syncing.
+        let day = |d| NaiveDate::from_ymd(2022, 5, d);
+        write(a.path().join("000000002.devlog"), "* From A\n").unwrap();
+        date::record_entry_date(a.path(), 2, day(2)).unwrap();
+        write(b.path().join("000000002.devlog"), "* From B\n").unwrap();
+        write(b.path().join("000000003.devlog"), "* Also from B\n").unwrap();
+        date::record_entry_date(b.path(), 2, day(12)).unwrap();
+        date::record_entry_date(b.path(), 3, day(13)).unwrap();
+
+        // Both machines track time and focus sessions before syncing.
+        let logs = [timelog::timelog_path, focus::focus_log_path];
+        for (i, log_path) in logs.iter().enumerate() {
+            write(log_path(a.path()), format!("from A {}\n", i)).unwrap();
+            write(log_path(b.path()), format!("from B {}\n", i)).unwrap();
+        }
+        sync(a.path()).unwrap();
+        let summary = sync(b.path()).unwrap();
+        assert_eq!(
+            summary.commit_message(),
+            Some("Add entries 2, 3; update focus.log, time.log")
+        );
+        assert_eq!(summary.renumbered(), &[(2, 3), (3, 4)]);
+
+        let read = |dir: &Path, seq: usize| read_to_string(dir.join(entry_file_name(seq))).unwrap();
+        assert_eq!(read(b.path(), 2), "* From A\n");
+        assert_eq!(read(b.path(), 3), "* From B\n");
+        assert_eq!(read(b.path(), 4), "* Also from B\n");
+
+        // The first machine receives the renumbered entries.
+        let summary = sync(a.path()).unwrap();
+        assert!(summary.renumbered().is_empty());
+        assert_eq!(read(a.path(), 3), "* From B\n");
+        assert_eq!(read(a.path(), 4), "* Also from B\n");
+
+        // The entry dates from both machines are merged.
+        for dir in &[a.path(), b.path()] {
+            let entry_date = |seq| date::entry_date(&dir.join(entry_file_name(seq))).unwrap();
+            assert_eq!(
+                (entry_date(2), entry_date(3), entry_date(4)),
+                (day(2), day(12), day(13))
+            );
+        }
+        // Both machines keep the time and focus logs from both sides.
+        for dir in &[a.path(), b.path()] {
+            for (i, log_path) in logs.iter().enumerate() {
+                let log = read_to_string(log_path(dir)).unwrap();
+                assert!(log.contains(&format!("from A {}\n", i)));
+                assert!(log.contains(&format!("from B {}\n", i)));
+            }
+        }
+        let status = git(b.path(), &["status", "--porcelain"]).unwrap();
+        assert_eq!(status, "");
+    }
+}
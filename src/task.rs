@@ -1,9 +1,15 @@
 //! A task is something the user wants or needs to do.
 
+use crate::date::parse_date;
+use chrono::NaiveDate;
 use std::fmt;
+use std::sync::Arc;
+
+/// Indentation for each level of subtasks when writing tasks to a devlog entry.
+pub const SUBTASK_INDENT: &str = "    ";
 
 /// Represents the user-assigned status of a task.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TaskStatus {
     /// The user has not yet started the task.
     ToDo,
@@ -16,9 +22,97 @@ pub enum TaskStatus {
 
     /// The user has completed the task.
     Done,
+
+    /// A status defined in the repository's configuration, such as "In Review".
+    Custom(Arc<CustomStatus>),
+}
+
+/// The definition of a custom task status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomStatus {
+    name: String,
+    marker: char,
+    display_name: String,
+    complete: bool,
+    carry_over: bool,
+}
+
+impl CustomStatus {
+    /// Defines a custom status.  `name` identifies the status in command-line arguments and JSON,
+    /// `marker` is the character that starts tasks with the status, and `display_name` is shown
+    /// in reports.  `complete` is whether the status means the task is finished,
+    /// and `carry_over` is whether rollover copies tasks with the status to the new entry.
+    pub fn new(
+        name: &str,
+        marker: char,
+        display_name: &str,
+        complete: bool,
+        carry_over: bool,
+    ) -> CustomStatus {
+        CustomStatus {
+            name: name.to_string(),
+            marker,
+            display_name: display_name.to_string(),
+            complete,
+            carry_over,
+        }
+    }
+
+    /// Returns a status for this definition.
+    pub fn into_status(self) -> TaskStatus {
+        TaskStatus::Custom(Arc::new(self))
+    }
 }
 
 impl TaskStatus {
+    /// Return a short, lowercase identifier for the task status (e.g. "todo"),
+    /// as used in command-line arguments and JSON.
+    pub fn name(&self) -> &str {
+        match self {
+            TaskStatus::ToDo => "todo",
+            TaskStatus::Started => "started",
+            TaskStatus::Blocked => "blocked",
+            TaskStatus::Done => "done",
+            TaskStatus::Custom(c) => &c.name,
+        }
+    }
+
+    /// Parse a built-in task status from its identifier (see `name`).
+    /// Returns `None` if the identifier is not recognized.
+    /// Custom statuses can be found with `ParseOptions::status_named`.
+    pub fn from_name(name: &str) -> Option<TaskStatus> {
+        match name {
+            "todo" => Some(TaskStatus::ToDo),
+            "started" => Some(TaskStatus::Started),
+            "blocked" => Some(TaskStatus::Blocked),
+            "done" => Some(TaskStatus::Done),
+            _ => None,
+        }
+    }
+
+    /// Return the character that marks a task with this status in a devlog entry.
+    pub fn marker(&self) -> char {
+        match self {
+            TaskStatus::ToDo => '*',
+            TaskStatus::Started => '^',
+            TaskStatus::Blocked => '-',
+            TaskStatus::Done => '+',
+            TaskStatus::Custom(c) => c.marker,
+        }
+    }
+
+    /// Parse a built-in task status from its marker character (see `marker`).
+    /// Returns `None` if the character is not a built-in task marker.
+    pub fn from_marker(c: char) -> Option<TaskStatus> {
+        match c {
+            '*' => Some(TaskStatus::ToDo),
+            '^' => Some(TaskStatus::Started),
+            '-' => Some(TaskStatus::Blocked),
+            '+' => Some(TaskStatus::Done),
+            _ => None,
+        }
+    }
+
     /// Return a human-readable name for the task status.
     pub fn display_name(&self) -> &str {
         match self {
@@ -26,6 +120,27 @@ impl TaskStatus {
             TaskStatus::Started => "In Progress",
             TaskStatus::Blocked => "Blocked",
             TaskStatus::Done => "Done",
+            TaskStatus::Custom(c) => &c.display_name,
+        }
+    }
+
+    /// Whether a task with this status is finished.
+    /// Of the built-in statuses, only `Done` is complete.
+    pub fn is_complete(&self) -> bool {
+        match self {
+            TaskStatus::Done => true,
+            TaskStatus::Custom(c) => c.complete,
+            _ => false,
+        }
+    }
+
+    /// Whether rollover copies tasks with this status to the new devlog entry.
+    /// Of the built-in statuses, all but `Done` carry over.
+    pub fn carries_over(&self) -> bool {
+        match self {
+            TaskStatus::Done => false,
+            TaskStatus::Custom(c) => c.carry_over,
+            _ => true,
         }
     }
 }
@@ -35,6 +150,8 @@ impl TaskStatus {
 pub struct Task {
     status: TaskStatus,
     content: String,
+    depth: usize,
+    body: Vec<String>,
 }
 
 impl Task {
@@ -43,61 +160,236 @@ impl Task {
         Task {
             status,
             content: content.to_string(),
+            depth: 0,
+            body: Vec::new(),
         }
     }
 
+    /// Returns the same task with the specified body lines, which follow the task's first line.
+    /// Body lines should not include the indentation that places them beneath the task.
+    pub fn with_body(mut self, body: Vec<String>) -> Task {
+        self.body = body;
+        self
+    }
+
+    /// Returns the same task nested at the specified depth,
+    /// where zero is a top-level task and one is a subtask of a top-level task.
+    pub fn with_depth(mut self, depth: usize) -> Task {
+        self.depth = depth;
+        self
+    }
+
     /// Parse a task from its string representation.
     /// A task string always begins with one of four characters:
     /// "*" means `ToDo`, "^" means `Started`, "+" means `Completed`,
     /// and "-" means `Blocked`.  The rest of the string, except for trailing whitespace,
     /// is the content of the task.  Returns `None` if the string is not a valid task.
     pub fn from_string(s: &str) -> Option<Task> {
-        let parse_content = |s: &str| s[1..].trim().to_string();
-        if s.starts_with("*") {
-            Some(Task {
-                status: TaskStatus::ToDo,
-                content: parse_content(s),
-            })
-        } else if s.starts_with("^") {
-            Some(Task {
-                status: TaskStatus::Started,
-                content: parse_content(s),
-            })
-        } else if s.starts_with("+") {
-            Some(Task {
-                status: TaskStatus::Done,
-                content: parse_content(s),
-            })
-        } else if s.starts_with("-") {
-            Some(Task {
-                status: TaskStatus::Blocked,
-                content: parse_content(s),
-            })
-        } else {
-            None
-        }
+        let marker = s.chars().next()?;
+        let status = TaskStatus::from_marker(marker)?;
+        Some(Task::new(status, s[marker.len_utf8()..].trim()))
+    }
+
+    /// Parse a task from its string representation, like `from_string`,
+    /// but recognizing the markers of `statuses` instead of the built-in markers.
+    pub fn from_string_with(s: &str, statuses: &[TaskStatus]) -> Option<Task> {
+        let marker = s.chars().next()?;
+        let status = statuses.iter().find(|status| status.marker() == marker)?;
+        Some(Task::new(status.clone(), s[marker.len_utf8()..].trim()))
     }
 
     /// Returns the status of the task.
     pub fn status(&self) -> TaskStatus {
-        self.status
+        self.status.clone()
     }
 
     /// Returns the content of the task.
     pub fn content(&self) -> &str {
         &self.content
     }
+
+    /// Returns how deeply the task is nested under other tasks.
+    /// Top-level tasks have depth zero.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the lines following the task's first line that continue its content,
+    /// for example a command or a stack trace.
+    pub fn body(&self) -> &[String] {
+        &self.body
+    }
+
+    /// Formats the task, followed by its body lines indented beneath it,
+    /// the way it is written in a devlog entry.
+    pub fn format_with_body(&self) -> String {
+        let mut s = self.to_string();
+        for line in &self.body {
+            s.push('\n');
+            if !line.is_empty() {
+                for _ in 0..=self.depth {
+                    s.push_str(SUBTASK_INDENT);
+                }
+                s.push_str(line);
+            }
+        }
+        s
+    }
+
+    /// Returns the priority in the task's content, if it has one.
+    /// See `Priority` for the syntax.
+    pub fn priority(&self) -> Option<Priority> {
+        parse_priority(&self.content)
+    }
+
+    /// Returns the due date in the task's content, if it has one.
+    /// See `parse_due_date` for the syntax.
+    pub fn due_date(&self, entry_date: NaiveDate) -> Option<NaiveDate> {
+        parse_due_date(&self.content, entry_date)
+    }
+
+    /// Returns the date until which the task is deferred, if it has one.
+    /// See `parse_defer_date` for the syntax.
+    pub fn defer_date(&self, entry_date: NaiveDate) -> Option<NaiveDate> {
+        parse_defer_date(&self.content, entry_date)
+    }
+
+    /// Returns a copy of the task with relative due and defer dates in its content,
+    /// like "@friday" or "defer:monday", rewritten as absolute dates resolved against `entry_date`.
+    /// Rollover uses this so the dates keep their meaning in later entries.
+    pub fn resolve_dates(&self, entry_date: NaiveDate) -> Task {
+        let mut content = String::with_capacity(self.content.len());
+        let mut rest = self.content.as_str();
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            content.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            content.push_str(&resolve_date_attribute(&rest[..end], entry_date));
+            rest = &rest[end..];
+        }
+        content.push_str(rest);
+        Task {
+            content,
+            ..self.clone()
+        }
+    }
+
+    /// Whether the task's content contains `query`, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        self.content.to_lowercase().contains(&query.to_lowercase())
+    }
+
+    /// Returns the tags in the task's content, in order of appearance.
+    /// A tag is a word starting with "#", like "#backend" or "#bug-123".
+    pub fn tags(&self) -> Vec<&str> {
+        parse_tags(&self.content)
+    }
+}
+
+/// The priority of a task, from "A" (most important) to "Z" (least important).
+/// A task has a priority if its content starts with a letter in parentheses, like "(A)",
+/// or contains a word of exclamation marks: "!!!" is priority "A", "!!" is "B", and "!" is "C".
+/// Priorities are part of the task's content, so they are preserved on rollover.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Priority(char);
+
+impl Priority {
+    /// Returns the uppercase letter for the priority.
+    pub fn letter(&self) -> char {
+        self.0
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Returns the priority in a task's content, if it has one.
+pub fn parse_priority(s: &str) -> Option<Priority> {
+    let mut words = s.split_whitespace();
+    let first = words.next()?;
+    let mut chars = first.chars();
+    if let (Some('('), Some(c), Some(')'), None) =
+        (chars.next(), chars.next(), chars.next(), chars.next())
+    {
+        if c.is_ascii_alphabetic() {
+            return Some(Priority(c.to_ascii_uppercase()));
+        }
+    }
+
+    std::iter::once(first)
+        .chain(words)
+        .filter(|w| w.chars().all(|c| c == '!'))
+        .map(|w| match w.len() {
+            1 => Priority('C'),
+            2 => Priority('B'),
+            _ => Priority('A'),
+        })
+        .min()
+}
+
+const DUE_DATE_PREFIXES: &[&str] = &["due:", "@"];
+const DEFER_DATE_PREFIXES: &[&str] = &["defer:"];
+const DATE_ATTRIBUTE_PREFIXES: &[&str] = &["due:", "@", "defer:"];
+
+/// Returns the first due date in a task's content, if it has one.
+/// A due date is written as "due:" or "@" followed by any date accepted by `date::parse_date`,
+/// like "due:2022-05-20" or "@friday".  Relative dates are resolved against `entry_date`,
+/// the date when the devlog entry containing the task was created.
+pub fn parse_due_date(s: &str, entry_date: NaiveDate) -> Option<NaiveDate> {
+    parse_date_attribute(s, DUE_DATE_PREFIXES, entry_date)
+}
+
+/// Returns the first defer date in a task's content, if it has one.
+/// A defer date is written as "defer:" followed by any date accepted by `date::parse_date`,
+/// like "defer:2022-05-20" or "defer:monday".  Relative dates are resolved against `entry_date`.
+pub fn parse_defer_date(s: &str, entry_date: NaiveDate) -> Option<NaiveDate> {
+    parse_date_attribute(s, DEFER_DATE_PREFIXES, entry_date)
+}
+
+/// Returns the date in the first word that starts with one of `prefixes` followed by a valid date.
+fn parse_date_attribute(s: &str, prefixes: &[&str], entry_date: NaiveDate) -> Option<NaiveDate> {
+    s.split_whitespace()
+        .map(|w| w.trim_end_matches(|c: char| !c.is_alphanumeric()))
+        .filter_map(|w| prefixes.iter().find_map(|p| w.strip_prefix(p)))
+        .find_map(|d| parse_date(d, entry_date))
+}
+
+/// Rewrites a word like "@friday," as "@2022-05-06," if it is a due or defer date.
+fn resolve_date_attribute(word: &str, entry_date: NaiveDate) -> String {
+    let attr = word.trim_end_matches(|c: char| !c.is_alphanumeric());
+    let suffix = &word[attr.len()..];
+    DATE_ATTRIBUTE_PREFIXES
+        .iter()
+        .find_map(|p| {
+            let d = parse_date(attr.strip_prefix(p)?, entry_date)?;
+            Some(format!("{}{}{}", p, d.format("%Y-%m-%d"), suffix))
+        })
+        .unwrap_or_else(|| word.to_string())
+}
+
+/// Returns the tags (words starting with "#") in a string.
+pub fn parse_tags(s: &str) -> Vec<&str> {
+    s.split_whitespace()
+        .map(|w| w.trim_end_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|w| {
+            w.len() > 1
+                && w.starts_with('#')
+                && w[1..]
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        })
+        .collect()
 }
 
 impl fmt::Display for Task {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.status {
-            TaskStatus::ToDo => write!(f, "* ")?,
-            TaskStatus::Started => write!(f, "^ ")?,
-            TaskStatus::Done => write!(f, "+ ")?,
-            TaskStatus::Blocked => write!(f, "- ")?,
-        };
-        write!(f, "{}", self.content)
+        for _ in 0..self.depth {
+            write!(f, "{}", SUBTASK_INDENT)?;
+        }
+        write!(f, "{} {}", self.status.marker(), self.content)
     }
 }
 
@@ -152,6 +444,62 @@ mod tests {
         assert_eq!(t.content(), "done");
     }
 
+    #[test]
+    fn test_status_names() {
+        for s in &[
+            TaskStatus::ToDo,
+            TaskStatus::Started,
+            TaskStatus::Blocked,
+            TaskStatus::Done,
+        ] {
+            assert_eq!(TaskStatus::from_name(s.name()), Some(s.clone()));
+        }
+        assert_eq!(TaskStatus::from_name("unknown"), None);
+    }
+
+    #[test]
+    fn test_status_markers() {
+        for s in &[
+            TaskStatus::ToDo,
+            TaskStatus::Started,
+            TaskStatus::Blocked,
+            TaskStatus::Done,
+        ] {
+            assert_eq!(TaskStatus::from_marker(s.marker()), Some(s.clone()));
+            let t = Task::new(s.clone(), "foo");
+            assert!(t.to_string().starts_with(s.marker()));
+        }
+        assert_eq!(TaskStatus::from_marker('x'), None);
+    }
+
+    #[test]
+    fn test_custom_status() {
+        let review = CustomStatus::new("review", '%', "In Review", false, true).into_status();
+        assert_eq!(review.name(), "review");
+        assert_eq!(review.display_name(), "In Review");
+        assert!(!review.is_complete());
+        assert!(review.carries_over());
+        assert!(TaskStatus::Done.is_complete());
+        assert!(!TaskStatus::Done.carries_over());
+
+        let statuses = [TaskStatus::ToDo, review.clone()];
+        let t = Task::from_string_with("% Foo", &statuses).unwrap();
+        assert_eq!(t.status(), review);
+        assert_eq!(t.to_string(), "% Foo");
+        assert!(Task::from_string("% Foo").is_none());
+        assert!(Task::from_string_with("+ Foo", &statuses).is_none());
+    }
+
+    #[test]
+    fn test_tags() {
+        let t = Task::new(
+            TaskStatus::ToDo,
+            "Fix #bug-12 in #backend, not #1.5 or # or a#b",
+        );
+        assert_eq!(t.tags(), vec!["#bug-12", "#backend"]);
+        assert!(Task::new(TaskStatus::ToDo, "No tags").tags().is_empty());
+    }
+
     #[test]
     fn test_fmt_todo() {
         let t = Task::new(TaskStatus::ToDo, "INCOMPLETE");
@@ -173,10 +521,128 @@ mod tests {
         assert_eq!(s, "+ DONE");
     }
 
+    #[test]
+    fn test_fmt_subtask() {
+        let t = Task::new(TaskStatus::ToDo, "SUBTASK").with_depth(2);
+        assert_eq!(t.to_string(), "        * SUBTASK");
+    }
+
+    #[test]
+    fn test_format_with_body() {
+        let body = vec!["$ cargo test".to_string(), "  --release".to_string()];
+        let t = Task::new(TaskStatus::Started, "Fix tests").with_body(body.clone());
+        assert_eq!(t.to_string(), "^ Fix tests");
+        assert_eq!(
+            t.format_with_body(),
+            "^ Fix tests\n    $ cargo test\n      --release"
+        );
+
+        let t = t.with_depth(1);
+        assert_eq!(
+            t.format_with_body(),
+            "    ^ Fix tests\n        $ cargo test\n          --release"
+        );
+    }
+
     #[test]
     fn test_fmt_blocked() {
         let t = Task::new(TaskStatus::Blocked, "BLOCKED");
         let s = format!("{}", t);
         assert_eq!(s, "- BLOCKED");
     }
+
+    #[test]
+    fn test_priority() {
+        let priority = |s: &str| {
+            Task::new(TaskStatus::ToDo, s)
+                .priority()
+                .map(|p| p.letter())
+        };
+        assert_eq!(priority("(A) Fix the build"), Some('A'));
+        assert_eq!(priority("(c) Update docs"), Some('C'));
+        assert_eq!(priority("Fix the build !!"), Some('B'));
+        assert_eq!(priority("! Fix ! the build !!!"), Some('A'));
+        assert_eq!(priority("Fix the build"), None);
+        assert_eq!(priority("Fix (A) the build"), None);
+        assert_eq!(priority("(AB) Fix the build"), None);
+        assert_eq!(priority("Wow! Fix the build"), None);
+        assert!(Priority('A') < Priority('B'));
+    }
+
+    #[test]
+    fn test_due_date() {
+        // 2022-05-04 is a Wednesday.
+        let entry_date = NaiveDate::from_ymd(2022, 5, 4);
+        let due = |s: &str| Task::new(TaskStatus::ToDo, s).due_date(entry_date);
+        assert_eq!(
+            due("Submit report due:2022-05-20"),
+            Some(NaiveDate::from_ymd(2022, 5, 20))
+        );
+        assert_eq!(
+            due("Submit report @friday."),
+            Some(NaiveDate::from_ymd(2022, 5, 6))
+        );
+        assert_eq!(
+            due("Submit report due:tomorrow"),
+            Some(NaiveDate::from_ymd(2022, 5, 5))
+        );
+        assert_eq!(due("Ask @alice about the report"), None);
+        assert_eq!(due("Submit report defer:friday"), None);
+        assert_eq!(due("Submit report"), None);
+    }
+
+    #[test]
+    fn test_defer_date() {
+        let entry_date = NaiveDate::from_ymd(2022, 5, 4);
+        let defer = |s: &str| Task::new(TaskStatus::ToDo, s).defer_date(entry_date);
+        assert_eq!(
+            defer("Plan offsite defer:monday"),
+            Some(NaiveDate::from_ymd(2022, 5, 9))
+        );
+        assert_eq!(defer("Plan offsite @monday"), None);
+    }
+
+    #[test]
+    fn test_resolve_dates() {
+        // 2022-05-04 is a Wednesday.
+        let entry_date = NaiveDate::from_ymd(2022, 5, 4);
+        let resolve = |s: &str| {
+            Task::new(TaskStatus::ToDo, s)
+                .resolve_dates(entry_date)
+                .content()
+                .to_string()
+        };
+        assert_eq!(
+            resolve("Plan offsite defer:monday, maybe"),
+            "Plan offsite defer:2022-05-09, maybe"
+        );
+        assert_eq!(
+            resolve("Submit  report @friday. Ask @alice due:2022-06-01"),
+            "Submit  report @2022-05-06. Ask @alice due:2022-06-01"
+        );
+        assert_eq!(
+            resolve("Submit report due:tomorrow"),
+            "Submit report due:2022-05-05"
+        );
+        assert_eq!(resolve("Submit report"), "Submit report");
+
+        let t = Task::new(TaskStatus::Blocked, "Foo @fri")
+            .with_depth(1)
+            .with_body(vec!["about foo".to_string()]);
+        let resolved = t.resolve_dates(entry_date);
+        assert_eq!(resolved.depth(), 1);
+        assert_eq!(resolved.body(), t.body());
+        assert_eq!(
+            resolved.due_date(NaiveDate::from_ymd(2022, 6, 1)),
+            t.due_date(entry_date)
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let t = Task::new(TaskStatus::ToDo, "Plan the Offsite #team");
+        assert!(t.matches("offsite"));
+        assert!(t.matches("#team"));
+        assert!(!t.matches("onsite"));
+    }
 }
@@ -0,0 +1,161 @@
+//! Templates for the initial contents of new devlog entry files.
+//!
+//! Templates are located in the `templates` subdirectory of the devlog repository.
+//! A template named after the weekday (e.g. `monday.devlog`) takes precedence
+//! over the default template (`daily.devlog`).  Since each repository has its own
+//! templates directory, different repositories can use different templates.
+//!
+//! Templates may contain these placeholders:
+//! * `{{date}}`: the date the entry was created, formatted like "2022-05-08".
+//! * `{{weekday}}`: the day of the week the entry was created, like "Monday".
+//! * `{{seq}}`: the sequence number of the entry.
+//! * `{{carried_tasks}}`: tasks carried over from the previous entry, one per line.
+//!   If the template does not contain this placeholder, carried tasks are added
+//!   to the end of the entry so they are never lost.
+
+use crate::error::Error;
+use crate::task::Task;
+use chrono::{Datelike, NaiveDate};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+const TEMPLATE_DIR_NAME: &str = "templates";
+const DEFAULT_TEMPLATE_NAME: &str = "daily";
+const TEMPLATE_EXT: &str = "devlog";
+
+const DATE_PLACEHOLDER: &str = "{{date}}";
+const WEEKDAY_PLACEHOLDER: &str = "{{weekday}}";
+const SEQ_PLACEHOLDER: &str = "{{seq}}";
+const CARRIED_TASKS_PLACEHOLDER: &str = "{{carried_tasks}}";
+
+/// Loads the template for an entry created on `date`,
+/// or returns `None` if the repository has no applicable template.
+pub fn load_template(repo_dir: &Path, date: NaiveDate) -> Result<Option<String>, Error> {
+    let weekday = weekday_name(date).to_lowercase();
+    for name in &[weekday.as_str(), DEFAULT_TEMPLATE_NAME] {
+        let p = template_path(repo_dir, name);
+        if p.is_file() {
+            return Ok(Some(read_to_string(&p)?));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the path to the template with the specified name (e.g. "daily" or "monday"),
+/// which may or may not exist.
+pub fn template_path(repo_dir: &Path, name: &str) -> PathBuf {
+    let mut p = repo_dir.to_path_buf();
+    p.push(TEMPLATE_DIR_NAME);
+    p.push(format!("{}.{}", name, TEMPLATE_EXT));
+    p
+}
+
+/// Replaces the placeholders in `template` for an entry created on `date`
+/// with sequence number `seq_num`, containing `carried_tasks` from the previous entry.
+pub fn render(template: &str, date: NaiveDate, seq_num: usize, carried_tasks: &[Task]) -> String {
+    let tasks: Vec<String> = carried_tasks.iter().map(|t| t.format_with_body()).collect();
+    let tasks = tasks.join("\n");
+
+    let mut s = template
+        .replace(DATE_PLACEHOLDER, &date.format("%Y-%m-%d").to_string())
+        .replace(WEEKDAY_PLACEHOLDER, weekday_name(date))
+        .replace(SEQ_PLACEHOLDER, &seq_num.to_string());
+
+    if s.contains(CARRIED_TASKS_PLACEHOLDER) {
+        s = s.replace(CARRIED_TASKS_PLACEHOLDER, &tasks);
+    } else if !tasks.is_empty() {
+        if !s.is_empty() && !s.ends_with('\n') {
+            s.push('\n');
+        }
+        s.push_str(&tasks);
+        s.push('\n');
+    }
+    s
+}
+
+fn weekday_name(date: NaiveDate) -> &'static str {
+    match date.weekday() {
+        chrono::Weekday::Mon => "Monday",
+        chrono::Weekday::Tue => "Tuesday",
+        chrono::Weekday::Wed => "Wednesday",
+        chrono::Weekday::Thu => "Thursday",
+        chrono::Weekday::Fri => "Friday",
+        chrono::Weekday::Sat => "Saturday",
+        chrono::Weekday::Sun => "Sunday",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::TaskStatus;
+    use std::fs::{create_dir_all, write};
+    use tempfile::tempdir;
+
+    fn sunday() -> NaiveDate {
+        NaiveDate::from_ymd(2022, 5, 8)
+    }
+
+    fn tasks() -> Vec<Task> {
+        vec![
+            Task::new(TaskStatus::ToDo, "Foo"),
+            Task::new(TaskStatus::Blocked, "Bar"),
+        ]
+    }
+
+    fn write_template(repo_dir: &Path, name: &str, contents: &str) {
+        let p = template_path(repo_dir, name);
+        create_dir_all(p.parent().unwrap()).unwrap();
+        write(&p, contents).unwrap();
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let template = "# {{weekday}} {{date}} (#{{seq}})\n\nGoals:\n{{carried_tasks}}\n\nNotes:\n";
+        let s = render(template, sunday(), 12, &tasks());
+        assert_eq!(
+            s,
+            "# Sunday 2022-05-08 (#12)\n\nGoals:\n* Foo\n- Bar\n\nNotes:\n"
+        );
+    }
+
+    #[test]
+    fn test_render_appends_tasks_without_placeholder() {
+        let s = render("Notes:", sunday(), 1, &tasks());
+        assert_eq!(s, "Notes:\n* Foo\n- Bar\n");
+    }
+
+    #[test]
+    fn test_render_no_tasks() {
+        let s = render("Goals:\n{{carried_tasks}}\n", sunday(), 1, &[]);
+        assert_eq!(s, "Goals:\n\n");
+        let s = render("Notes:\n", sunday(), 1, &[]);
+        assert_eq!(s, "Notes:\n");
+    }
+
+    #[test]
+    fn test_load_template_missing() {
+        let dir = tempdir().unwrap();
+        assert!(load_template(dir.path(), sunday()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_load_template_prefers_weekday() {
+        let dir = tempdir().unwrap();
+        write_template(dir.path(), "daily", "daily");
+        assert_eq!(
+            load_template(dir.path(), sunday()).unwrap().unwrap(),
+            "daily"
+        );
+
+        write_template(dir.path(), "sunday", "sunday");
+        assert_eq!(
+            load_template(dir.path(), sunday()).unwrap().unwrap(),
+            "sunday"
+        );
+        assert_eq!(
+            load_template(dir.path(), sunday().succ()).unwrap().unwrap(),
+            "daily"
+        );
+    }
+}
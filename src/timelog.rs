@@ -0,0 +1,342 @@
+//! Track time spent on tasks.
+//!
+//! Timers are recorded in the `time.log` file in the devlog repository.
+//! Each line records when a timer started or stopped.  Its fields are separated by tabs:
+//! an RFC 3339 timestamp like `2022-05-08T09:00:00-07:00`, then either `start` followed by
+//! the content of the task, like `Fix the build #ci`, or `stop`.
+//!
+//! Each line is written to disk as soon as the timer starts or stops,
+//! so a running timer survives the terminal closing or the machine crashing.
+
+use crate::error::Error;
+use crate::file::{LogFile, ParseOptions};
+use crate::repository::LogRepository;
+use crate::task::{parse_tags, Task, TaskStatus};
+use chrono::{DateTime, Duration, Local, NaiveDate, SecondsFormat};
+use std::collections::HashMap;
+use std::fs::{read_to_string, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const TIMELOG_FILE_NAME: &str = "time.log";
+const START: &str = "start";
+const STOP: &str = "stop";
+
+/// A period of time spent on a task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interval {
+    content: String,
+    start: DateTime<Local>,
+    end: Option<DateTime<Local>>,
+}
+
+impl Interval {
+    /// The content of the task, as it was when the timer started.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// When the timer started.
+    pub fn start(&self) -> DateTime<Local> {
+        self.start
+    }
+
+    /// When the timer stopped, or `None` if it is still running.
+    pub fn end(&self) -> Option<DateTime<Local>> {
+        self.end
+    }
+
+    /// The time spent on the task, counting a running timer up to `now`.
+    pub fn duration(&self, now: DateTime<Local>) -> Duration {
+        self.end.unwrap_or(now) - self.start
+    }
+}
+
+/// Returns the path to the time log file, which may or may not exist.
+pub fn timelog_path(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(TIMELOG_FILE_NAME)
+}
+
+/// Loads all recorded intervals, in the order they started.
+/// Malformed lines, such as a line cut short by a crash, are ignored.
+pub fn load(repo_dir: &Path) -> Result<Vec<Interval>, Error> {
+    let p = timelog_path(repo_dir);
+    if !p.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let mut intervals: Vec<Interval> = Vec::new();
+    for line in read_to_string(&p)?.lines() {
+        let mut fields = line.splitn(3, '\t');
+        let t = match fields.next().map(DateTime::parse_from_rfc3339) {
+            Some(Ok(t)) => t.with_timezone(&Local),
+            _ => continue,
+        };
+        match (fields.next(), fields.next()) {
+            (Some(START), Some(content)) => {
+                stop_last(&mut intervals, t);
+                intervals.push(Interval {
+                    content: content.to_string(),
+                    start: t,
+                    end: None,
+                });
+            }
+            (Some(STOP), _) => stop_last(&mut intervals, t),
+            _ => {}
+        }
+    }
+    Ok(intervals)
+}
+
+fn stop_last(intervals: &mut [Interval], t: DateTime<Local>) {
+    if let Some(last) = intervals.last_mut() {
+        if last.end.is_none() {
+            last.end = Some(t);
+        }
+    }
+}
+
+/// Returns the running timer, if there is one.
+pub fn running(repo_dir: &Path) -> Result<Option<Interval>, Error> {
+    Ok(load(repo_dir)?.pop().filter(|i| i.end.is_none()))
+}
+
+/// Starts a timer for the task with the specified content at `now`.
+/// Only one timer runs at a time, so this stops the running timer, if any, and returns it.
+pub fn start(
+    repo_dir: &Path,
+    content: &str,
+    now: DateTime<Local>,
+) -> Result<Option<Interval>, Error> {
+    let stopped = stop(repo_dir, now)?;
+    // Tabs and newlines would corrupt the log, and are never part of a task's first line.
+    let content = content.replace(['\t', '\n'], " ");
+    append(
+        repo_dir,
+        &format!("{}\t{}\t{}", timestamp(now), START, content),
+    )?;
+    Ok(stopped)
+}
+
+/// Stops the running timer at `now` and returns it, or returns `None` if no timer is running.
+pub fn stop(repo_dir: &Path, now: DateTime<Local>) -> Result<Option<Interval>, Error> {
+    match running(repo_dir)? {
+        Some(mut interval) => {
+            append(repo_dir, &format!("{}\t{}", timestamp(now), STOP))?;
+            interval.end = Some(now);
+            Ok(Some(interval))
+        }
+        None => Ok(None),
+    }
+}
+
+fn timestamp(t: DateTime<Local>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+fn append(repo_dir: &Path, line: &str) -> Result<(), Error> {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(timelog_path(repo_dir))?;
+    writeln!(f, "{}", line)?;
+    f.sync_data()?;
+    Ok(())
+}
+
+/// Finds the tasks in the latest devlog entry that match `query` (see `Task::matches`).
+/// If exactly one task matches, marks it as started and starts a timer for it at `now`.
+/// Returns the matching tasks, so the caller can report when zero or several tasks match.
+pub fn start_task(
+    repo: &LogRepository,
+    options: &ParseOptions,
+    query: &str,
+    now: DateTime<Local>,
+) -> Result<Vec<Task>, Error> {
+    let matches = mark_started(repo, options, query)?;
+    if let [t] = &matches[..] {
+        start(repo.path(), t.content(), now)?;
+    }
+    Ok(matches)
+}
+
+/// Finds the tasks in the latest devlog entry that match `query` (see `Task::matches`).
+/// If exactly one task matches, marks it as started.
+/// Returns the matching tasks, so the caller can report when zero or several tasks match.
+pub fn mark_started(
+    repo: &LogRepository,
+    options: &ParseOptions,
+    query: &str,
+) -> Result<Vec<Task>, Error> {
+    let latest = repo
+        .latest()?
+        .ok_or(Error::InvalidArg("no devlog entries"))?;
+    let mut f = LogFile::load_with(latest.path(), options)?;
+    let matches = f.find_tasks(query);
+    if let [i] = matches[..] {
+        if f.tasks()[i].status() != TaskStatus::Started {
+            f.set_status(i, TaskStatus::Started);
+            f.save(latest.path())?;
+        }
+    }
+    Ok(matches.iter().map(|&i| f.tasks()[i].clone()).collect())
+}
+
+/// Prints the total time spent on each task and tag in intervals that started on or after `since`,
+/// with the most time first.  A running timer counts up to `now`.
+pub fn print_report<W: Write>(
+    w: &mut W,
+    intervals: &[Interval],
+    since: NaiveDate,
+    now: DateTime<Local>,
+) -> Result<(), Error> {
+    let mut tasks: Vec<(String, Duration)> = Vec::new();
+    let mut tags: HashMap<String, Duration> = HashMap::new();
+    let mut total = Duration::zero();
+    for interval in intervals
+        .iter()
+        .filter(|i| i.start.naive_local().date() >= since)
+    {
+        let d = interval.duration(now);
+        total = total + d;
+        match tasks.iter_mut().find(|(c, _)| *c == interval.content) {
+            Some((_, sum)) => *sum = *sum + d,
+            None => tasks.push((interval.content.clone(), d)),
+        }
+        for tag in parse_tags(&interval.content) {
+            let sum = tags.entry(tag.to_string()).or_insert_with(Duration::zero);
+            *sum = *sum + d;
+        }
+    }
+
+    if tasks.is_empty() {
+        return Ok(());
+    }
+
+    let mut tags: Vec<(String, Duration)> = tags.into_iter().collect();
+    tasks.sort_by_key(|(_, d)| std::cmp::Reverse(*d));
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    writeln!(w, "Tasks:")?;
+    for (content, d) in &tasks {
+        writeln!(w, "{:>8}  {}", format_duration(*d), content)?;
+    }
+    if !tags.is_empty() {
+        writeln!(w, "\nTags:")?;
+        for (tag, d) in &tags {
+            writeln!(w, "{:>8}  {}", format_duration(*d), tag)?;
+        }
+    }
+    writeln!(w, "\nTotal: {}", format_duration(total))?;
+    Ok(())
+}
+
+/// Formats a duration in hours and minutes, like "1h 05m" or "45m".
+pub fn format_duration(d: Duration) -> String {
+    let minutes = d.num_minutes().max(0);
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, m) => format!("{}h {:02}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::str;
+    use tempfile::tempdir;
+
+    fn at(h: u32, m: u32) -> DateTime<Local> {
+        Local.ymd(2022, 5, 8).and_hms(h, m, 0)
+    }
+
+    #[test]
+    fn test_start_and_stop() {
+        let dir = tempdir().unwrap();
+        assert_eq!(running(dir.path()).unwrap(), None);
+        assert_eq!(stop(dir.path(), at(9, 0)).unwrap(), None);
+
+        assert_eq!(start(dir.path(), "Foo", at(9, 0)).unwrap(), None);
+        assert_eq!(running(dir.path()).unwrap().unwrap().content(), "Foo");
+
+        let stopped = start(dir.path(), "Bar", at(9, 30)).unwrap().unwrap();
+        assert_eq!(stopped.content(), "Foo");
+        assert_eq!(stopped.duration(at(12, 0)), Duration::minutes(30));
+
+        let stopped = stop(dir.path(), at(10, 0)).unwrap().unwrap();
+        assert_eq!(stopped.content(), "Bar");
+        assert_eq!(running(dir.path()).unwrap(), None);
+
+        let intervals = load(dir.path()).unwrap();
+        assert_eq!(intervals.len(), 2);
+        assert_eq!(intervals[1].end(), Some(at(10, 0)));
+    }
+
+    #[test]
+    fn test_load_ignores_malformed_lines() {
+        let dir = tempdir().unwrap();
+        start(dir.path(), "Foo", at(9, 0)).unwrap();
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(timelog_path(dir.path()))
+            .unwrap();
+        write!(f, "garbage\n2022-05-08T10:").unwrap();
+        let intervals = load(dir.path()).unwrap();
+        assert_eq!(intervals.len(), 1);
+        assert_eq!(intervals[0].end(), None);
+    }
+
+    #[test]
+    fn test_start_task() {
+        let dir = tempdir().unwrap();
+        let repo = LogRepository::new(dir.path());
+        let logpath = repo.init().unwrap();
+        std::fs::write(logpath.path(), "* Fix the build #ci\n* Fix docs\n").unwrap();
+        let options = ParseOptions::default();
+
+        let matches = start_task(&repo, &options, "fix", at(9, 0)).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(running(dir.path()).unwrap(), None);
+
+        let matches = start_task(&repo, &options, "build", at(9, 0)).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(logpath.path()).unwrap(),
+            "^ Fix the build #ci\n* Fix docs\n"
+        );
+        assert_eq!(
+            running(dir.path()).unwrap().unwrap().content(),
+            "Fix the build #ci"
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let dir = tempdir().unwrap();
+        start(dir.path(), "Fix the build #ci", at(9, 0)).unwrap();
+        start(dir.path(), "Write docs", at(10, 30)).unwrap();
+        start(dir.path(), "Fix the build #ci", at(11, 0)).unwrap();
+        let intervals = load(dir.path()).unwrap();
+
+        let mut buf = Vec::new();
+        let since = NaiveDate::from_ymd(2022, 5, 8);
+        print_report(&mut buf, &intervals, since, at(11, 15)).unwrap();
+        assert_eq!(
+            str::from_utf8(&buf).unwrap(),
+            "Tasks:\n  1h 45m  Fix the build #ci\n     30m  Write docs\n\nTags:\n  1h 45m  #ci\n\nTotal: 2h 15m\n"
+        );
+
+        let mut buf = Vec::new();
+        let since = NaiveDate::from_ymd(2022, 5, 9);
+        print_report(&mut buf, &intervals, since, at(11, 15)).unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::minutes(45)), "45m");
+        assert_eq!(format_duration(Duration::minutes(65)), "1h 05m");
+        assert_eq!(format_duration(Duration::minutes(600)), "10h 00m");
+    }
+}
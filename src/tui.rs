@@ -0,0 +1,778 @@
+//! Interactive terminal UI for viewing and updating devlog entries.
+//!
+//! The UI shows the tasks of a devlog entry grouped by status, like `devlog status`.
+//! Every change is saved to the entry file immediately, leaving notes and other
+//! non-task lines untouched.  Drawing and input go through the `Backend` trait,
+//! so the UI can run against a real terminal (`TerminalBackend`) or be tested
+//! without one (`HeadlessBackend`).
+
+use crate::config::Config;
+use crate::date;
+use crate::deferred;
+use crate::error::Error;
+use crate::file::LogFile;
+use crate::lock::RepoLock;
+use crate::path::LogPath;
+use crate::repository::LogRepository;
+use crate::rollover;
+use crate::task::{Task, TaskStatus};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, execute, queue, style, terminal};
+use std::collections::VecDeque;
+use std::io::Write;
+
+const HELP_LINE: &str =
+    "j/k:select t/s/b/d:status space:done a:add J/K:move [/]:browse r:rollover q:quit";
+
+/// A key pressed by the user.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Esc,
+    Backspace,
+
+    /// Any other input, such as a terminal resize.  The UI is redrawn but otherwise unchanged.
+    Other,
+}
+
+/// Draws the UI and reads input.
+pub trait Backend {
+    /// Returns the size of the drawing area as (width, height) in characters.
+    fn size(&self) -> Result<(usize, usize), Error>;
+
+    /// Replaces the contents of the drawing area with `lines`.
+    fn draw(&mut self, lines: &[String]) -> Result<(), Error>;
+
+    /// Waits for the next key.  Returns `None` if there is no more input.
+    fn next_key(&mut self) -> Result<Option<Key>, Error>;
+}
+
+/// A backend that replays a fixed sequence of keys and records every frame it draws.
+pub struct HeadlessBackend {
+    width: usize,
+    height: usize,
+    keys: VecDeque<Key>,
+    frames: Vec<Vec<String>>,
+}
+
+impl HeadlessBackend {
+    /// Creates a backend with a drawing area of the specified size,
+    /// which will provide `keys` as input.
+    pub fn new(width: usize, height: usize, keys: &[Key]) -> HeadlessBackend {
+        HeadlessBackend {
+            width,
+            height,
+            keys: keys.iter().copied().collect(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Returns every frame drawn so far, oldest first.
+    pub fn frames(&self) -> &[Vec<String>] {
+        &self.frames
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn size(&self) -> Result<(usize, usize), Error> {
+        Ok((self.width, self.height))
+    }
+
+    fn draw(&mut self, lines: &[String]) -> Result<(), Error> {
+        self.frames.push(lines.to_vec());
+        Ok(())
+    }
+
+    fn next_key(&mut self) -> Result<Option<Key>, Error> {
+        Ok(self.keys.pop_front())
+    }
+}
+
+/// A backend that draws to a terminal using the alternate screen.
+/// The terminal is restored when the backend is dropped.
+pub struct TerminalBackend<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TerminalBackend<W> {
+    /// Switches the terminal to raw mode and the alternate screen.
+    pub fn new(mut out: W) -> Result<TerminalBackend<W>, Error> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(TerminalBackend { out })
+    }
+}
+
+impl<W: Write> Backend for TerminalBackend<W> {
+    fn size(&self) -> Result<(usize, usize), Error> {
+        let (w, h) = terminal::size()?;
+        Ok((w as usize, h as usize))
+    }
+
+    fn draw(&mut self, lines: &[String]) -> Result<(), Error> {
+        queue!(self.out, terminal::Clear(terminal::ClearType::All))?;
+        for (row, line) in lines.iter().enumerate() {
+            queue!(self.out, cursor::MoveTo(0, row as u16), style::Print(line))?;
+        }
+        self.out.flush().map_err(From::from)
+    }
+
+    fn next_key(&mut self) -> Result<Option<Key>, Error> {
+        loop {
+            let key = match event::read()? {
+                Event::Key(k) if k.kind != KeyEventKind::Release => match k.code {
+                    KeyCode::Char(c) => Key::Char(c),
+                    KeyCode::Up => Key::Up,
+                    KeyCode::Down => Key::Down,
+                    KeyCode::Left => Key::Left,
+                    KeyCode::Right => Key::Right,
+                    KeyCode::Enter => Key::Enter,
+                    KeyCode::Esc => Key::Esc,
+                    KeyCode::Backspace => Key::Backspace,
+                    _ => continue,
+                },
+                Event::Resize(_, _) => Key::Other,
+                _ => continue,
+            };
+            return Ok(Some(key));
+        }
+    }
+}
+
+impl<W: Write> Drop for TerminalBackend<W> {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, terminal::LeaveAlternateScreen, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+enum Mode {
+    Browse,
+    AddTask(String),
+    ConfirmRollover(Vec<Task>),
+}
+
+/// The state of the UI: which entry is displayed, which task is selected,
+/// and what the user is currently doing.
+pub struct App<'a> {
+    config: &'a Config,
+    repo: &'a LogRepository,
+    logpath: LogPath,
+    logfile: LogFile,
+    selected: Option<usize>,
+    mode: Mode,
+    message: Option<String>,
+    quit: bool,
+}
+
+impl<'a> App<'a> {
+    /// Creates the UI state, displaying the most recent entry in the repository.
+    pub fn new(config: &'a Config, repo: &'a LogRepository) -> Result<App<'a>, Error> {
+        let logpath = repo
+            .latest()?
+            .ok_or(Error::InvalidArg("repository has not been initialized"))?;
+        let logfile = LogFile::load_with(logpath.path(), config.parse_options())?;
+        let mut app = App {
+            config,
+            repo,
+            logpath,
+            logfile,
+            selected: None,
+            mode: Mode::Browse,
+            message: None,
+            quit: false,
+        };
+        app.selected = app.display_order().first().copied();
+        Ok(app)
+    }
+
+    /// Returns the path of the displayed entry.
+    pub fn logpath(&self) -> &LogPath {
+        &self.logpath
+    }
+
+    /// Returns the displayed entry.
+    pub fn logfile(&self) -> &LogFile {
+        &self.logfile
+    }
+
+    /// Returns the index of the selected task, if any.
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Whether the user asked to quit.
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Updates the state in response to a key press.
+    /// Changes to tasks are saved to the entry file immediately.
+    pub fn handle_key(&mut self, key: Key) -> Result<(), Error> {
+        self.message = None;
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Browse => self.handle_browse_key(key),
+            Mode::AddTask(input) => self.handle_add_task_key(key, input),
+            Mode::ConfirmRollover(tasks) => self.handle_rollover_key(key, tasks),
+        }
+    }
+
+    fn handle_browse_key(&mut self, key: Key) -> Result<(), Error> {
+        match key {
+            Key::Char('q') | Key::Esc => self.quit = true,
+            Key::Char('j') | Key::Down => self.move_selection(1),
+            Key::Char('k') | Key::Up => self.move_selection(-1),
+            Key::Char('t') => self.set_selected_status(TaskStatus::ToDo)?,
+            Key::Char('s') => self.set_selected_status(TaskStatus::Started)?,
+            Key::Char('b') => self.set_selected_status(TaskStatus::Blocked)?,
+            Key::Char('d') => self.set_selected_status(TaskStatus::Done)?,
+            Key::Char(' ') => self.toggle_selected_done()?,
+            Key::Char('J') => self.reorder_selected(1)?,
+            Key::Char('K') => self.reorder_selected(-1)?,
+            Key::Char('a') => self.mode = Mode::AddTask(String::new()),
+            Key::Char('[') | Key::Left => self.browse(-1)?,
+            Key::Char(']') | Key::Right => self.browse(1)?,
+            Key::Char('r') => self.preview_rollover()?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_add_task_key(&mut self, key: Key, mut input: String) -> Result<(), Error> {
+        match key {
+            Key::Enter => {
+                let content = input.trim();
+                if !content.is_empty() {
+                    if let Some(_lock) = self.lock_for_save()? {
+                        let i = self.logfile.add_task(Task::new(TaskStatus::ToDo, content));
+                        self.selected = Some(i);
+                        self.save()?;
+                    }
+                }
+            }
+            Key::Esc => {}
+            Key::Backspace => {
+                input.pop();
+                self.mode = Mode::AddTask(input);
+            }
+            Key::Char(c) => {
+                input.push(c);
+                self.mode = Mode::AddTask(input);
+            }
+            _ => self.mode = Mode::AddTask(input),
+        }
+        Ok(())
+    }
+
+    fn handle_rollover_key(&mut self, key: Key, tasks: Vec<Task>) -> Result<(), Error> {
+        match key {
+            Key::Char('y') | Key::Enter => {
+                let _lock = match self.try_lock()? {
+                    Some(lock) => lock,
+                    None => return Ok(()),
+                };
+                let mut out = Vec::new();
+                let (next, count) = rollover::rollover(&mut out, self.config, &self.logpath)?;
+                let hook_output = String::from_utf8_lossy(&out).trim().to_string();
+                self.open_entry(next)?;
+                self.message = Some(if hook_output.is_empty() {
+                    format!("Imported {} tasks into a new devlog", count)
+                } else {
+                    hook_output
+                });
+            }
+            Key::Char('n') | Key::Esc => {}
+            _ => self.mode = Mode::ConfirmRollover(tasks),
+        }
+        Ok(())
+    }
+
+    /// Task indices in the order they are displayed: grouped by status, then in file order.
+    fn display_order(&self) -> Vec<usize> {
+        let tasks = self.logfile.tasks();
+        self.config
+            .parse_options()
+            .statuses()
+            .iter()
+            .flat_map(|status| (0..tasks.len()).filter(move |&i| tasks[i].status() == *status))
+            .collect()
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let order = self.display_order();
+        if let Some(pos) = self.selected_position(&order) {
+            let new_pos = pos as isize + delta;
+            if new_pos >= 0 && (new_pos as usize) < order.len() {
+                self.selected = Some(order[new_pos as usize]);
+            }
+        }
+    }
+
+    fn selected_position(&self, order: &[usize]) -> Option<usize> {
+        self.selected
+            .and_then(|selected| order.iter().position(|&i| i == selected))
+    }
+
+    fn set_selected_status(&mut self, status: TaskStatus) -> Result<(), Error> {
+        if let Some(i) = self.selected {
+            if self.logfile.tasks()[i].status() != status {
+                if let Some(_lock) = self.lock_for_save()? {
+                    self.logfile.set_status(i, status);
+                    self.save()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn toggle_selected_done(&mut self) -> Result<(), Error> {
+        if let Some(i) = self.selected {
+            let status = match self.logfile.tasks()[i].status() {
+                TaskStatus::Done => TaskStatus::ToDo,
+                _ => TaskStatus::Done,
+            };
+            self.set_selected_status(status)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the selected task up or down within its status group.
+    fn reorder_selected(&mut self, delta: isize) -> Result<(), Error> {
+        let order = self.display_order();
+        if let (Some(i), Some(pos)) = (self.selected, self.selected_position(&order)) {
+            let other_pos = pos as isize + delta;
+            if other_pos >= 0 && (other_pos as usize) < order.len() {
+                let j = order[other_pos as usize];
+                let tasks = self.logfile.tasks();
+                if tasks[i].status() == tasks[j].status() {
+                    if let Some(_lock) = self.lock_for_save()? {
+                        self.logfile.swap_tasks(i, j);
+                        self.selected = Some(j);
+                        self.save()?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Displays the previous (`delta < 0`) or next (`delta > 0`) entry in the repository.
+    fn browse(&mut self, delta: isize) -> Result<(), Error> {
+        let mut paths = self.repo.list()?;
+        paths.sort();
+        if let Some(pos) = paths.iter().position(|p| *p == self.logpath) {
+            let new_pos = pos as isize + delta;
+            if new_pos >= 0 && (new_pos as usize) < paths.len() {
+                let p = paths.swap_remove(new_pos as usize);
+                self.open_entry(p)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn preview_rollover(&mut self) -> Result<(), Error> {
+        if self.is_latest()? {
+            let options = self.config.parse_options();
+            let today = date::today();
+            let mut tasks = rollover::load_carryover_tasks(self.logpath.path(), options, today)?;
+            tasks.extend(deferred::load_ready(
+                self.config.repo_dir(),
+                options,
+                today,
+            )?);
+            self.mode = Mode::ConfirmRollover(tasks);
+        } else {
+            self.message = Some("Only the most recent devlog can be rolled over".to_string());
+        }
+        Ok(())
+    }
+
+    fn is_latest(&self) -> Result<bool, Error> {
+        Ok(self.repo.latest()?.as_ref() == Some(&self.logpath))
+    }
+
+    fn open_entry(&mut self, logpath: LogPath) -> Result<(), Error> {
+        self.logfile = LogFile::load_with(logpath.path(), self.config.parse_options())?;
+        self.logpath = logpath;
+        self.selected = self.display_order().first().copied();
+        Ok(())
+    }
+
+    /// Acquires the repository lock, or shows a message and returns `None`
+    /// if another devlog command holds it.
+    fn try_lock(&mut self) -> Result<Option<RepoLock>, Error> {
+        match RepoLock::acquire(self.config.repo_dir()) {
+            Ok(lock) => Ok(Some(lock)),
+            Err(Error::RepositoryLocked(_)) => {
+                self.message =
+                    Some("Another devlog command is modifying the repository".to_string());
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Acquires the repository lock before changing the displayed entry.
+    /// If another command changed the entry since it was loaded, the entry is reloaded
+    /// and `None` is returned instead, so the change isn't applied to the wrong task.
+    fn lock_for_save(&mut self) -> Result<Option<RepoLock>, Error> {
+        let lock = match self.try_lock()? {
+            Some(lock) => lock,
+            None => return Ok(None),
+        };
+        let current = LogFile::load_with(self.logpath.path(), self.config.parse_options())?;
+        if current == self.logfile {
+            return Ok(Some(lock));
+        }
+        self.logfile = current;
+        if self
+            .selected
+            .is_some_and(|i| i >= self.logfile.tasks().len())
+        {
+            self.selected = self.display_order().first().copied();
+        }
+        self.message = Some("The devlog changed on disk and was reloaded".to_string());
+        Ok(None)
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        self.logfile.save(self.logpath.path()).map_err(From::from)
+    }
+
+    /// Renders the UI as lines of text that fit within `width` and `height`.
+    pub fn render(&self, width: usize, height: usize) -> Vec<String> {
+        let header = format!(
+            "devlog #{}{}",
+            self.logpath.seq_num(),
+            if self.is_latest().unwrap_or(false) {
+                " (latest)"
+            } else {
+                ""
+            }
+        );
+
+        let (body, selected_row) = match &self.mode {
+            Mode::ConfirmRollover(tasks) => (self.render_rollover_preview(tasks), None),
+            _ => self.render_tasks(),
+        };
+
+        let footer = match (&self.mode, &self.message) {
+            (Mode::AddTask(input), _) => format!("New task: {}_", input),
+            (Mode::ConfirmRollover(_), _) => "Rollover? y: confirm  n: cancel".to_string(),
+            (Mode::Browse, Some(msg)) => msg.clone(),
+            (Mode::Browse, None) => HELP_LINE.to_string(),
+        };
+
+        // Scroll the body so the selected task stays visible.
+        let body_height = height.saturating_sub(3);
+        let offset = match selected_row {
+            Some(row) if row >= body_height => row + 1 - body_height,
+            _ => 0,
+        };
+
+        let mut lines = Vec::with_capacity(height);
+        lines.push(header);
+        lines.push(String::new());
+        lines.extend(body.into_iter().skip(offset).take(body_height));
+        while lines.len() + 1 < height {
+            lines.push(String::new());
+        }
+        lines.push(footer);
+        lines
+            .into_iter()
+            .take(height)
+            .map(|l| l.chars().take(width).collect())
+            .collect()
+    }
+
+    fn render_tasks(&self) -> (Vec<String>, Option<usize>) {
+        let tasks = self.logfile.tasks();
+        if tasks.is_empty() {
+            return (vec!["No tasks.  Press \"a\" to add one.".to_string()], None);
+        }
+
+        let mut body = Vec::new();
+        let mut selected_row = None;
+        for status in self.config.parse_options().statuses() {
+            let section: Vec<usize> = (0..tasks.len())
+                .filter(|&i| tasks[i].status() == *status)
+                .collect();
+            if section.is_empty() {
+                continue;
+            }
+            if !body.is_empty() {
+                body.push(String::new());
+            }
+            body.push(format!("{}:", status.display_name()));
+            for i in section {
+                let marker = if self.selected == Some(i) {
+                    selected_row = Some(body.len());
+                    ">"
+                } else {
+                    " "
+                };
+                body.push(format!("{} {}", marker, tasks[i]));
+            }
+        }
+        (body, selected_row)
+    }
+
+    fn render_rollover_preview(&self, tasks: &[Task]) -> Vec<String> {
+        let mut body = vec![format!(
+            "Rollover will copy {} tasks into a new devlog:",
+            tasks.len()
+        )];
+        body.extend(tasks.iter().map(|t| format!("  {}", t)));
+        body
+    }
+}
+
+/// Runs the UI until the user quits or the backend has no more input.
+pub fn run<B: Backend>(
+    backend: &mut B,
+    config: &Config,
+    repo: &LogRepository,
+) -> Result<(), Error> {
+    let mut app = App::new(config, repo)?;
+    loop {
+        let (width, height) = backend.size()?;
+        backend.draw(&app.render(width, height))?;
+        if app.should_quit() {
+            return Ok(());
+        }
+        match backend.next_key()? {
+            Some(key) => app.handle_key(key)?,
+            None => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+    use tempfile::tempdir;
+
+    const ENTRY: &str = "Notes at the top\n* Foo\n    about foo\n^ Bar\n+ Baz\n* Boo\n";
+
+    fn init_repo(dir: &std::path::Path) -> (Config, LogRepository) {
+        let repo = LogRepository::new(dir);
+        let p = repo.init().unwrap();
+        std::fs::write(p.path(), ENTRY).unwrap();
+        (Config::new(dir, ""), repo)
+    }
+
+    fn run_keys(config: &Config, repo: &LogRepository, keys: &[Key]) -> HeadlessBackend {
+        let mut backend = HeadlessBackend::new(80, 20, keys);
+        run(&mut backend, config, repo).unwrap();
+        backend
+    }
+
+    fn chars(s: &str) -> Vec<Key> {
+        s.chars().map(Key::Char).collect()
+    }
+
+    fn latest_contents(repo: &LogRepository) -> String {
+        read_to_string(repo.latest().unwrap().unwrap().path()).unwrap()
+    }
+
+    #[test]
+    fn test_render_grouped_by_status() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let backend = run_keys(&config, &repo, &[]);
+        let frame = &backend.frames()[0];
+        assert_eq!(
+            &frame[..11],
+            &[
+                "devlog #1 (latest)",
+                "",
+                "In Progress:",
+                "> ^ Bar",
+                "",
+                "To Do:",
+                "  * Foo",
+                "  * Boo",
+                "",
+                "Done:",
+                "  + Baz",
+            ]
+        );
+        assert_eq!(frame.len(), 20);
+        assert_eq!(frame[19], HELP_LINE);
+    }
+
+    #[test]
+    fn test_set_status_preserves_notes() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        // Select "Foo" and mark it done, which moves it to the "Done" section.
+        // Then select "Bar" and mark it blocked.
+        run_keys(&config, &repo, &chars("jdkkb"));
+        assert_eq!(
+            latest_contents(&repo),
+            "Notes at the top\n+ Foo\n    about foo\n- Bar\n+ Baz\n* Boo\n"
+        );
+    }
+
+    #[test]
+    fn test_toggle_done() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        run_keys(&config, &repo, &chars(" "));
+        assert!(latest_contents(&repo).contains("\n+ Bar\n"));
+    }
+
+    #[test]
+    fn test_add_task() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let mut keys = chars("aNew tasx");
+        keys.push(Key::Backspace);
+        keys.push(Key::Char('k'));
+        keys.push(Key::Enter);
+        let backend = run_keys(&config, &repo, &keys);
+        assert_eq!(
+            latest_contents(&repo),
+            "Notes at the top\n* Foo\n    about foo\n^ Bar\n+ Baz\n* Boo\n* New task\n"
+        );
+        let frames = backend.frames();
+        assert_eq!(frames[frames.len() - 2][19], "New task: New task_");
+        assert!(frames[frames.len() - 1].contains(&"> * New task".to_string()));
+    }
+
+    #[test]
+    fn test_cancel_add_task() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let mut keys = chars("afoo");
+        keys.push(Key::Esc);
+        run_keys(&config, &repo, &keys);
+        assert_eq!(latest_contents(&repo), ENTRY);
+    }
+
+    #[test]
+    fn test_reorder_within_group() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        // Select "Foo", move it below "Boo", then try to move it past the end of the group.
+        // The indented line continues "Foo", so it moves too.
+        run_keys(&config, &repo, &chars("jJJ"));
+        assert_eq!(
+            latest_contents(&repo),
+            "Notes at the top\n* Boo\n^ Bar\n+ Baz\n* Foo\n    about foo\n"
+        );
+    }
+
+    #[test]
+    fn test_rollover_with_preview() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let backend = run_keys(&config, &repo, &chars("ry"));
+        let preview = &backend.frames()[1];
+        assert_eq!(preview[2], "Rollover will copy 3 tasks into a new devlog:");
+        assert_eq!(preview[3], "  * Foo");
+
+        let latest = repo.latest().unwrap().unwrap();
+        assert_eq!(latest.seq_num(), 2);
+        assert_eq!(
+            latest_contents(&repo),
+            "* Foo\n    about foo\n^ Bar\n* Boo\n"
+        );
+        assert_eq!(backend.frames()[2][0], "devlog #2 (latest)");
+    }
+
+    #[test]
+    fn test_cancel_rollover() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        run_keys(&config, &repo, &chars("rn"));
+        assert_eq!(repo.latest().unwrap().unwrap().seq_num(), 1);
+    }
+
+    #[test]
+    fn test_rollover_while_locked() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let _lock = RepoLock::acquire(dir.path()).unwrap();
+        let backend = run_keys(&config, &repo, &chars("ry"));
+        assert_eq!(repo.latest().unwrap().unwrap().seq_num(), 1);
+        let footer = backend.frames()[2].last().unwrap().clone();
+        assert_eq!(footer, "Another devlog command is modifying the repository");
+    }
+
+    #[test]
+    fn test_edit_while_locked() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let lock = RepoLock::acquire(dir.path()).unwrap();
+        let backend = run_keys(&config, &repo, &chars(" aFoo\n"));
+        assert_eq!(latest_contents(&repo), ENTRY);
+        let footer = backend.frames()[1].last().unwrap().clone();
+        assert_eq!(footer, "Another devlog command is modifying the repository");
+
+        drop(lock);
+        run_keys(&config, &repo, &chars(" "));
+        assert_ne!(latest_contents(&repo), ENTRY);
+    }
+
+    #[test]
+    fn test_reload_entry_changed_on_disk() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let mut app = App::new(&config, &repo).unwrap();
+        let path = app.logpath().path().to_path_buf();
+        std::fs::write(&path, "* Other\n").unwrap();
+
+        app.handle_key(Key::Char('d')).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "* Other\n");
+        assert_eq!(app.logfile().tasks()[0].content(), "Other");
+        assert_eq!(app.selected(), Some(0));
+
+        app.handle_key(Key::Char('d')).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "+ Other\n");
+    }
+
+    #[test]
+    fn test_browse_entries() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let next = repo.latest().unwrap().unwrap().next().unwrap();
+        std::fs::write(next.path(), "* Second\n").unwrap();
+
+        let backend = run_keys(&config, &repo, &chars("[r]"));
+        let frames = backend.frames();
+        assert_eq!(frames[0][0], "devlog #2 (latest)");
+        assert_eq!(frames[1][0], "devlog #1");
+        assert_eq!(
+            frames[2][19],
+            "Only the most recent devlog can be rolled over"
+        );
+        assert_eq!(frames[3][0], "devlog #2 (latest)");
+    }
+
+    #[test]
+    fn test_quit() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let backend = run_keys(&config, &repo, &chars("qj"));
+        assert_eq!(backend.frames().len(), 2);
+    }
+
+    #[test]
+    fn test_scroll_to_selected() {
+        let dir = tempdir().unwrap();
+        let (config, repo) = init_repo(dir.path());
+        let mut backend = HeadlessBackend::new(10, 6, &chars("jjj"));
+        run(&mut backend, &config, &repo).unwrap();
+        let last = backend.frames().last().unwrap();
+        assert_eq!(last.len(), 6);
+        assert_eq!(last[0], "devlog #1 ");
+        assert_eq!(last[4], "> + Baz");
+    }
+}